@@ -0,0 +1,669 @@
+//! The `#[proton_node]` attribute, which builds a complete NodeDef from Rust code and
+//! implements ProtonNode so that it can be registered with a NodeDefRegistry.
+//!
+//! On a function, each argument becomes an input and the return value becomes the
+//! outputs. The function is left as it is, and a unit struct named after it
+//! (`add_counts` becomes `AddCountsNode`) implements ProtonNode:
+//!
+//! ```ignore
+//! /// Adds two counts together.
+//! #[proton_node(id = "core.count.add", name = "Add Counts", outputs(sum = "Total"))]
+//! fn add_counts(
+//!     #[input(description = "First count")] first: i64,
+//!     #[input(description = "Second count", default = "count:1")] second: i64,
+//! ) -> i64 {
+//!     first + second
+//! }
+//!
+//! AddCountsNode::register(&registry);
+//! ```
+//!
+//! On an executor, the attribute goes on an impl block with a single method, which
+//! takes `&self`, the inputs, and optionally the `&ExecutionContext`. The executor then
+//! implements NodeExecutor by calling that method, and implements ProtonNode itself.
+//! Every Node gets its own executor from `Default::default()`.
+//!
+//! The attribute takes:
+//! - `id`: the id the NodeDef is registered under.
+//! - `name`: the human-readable name of the NodeDef.
+//! - `outputs(name = "description", ...)`: one entry per output. A tuple return type has
+//!   one output per element, `()` has none, and any other type has one.
+//!
+//! The doc comment of the function or impl block becomes the NodeDef's description.
+//! Each input argument has an `#[input(...)]` attribute with a `description`, and
+//! optionally a `name` (otherwise the argument name, with underscores as spaces) and a
+//! `default` in NodeValue literal syntax, such as `default = "mag:0.5"`. Inputs with a
+//! default or of type `Option<T>` are not required. Argument types must implement
+//! FromNodeValue, and output types IntoNodeValue.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use proton_shared::node_value::{NodeValue, NodeValueType};
+use quote::{format_ident, quote, ToTokens};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Attribute, AttributeArgs, FnArg, GenericArgument, Ident, ImplItem, Item,
+    ItemFn, ItemImpl, Lit, LitStr, Meta, NestedMeta, Pat, PatType, PathArguments, ReturnType, Type,
+};
+
+#[proc_macro_attribute]
+pub fn proton_node(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let item = parse_macro_input!(item as Item);
+    let expanded = NodeArgs::parse(args).and_then(|args| match item {
+        Item::Fn(function) => expand_function(args, function),
+        Item::Impl(block) => expand_executor(args, block),
+        item => Err(syn::Error::new_spanned(
+            item,
+            "#[proton_node] goes on a function or on the impl block of an executor",
+        )),
+    });
+    expanded.unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+/// Arguments of the attribute itself.
+struct NodeArgs {
+    id: LitStr,
+    name: LitStr,
+    outputs: Vec<(Ident, LitStr)>,
+
+    /// Where the outputs are listed, for errors about how many there are.
+    outputs_span: Span,
+}
+
+impl NodeArgs {
+    fn parse(args: AttributeArgs) -> syn::Result<NodeArgs> {
+        let mut id = None;
+        let mut name = None;
+        let mut outputs = None;
+        for arg in args {
+            match arg {
+                NestedMeta::Meta(Meta::NameValue(pair)) if pair.path.is_ident("id") => {
+                    id = Some(string_literal(&pair.lit)?);
+                }
+                NestedMeta::Meta(Meta::NameValue(pair)) if pair.path.is_ident("name") => {
+                    name = Some(string_literal(&pair.lit)?);
+                }
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("outputs") => {
+                    let pairs = list
+                        .nested
+                        .iter()
+                        .map(|nested| match nested {
+                            NestedMeta::Meta(Meta::NameValue(pair)) => {
+                                match pair.path.get_ident() {
+                                    Some(ident) => Ok((ident.clone(), string_literal(&pair.lit)?)),
+                                    None => Err(syn::Error::new_spanned(
+                                        &pair.path,
+                                        "expected an output name",
+                                    )),
+                                }
+                            }
+                            nested => Err(syn::Error::new_spanned(
+                                nested,
+                                "expected `name = \"description\"`",
+                            )),
+                        })
+                        .collect::<syn::Result<_>>()?;
+                    outputs = Some((pairs, list.span()));
+                }
+                arg => {
+                    return Err(syn::Error::new_spanned(
+                        arg,
+                        "expected `id`, `name` or `outputs(...)`",
+                    ))
+                }
+            }
+        }
+
+        let missing = |arg: &str| {
+            syn::Error::new(
+                Span::call_site(),
+                format!("#[proton_node] needs a `{}`", arg),
+            )
+        };
+        let (outputs, outputs_span) = outputs.unwrap_or_else(|| (vec![], Span::call_site()));
+        Ok(NodeArgs {
+            id: id.ok_or_else(|| missing("id"))?,
+            name: name.ok_or_else(|| missing("name"))?,
+            outputs,
+            outputs_span,
+        })
+    }
+
+    /// Checks that there is a name and description for each output type.
+    fn check_outputs(&self, output_types: &[Type]) -> syn::Result<()> {
+        if self.outputs.len() == output_types.len() {
+            return Ok(());
+        }
+        Err(syn::Error::new(
+            self.outputs_span,
+            format!(
+                "the node has {} outputs, but `outputs(...)` describes {}",
+                output_types.len(),
+                self.outputs.len()
+            ),
+        ))
+    }
+}
+
+/// An argument that becomes an input of the NodeDef.
+struct InputArg {
+    ident: Ident,
+    name: String,
+    description: String,
+
+    /// Type the input converts to, without the Option around optional inputs.
+    value_type: Type,
+    optional: bool,
+
+    /// Default value in literal syntax, and the type of value it parses to.
+    default: Option<(LitStr, NodeValueType)>,
+}
+
+impl InputArg {
+    /// Reads an argument, removing the attributes that describe it.
+    fn parse(arg: &mut PatType) -> syn::Result<InputArg> {
+        let ident = match &*arg.pat {
+            Pat::Ident(pat) => pat.ident.clone(),
+            pat => {
+                return Err(syn::Error::new_spanned(
+                    pat,
+                    "node inputs must be plain names",
+                ))
+            }
+        };
+        let mut name = ident.to_string().replace('_', " ");
+        let mut description = String::new();
+        let mut default = None;
+        for attr in arg.attrs.iter().filter(|attr| attr.path.is_ident("input")) {
+            let list = match attr.parse_meta()? {
+                Meta::List(list) => list,
+                meta => {
+                    return Err(syn::Error::new_spanned(
+                        meta,
+                        "expected `#[input(description = \"...\")]`",
+                    ))
+                }
+            };
+            for nested in list.nested {
+                let pair = match nested {
+                    NestedMeta::Meta(Meta::NameValue(pair)) => pair,
+                    nested => {
+                        return Err(syn::Error::new_spanned(
+                            nested,
+                            "expected `name`, `description` or `default`",
+                        ))
+                    }
+                };
+                let value = string_literal(&pair.lit)?;
+                if pair.path.is_ident("name") {
+                    name = value.value();
+                } else if pair.path.is_ident("description") {
+                    description = value.value();
+                } else if pair.path.is_ident("default") {
+                    default = Some(value);
+                } else {
+                    return Err(syn::Error::new_spanned(
+                        pair.path,
+                        "expected `name`, `description` or `default`",
+                    ));
+                }
+            }
+        }
+        arg.attrs.retain(|attr| !attr.path.is_ident("input"));
+        if description.is_empty() {
+            return Err(syn::Error::new_spanned(
+                &ident,
+                "describe each input with `#[input(description = \"...\")]`",
+            ));
+        }
+
+        // Defaults are checked here so that typos are caught at compile time.
+        let default = match default {
+            Some(literal) => match literal.value().parse::<NodeValue>() {
+                Ok(value) => Some((literal, NodeValueType::from(&value))),
+                Err(err) => {
+                    return Err(syn::Error::new(
+                        literal.span(),
+                        format!("invalid default: {}", err),
+                    ))
+                }
+            },
+            None => None,
+        };
+        let (value_type, optional) = match option_inner(&arg.ty) {
+            Some(inner) => (inner.clone(), true),
+            None => ((*arg.ty).clone(), false),
+        };
+        Ok(InputArg {
+            ident,
+            name,
+            description,
+            value_type,
+            optional,
+            default,
+        })
+    }
+
+    fn to_def(&self) -> TokenStream2 {
+        let InputArg {
+            name,
+            description,
+            value_type,
+            ..
+        } = self;
+        let required = !self.optional && self.default.is_none();
+        let default = match &self.default {
+            Some((literal, default_type)) => {
+                let variant = Ident::new(&format!("{:?}", default_type), literal.span());
+                let message = format!(
+                    "the default of input `{}` is a {:?}, which the input does not take",
+                    self.ident, default_type
+                );
+                quote! {{
+                    const _: () = assert!(
+                        <#value_type as ::proton_shared::node_value::FromNodeValue>::VALUE_TYPE
+                            as u8
+                            == ::proton_shared::node_value::NodeValueType::#variant as u8,
+                        #message
+                    );
+                    Some(#literal.parse().unwrap())
+                }}
+            }
+            None => quote!(None),
+        };
+        quote! {
+            ::proton_shared::node_def::NodeInputDef {
+                desc: ::proton_shared::node_def::NodeDefBasicDescription {
+                    name: #name.to_string(),
+                    description: #description.to_string(),
+                },
+                allowed_types: vec![
+                    <#value_type as ::proton_shared::node_value::FromNodeValue>::VALUE_TYPE
+                ],
+                required: #required,
+                enum_def: None,
+                composite_type: None,
+                default: #default,
+            }
+        }
+    }
+
+    /// Converts the input from the NodeValue at an index of `inputs`.
+    fn to_conversion(&self, inputs: &Ident, index: usize) -> TokenStream2 {
+        let ident = &self.ident;
+        let value_type = &self.value_type;
+        let (convert, rust_type) = if self.optional {
+            (quote!(optional_node_input), quote!(Option<#value_type>))
+        } else {
+            (quote!(node_input), quote!(#value_type))
+        };
+        quote! {
+            let #ident: #rust_type = ::proton_shared::node_value::#convert(&#inputs, #index)
+                .unwrap_or_else(|err| panic!("Invalid NodeValue input: {}", err));
+        }
+    }
+}
+
+fn expand_function(args: NodeArgs, mut function: ItemFn) -> syn::Result<TokenStream2> {
+    if !function.sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &function.sig.generics,
+            "node functions can't be generic",
+        ));
+    }
+    let description = node_description(&function.attrs, &function.sig.ident)?;
+    let mut inputs = vec![];
+    for arg in function.sig.inputs.iter_mut() {
+        match arg {
+            FnArg::Typed(arg) if is_context(&arg.ty) => {
+                return Err(syn::Error::new_spanned(
+                    arg,
+                    "functions don't get the ExecutionContext, so put #[proton_node] on an \
+                     executor instead",
+                ))
+            }
+            FnArg::Typed(arg) => inputs.push(InputArg::parse(arg)?),
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new_spanned(
+                    receiver,
+                    "methods need #[proton_node] on their impl block instead",
+                ))
+            }
+        }
+    }
+    let output_types = output_types(&function.sig.output);
+    args.check_outputs(&output_types)?;
+
+    let function_name = &function.sig.ident;
+    let inputs_ident = inputs_ident(&inputs);
+    let conversions = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| input.to_conversion(&inputs_ident, index));
+    let arg_idents = inputs.iter().map(|input| &input.ident);
+    // Taken before the inputs, which could have the same name as the function.
+    let function_ident = Ident::new("function", Span::mixed_site());
+    let outputs = wrap_outputs(
+        quote!(#function_ident(#(#arg_idents),*)),
+        output_types.len(),
+    );
+    let runner = quote! {
+        ::proton_shared::node_def::NodeDefRunner::Function(
+            |#inputs_ident: Vec<&::proton_shared::node_value::NodeValue>| {
+                let #function_ident = #function_name;
+                #(#conversions)*
+                #outputs
+            }
+        )
+    };
+
+    let node = format_ident!("{}Node", camel_case(&function_name.to_string()));
+    let doc = format!(
+        "NodeDef of [`{}`], registered as `{}`.",
+        function_name,
+        args.id.value()
+    );
+    let vis = &function.vis;
+    let proton_node = proton_node_impl(
+        &node.to_token_stream(),
+        &args,
+        &description,
+        &inputs,
+        &output_types,
+        runner,
+    );
+    Ok(quote! {
+        #function
+
+        #[doc = #doc]
+        #vis struct #node;
+
+        #proton_node
+    })
+}
+
+fn expand_executor(args: NodeArgs, mut block: ItemImpl) -> syn::Result<TokenStream2> {
+    if let Some((_, path, _)) = &block.trait_ {
+        return Err(syn::Error::new_spanned(
+            path,
+            "#[proton_node] goes on the executor's own impl block, not a trait impl",
+        ));
+    }
+    if !block.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &block.generics,
+            "executors can't be generic",
+        ));
+    }
+    let self_ty = block.self_ty.clone();
+    let description = node_description(&block.attrs, &self_ty)?;
+    let mut methods = block.items.iter_mut().filter_map(|item| match item {
+        ImplItem::Method(method) => Some(method),
+        _ => None,
+    });
+    let method = match (methods.next(), methods.next()) {
+        (Some(method), None) => method,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &self_ty,
+                "the impl block of an executor needs exactly one method, which runs the node",
+            ))
+        }
+    };
+
+    let context_ident = Ident::new("context", Span::mixed_site());
+    let mut inputs = vec![];
+    let mut call_args = vec![];
+    let mut takes_self = false;
+    let mut takes_context = false;
+    for arg in method.sig.inputs.iter_mut() {
+        match arg {
+            FnArg::Receiver(receiver)
+                if receiver.reference.is_some() && receiver.mutability.is_none() =>
+            {
+                takes_self = true;
+            }
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new_spanned(
+                    receiver,
+                    "executors run with `&self`, and can keep state behind a Mutex",
+                ))
+            }
+            FnArg::Typed(arg) if is_context(&arg.ty) => {
+                takes_context = true;
+                call_args.push(context_ident.clone());
+            }
+            FnArg::Typed(arg) => {
+                let input = InputArg::parse(arg)?;
+                call_args.push(input.ident.clone());
+                inputs.push(input);
+            }
+        }
+    }
+    if !takes_self {
+        return Err(syn::Error::new_spanned(
+            &method.sig,
+            "executors run with `&self`",
+        ));
+    }
+    let output_types = output_types(&method.sig.output);
+    args.check_outputs(&output_types)?;
+
+    let method_name = &method.sig.ident;
+    let inputs_ident = inputs_ident(&inputs);
+    let context_ident = if takes_context {
+        context_ident
+    } else {
+        Ident::new("_context", Span::mixed_site())
+    };
+    let conversions = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| input.to_conversion(&inputs_ident, index));
+    let outputs = wrap_outputs(
+        quote!(<#self_ty>::#method_name(self, #(#call_args),*)),
+        output_types.len(),
+    );
+    let runner = quote! {
+        ::proton_shared::node_def::NodeDefRunner::Executor(|| {
+            Box::new(<#self_ty as ::std::default::Default>::default())
+        })
+    };
+    let proton_node = proton_node_impl(
+        &self_ty.to_token_stream(),
+        &args,
+        &description,
+        &inputs,
+        &output_types,
+        runner,
+    );
+    Ok(quote! {
+        #block
+
+        impl ::proton_shared::node_def::NodeExecutor for #self_ty {
+            fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+            fn execute(
+                &self,
+                #inputs_ident: Vec<&::proton_shared::node_value::NodeValue>,
+                #context_ident: &::proton_shared::node_def::ExecutionContext,
+            ) -> Vec<::proton_shared::node_value::NodeValue> {
+                #(#conversions)*
+                #outputs
+            }
+        }
+
+        #proton_node
+    })
+}
+
+fn proton_node_impl(
+    node: &TokenStream2,
+    args: &NodeArgs,
+    description: &str,
+    inputs: &[InputArg],
+    output_types: &[Type],
+    runner: TokenStream2,
+) -> TokenStream2 {
+    let NodeArgs { id, name, .. } = args;
+    let input_defs = inputs.iter().map(InputArg::to_def);
+    let output_defs = args.outputs.iter().zip(output_types).map(
+        |((ident, description), output_type)| {
+            let name = ident.to_string().replace('_', " ");
+            quote! {
+                ::proton_shared::node_def::NodeOutputDef {
+                    desc: ::proton_shared::node_def::NodeDefBasicDescription {
+                        name: #name.to_string(),
+                        description: #description.to_string(),
+                    },
+                    output_type:
+                        <#output_type as ::proton_shared::node_value::IntoNodeValue>::VALUE_TYPE,
+                    enum_def: None,
+                    composite_type: None,
+                }
+            }
+        },
+    );
+    quote! {
+        impl ::proton_shared::node_def::ProtonNode for #node {
+            const ID: &'static str = #id;
+
+            fn node_def() -> ::proton_shared::node_def::NodeDef {
+                ::proton_shared::node_def::NodeDef {
+                    desc: ::proton_shared::node_def::NodeDefBasicDescription {
+                        name: #name.to_string(),
+                        description: #description.to_string(),
+                    },
+                    inputs: vec![#(#input_defs),*],
+                    outputs: vec![#(#output_defs),*],
+                    runner: #runner,
+                }
+            }
+        }
+    }
+}
+
+/// Name of the NodeValues passed to the runner. Kept apart from the names of the
+/// node's own inputs by mixed-site hygiene.
+fn inputs_ident(inputs: &[InputArg]) -> Ident {
+    let name = if inputs.is_empty() {
+        "_inputs"
+    } else {
+        "inputs"
+    };
+    Ident::new(name, Span::mixed_site())
+}
+
+/// Wraps the values returned by a call as a Vec of NodeValues.
+fn wrap_outputs(call: TokenStream2, count: usize) -> TokenStream2 {
+    match count {
+        0 => quote! {
+            #call;
+            vec![]
+        },
+        1 => quote! {
+            vec![::proton_shared::node_value::IntoNodeValue::into_node_value(#call)]
+        },
+        _ => {
+            let parts: Vec<Ident> = (0..count)
+                .map(|index| Ident::new(&format!("output_{}", index), Span::mixed_site()))
+                .collect();
+            quote! {
+                let (#(#parts,)*) = #call;
+                vec![#(::proton_shared::node_value::IntoNodeValue::into_node_value(#parts)),*]
+            }
+        }
+    }
+}
+
+/// Types of each output, given the return type of the function that computes them.
+fn output_types(output: &ReturnType) -> Vec<Type> {
+    match output {
+        ReturnType::Default => vec![],
+        ReturnType::Type(_, output_type) => match &**output_type {
+            Type::Tuple(tuple) => tuple.elems.iter().cloned().collect(),
+            output_type => vec![output_type.clone()],
+        },
+    }
+}
+
+/// Joins doc comment lines into paragraphs, which are separated by blank lines.
+fn node_description(attrs: &[Attribute], item: &impl ToTokens) -> syn::Result<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("doc"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::NameValue(pair)) => match pair.lit {
+                Lit::Str(line) => Some(line.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    let description = lines
+        .split(|line| line.is_empty())
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| paragraph.join(" "))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if description.is_empty() {
+        return Err(syn::Error::new_spanned(
+            item,
+            "document the node with a doc comment, which becomes its description",
+        ));
+    }
+    Ok(description)
+}
+
+fn string_literal(lit: &Lit) -> syn::Result<LitStr> {
+    match lit {
+        Lit::Str(lit) => Ok(lit.clone()),
+        lit => Err(syn::Error::new_spanned(lit, "expected a string")),
+    }
+}
+
+/// True for `&ExecutionContext`, which executors can take to get the context.
+fn is_context(arg_type: &Type) -> bool {
+    match arg_type {
+        Type::Reference(reference) => match &*reference.elem {
+            Type::Path(path) => path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "ExecutionContext"),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The T of an `Option<T>`.
+fn option_inner(arg_type: &Type) -> Option<&Type> {
+    let segment = match arg_type {
+        Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
@@ -1,13 +1,22 @@
-use super::node::{Node, NodeInput, NodeInputDiscriminants, NodeOutputRef};
-use parking_lot::RwLock;
-use proton_shared::node_def::NodeExecutor;
-use proton_shared::node_def_registry::NodeDefRegistry;
+use super::node::{
+    output_device_name, Node, NodeInput, NodeInputDiscriminants, NodeOutputRef, PreparedNode,
+};
+use super::output_device::{OutputDeviceThreads, OutputQueueConfig};
+use parking_lot::{Mutex, RwLock};
+use proton_shared::coercion::{find_coercion, Coercion};
+use proton_shared::node_def::{ExecutionContext, NodeDef};
+use proton_shared::node_def_registry::{NodeDefRegistry, NodeDefRegistryError};
 use proton_shared::node_value::*;
+use proton_shared::pixel_map::PixelMap;
+use proton_shared::shader_registry::ShaderRegistry;
+use proton_shared::tempo::{TempoChange, TempoClock};
 use rayon::prelude::*;
 use rayon::{ThreadPool, ThreadPoolBuilder};
 use std::cmp::min;
 use std::collections::{HashMap, HashSet};
 use std::iter::Iterator;
+use std::sync::Arc;
+use std::time::Instant;
 
 /// Represents the current state of a ComputeGraph, including any errors that may
 /// prevent it from executing.
@@ -19,6 +28,32 @@ pub enum ComputeGraphState {
         from_node: u32,
         to_missing_node: u32,
     },
+    ErrTypeMismatch {
+        from_node: u32,
+        to_node: u32,
+        input_index: usize,
+    },
+    ErrInvalidConst {
+        node: u32,
+        input_index: usize,
+    },
+
+    /// The Node leaves out an input that its NodeDef requires and has no default for.
+    ErrMissingInput {
+        node: u32,
+        input_index: usize,
+    },
+
+    /// The Node has more inputs than its NodeDef.
+    ErrTooManyInputs {
+        node: u32,
+    },
+
+    /// The registry has no NodeDef matching the Node's def_name.
+    ErrUnknownNodeDef {
+        node: u32,
+        error: NodeDefRegistryError,
+    },
     Ready,
 }
 
@@ -35,8 +70,36 @@ pub struct ComputeGraph {
     /// by definition be executed in parallel. Computed lazily.
     waves: Option<Vec<Vec<u32>>>,
 
-    /// Stores optional NodeExecutor instances for each Node.
-    executors: Option<HashMap<u32, Option<Box<dyn NodeExecutor>>>>,
+    /// Stores the prepared state of each Node, including any NodeExecutor instance.
+    prepared_nodes: Option<HashMap<u32, PreparedNode>>,
+
+    /// When the graph was last prepared, which is the zero point for elapsed time.
+    prepared_at: Option<Instant>,
+
+    /// Elapsed time of the most recent execution since the graph was last prepared,
+    /// whether it came from the wall clock or was given to `.execute_at`.
+    last_elapsed: Mutex<NodeDuration>,
+
+    /// Musical clock shared by every Node in the graph. It keeps running when the
+    /// graph is prepared again.
+    tempo: Mutex<TempoClock>,
+
+    /// Programs backing the Shader values produced by the graph's Nodes.
+    shaders: ShaderRegistry,
+
+    /// Where the installation's LEDs are. Replacing it takes effect on the next
+    /// execution without preparing the graph again.
+    pixel_map: RwLock<Arc<PixelMap>>,
+
+    /// Tempo changes requested from outside the graph, applied at the start of the
+    /// next execution.
+    pending_tempo_changes: Mutex<Vec<TempoChange>>,
+
+    /// Dedicated threads that drive output devices, fed by per-device queues.
+    output_devices: Option<OutputDeviceThreads>,
+
+    /// Buffering settings used for output device queues on the next prepare.
+    output_queue_config: OutputQueueConfig,
 
     /// Multithreaded task runner that takes an array of inputs and produces an
     /// array of outputs based on the provided Node evaluator function.
@@ -51,11 +114,19 @@ impl ComputeGraph {
             nodes.insert(node.id, node);
         }
         ComputeGraph {
-            nodes: nodes,
+            nodes,
             registry: node_def_registry,
             state: ComputeGraphState::Unprepared,
             waves: None,
-            executors: None,
+            prepared_nodes: None,
+            prepared_at: None,
+            last_elapsed: Mutex::new(NodeDuration::ZERO),
+            tempo: Mutex::new(TempoClock::default()),
+            shaders: ShaderRegistry::new(),
+            pixel_map: RwLock::new(Arc::new(PixelMap::default())),
+            pending_tempo_changes: Mutex::new(vec![]),
+            output_devices: None,
+            output_queue_config: OutputQueueConfig::default(),
             runner: None,
         }
     }
@@ -65,6 +136,40 @@ impl ComputeGraph {
         self.state.clone()
     }
 
+    /// Sets how output device frames are buffered. Takes effect on the next `.prepare`.
+    pub fn set_output_queue_config(&mut self, config: OutputQueueConfig) {
+        self.output_queue_config = config;
+        self.state = ComputeGraphState::Unprepared;
+        self.waves = None;
+    }
+
+    /// The musical clock as of the most recent execution.
+    pub fn get_tempo(&self) -> TempoClock {
+        self.tempo.lock().clone()
+    }
+
+    /// Changes the musical clock, such as to set the tempo from a user interface.
+    /// The change is applied at the start of the next execution.
+    pub fn request_tempo_change(&self, change: TempoChange) {
+        self.pending_tempo_changes.lock().push(change);
+    }
+
+    /// Programs backing the Shader values produced by the graph, such as for drawing
+    /// them outside of the graph.
+    pub fn get_shader_registry(&self) -> ShaderRegistry {
+        self.shaders.clone()
+    }
+
+    pub fn get_pixel_map(&self) -> Arc<PixelMap> {
+        self.pixel_map.read().clone()
+    }
+
+    /// Replaces the map of LED positions used by Nodes that sample effects per
+    /// fixture, such as after fixtures are moved.
+    pub fn set_pixel_map(&self, pixel_map: PixelMap) {
+        *self.pixel_map.write() = Arc::new(pixel_map);
+    }
+
     /// Adds or updates a Node in the graph
     pub fn set_node(&mut self, node: Node) {
         self.nodes.insert(node.id, node);
@@ -72,6 +177,13 @@ impl ComputeGraph {
         self.waves = None;
     }
 
+    /// A Node in the graph. Once the graph is prepared, the Node's def_name includes
+    /// the version of the NodeDef it was prepared against, so that saving the Node
+    /// keeps it on that version.
+    pub fn get_node(&self, node_id: &u32) -> Option<&Node> {
+        self.nodes.get(node_id)
+    }
+
     /// Removes a Node from the graph
     pub fn remove_node(&mut self, node_id: &u32) {
         self.nodes.remove(node_id);
@@ -83,8 +195,24 @@ impl ComputeGraph {
     /// can be evaluated in parallel. This is based on a fairly simple topological
     /// sorting algorithm. Can be optimized in the future as necessary.
     ///
-    /// Returns false if the input graph is invalid, such as if it contains a cycle.
+    /// Returns false if the input graph is invalid, such as if it contains a cycle
+    /// or a wire between incompatible types.
     pub fn prepare(&mut self, max_threads: u16) -> bool {
+        let mut defs = match self.resolve_node_defs() {
+            Ok(defs) => defs,
+            Err(state) => {
+                self.state = state;
+                return false;
+            }
+        };
+        let mut input_coercions_per_node = match self.compute_input_coercions(&defs) {
+            Ok(input_coercions) => input_coercions,
+            Err(state) => {
+                self.state = state;
+                return false;
+            }
+        };
+
         let maybe_max_parallel = self.prepare_graph_order();
         if maybe_max_parallel.is_none() {
             return false;
@@ -100,33 +228,55 @@ impl ComputeGraph {
                 .unwrap(),
         );
 
+        // Start a thread for each output device. Dropping the previous threads
+        // first makes sure any frames still queued for them get delivered.
+        self.output_devices = None;
+        let device_names = defs.values().filter_map(|def| output_device_name(def));
+        self.output_devices = Some(OutputDeviceThreads::new(
+            device_names.collect::<Vec<String>>(),
+            &self.output_queue_config,
+        ));
+
         // Prepare each node.
-        let active_outputs_per_node = self.compute_active_outputs();
-        let nodes = &self.nodes;
-        self.executors = self.runner.as_ref().unwrap().install(|| {
-            return Some(
-                nodes
-                    .par_iter()
-                    .map(|(id, node)| {
-                        (
-                            *id,
-                            node.with_registry(&self.registry)
-                                .prepare(active_outputs_per_node.get(id).unwrap()),
-                        )
+        let mut active_outputs_per_node = self.compute_active_outputs(&defs);
+        let per_node_state: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|(id, node)| {
+                (
+                    node,
+                    defs.remove(id).unwrap(),
+                    active_outputs_per_node.remove(id).unwrap(),
+                    input_coercions_per_node.remove(id).unwrap(),
+                )
+            })
+            .collect();
+        self.prepared_nodes = self.runner.as_ref().unwrap().install(|| {
+            Some(
+                per_node_state
+                    .into_par_iter()
+                    .map(|(node, def, active_outputs, input_coercions)| {
+                        (node.id, node.prepare(def, active_outputs, input_coercions))
                     })
                     .collect(),
-            );
+            )
         });
 
+        // Elapsed time restarts from zero, so move the clock along with it.
+        if self.prepared_at.is_some() {
+            let last_elapsed = std::mem::replace(self.last_elapsed.get_mut(), NodeDuration::ZERO);
+            self.tempo.lock().rebase(last_elapsed);
+        }
+        self.prepared_at = Some(Instant::now());
         self.state = ComputeGraphState::Ready;
-        return true;
+        true
     }
 
     /// Topologially sorts the graph into a canonical execution order. Returns the
     /// maximum number of operation that can ever execute in parallel, whih puts an
     /// upper bound on the number of threads to use.
     fn prepare_graph_order(&mut self) -> Option<u16> {
-        if self.waves != None {
+        if self.waves.is_some() {
             return None;
         }
 
@@ -148,7 +298,7 @@ impl ComputeGraph {
                 }
 
                 for dep in deps {
-                    if !nodes_in_prev_wave.contains(&dep) {
+                    if !nodes_in_prev_wave.contains(dep) {
                         continue 'outer;
                     }
                 }
@@ -160,7 +310,7 @@ impl ComputeGraph {
             nodes_in_prev_wave.extend(nodes_in_this_wave.iter());
             nodes_in_this_wave.clear();
 
-            if wave.len() == 0 {
+            if wave.is_empty() {
                 // An empty wave means there's a cycle.
                 self.state = ComputeGraphState::ErrFoundCycle;
                 return None;
@@ -172,7 +322,7 @@ impl ComputeGraph {
         }
 
         self.waves = Some(waves);
-        return Some(max_parallel);
+        Some(max_parallel)
     }
 
     /// Build a map of each node and the other nodes it relies on.
@@ -200,8 +350,106 @@ impl ComputeGraph {
             .collect()
     }
 
+    /// Looks up every Node's NodeDef, returning the error state for the first one that
+    /// is not registered. Nodes that ask for the latest version of their NodeDef are
+    /// pinned to the version found, so that registering a newer version that is not
+    /// compatible with it does not change them the next time the graph is prepared.
+    fn resolve_node_defs(&mut self) -> Result<HashMap<u32, Arc<NodeDef>>, ComputeGraphState> {
+        let mut defs = HashMap::with_capacity(self.nodes.len());
+        for node in self.nodes.values_mut() {
+            let (version, def) = self.registry.resolve(&node.def_name).map_err(|error| {
+                ComputeGraphState::ErrUnknownNodeDef {
+                    node: node.id,
+                    error,
+                }
+            })?;
+            if !node.def_name.contains('@') {
+                node.def_name = format!("{}@{}", node.def_name, version);
+            }
+            defs.insert(node.id, def);
+        }
+        Ok(defs)
+    }
+
+    /// Checks that every wire connects to an existing output whose type is accepted by
+    /// the input it drives, either directly or through a Coercion, and that every
+    /// constant input is accepted as-is. Returns the error state for the first invalid
+    /// input found.
+    fn compute_input_coercions(
+        &self,
+        defs: &HashMap<u32, Arc<NodeDef>>,
+    ) -> Result<HashMap<u32, Vec<Option<Coercion>>>, ComputeGraphState> {
+        let mut result = HashMap::with_capacity(self.nodes.len());
+        for node in self.nodes.values() {
+            let input_defs = &defs[&node.id].inputs;
+            if node.inputs.len() > input_defs.len() {
+                return Err(ComputeGraphState::ErrTooManyInputs { node: node.id });
+            }
+            let left_out = input_defs.iter().enumerate().skip(node.inputs.len());
+            for (input_index, input_def) in left_out {
+                if input_def.required && input_def.default.is_none() {
+                    return Err(ComputeGraphState::ErrMissingInput {
+                        node: node.id,
+                        input_index,
+                    });
+                }
+            }
+
+            let mut coercions = Vec::with_capacity(node.inputs.len());
+            for (input_index, input) in node.inputs.iter().enumerate() {
+                let input_def = input_defs.get(input_index);
+                let wire = match input {
+                    NodeInput::Wire(wire) => wire,
+                    NodeInput::Const(value) => {
+                        match input_def {
+                            Some(input_def) if input_def.accepts(value) => coercions.push(None),
+                            _ => {
+                                return Err(ComputeGraphState::ErrInvalidConst {
+                                    node: node.id,
+                                    input_index,
+                                })
+                            }
+                        }
+                        continue;
+                    }
+                };
+
+                let output_def = defs
+                    .get(&wire.from_node_id)
+                    .ok_or(ComputeGraphState::ErrInvalidWire {
+                        from_node: node.id,
+                        to_missing_node: wire.from_node_id,
+                    })?
+                    .outputs
+                    .get(wire.node_output_index as usize);
+                let mismatch = ComputeGraphState::ErrTypeMismatch {
+                    from_node: wire.from_node_id,
+                    to_node: node.id,
+                    input_index,
+                };
+                let (output_def, input_def) = match (output_def, input_def) {
+                    (Some(output_def), Some(input_def)) => (output_def, input_def),
+                    _ => return Err(mismatch),
+                };
+
+                if input_def.accepts_output(output_def) {
+                    coercions.push(None);
+                } else {
+                    let coercion = input_def
+                        .allowed_types
+                        .iter()
+                        .find_map(|to_type| find_coercion(output_def.output_type, *to_type))
+                        .ok_or(mismatch)?;
+                    coercions.push(Some(coercion));
+                }
+            }
+            result.insert(node.id, coercions);
+        }
+        Ok(result)
+    }
+
     /// Determines which outputs of each Node are actively in use.
-    fn compute_active_outputs(&self) -> HashMap<u32, Vec<bool>> {
+    fn compute_active_outputs(&self, defs: &HashMap<u32, Arc<NodeDef>>) -> HashMap<u32, Vec<bool>> {
         let all_wires = self.nodes.values().flat_map(|node| {
             node.inputs
                 .iter()
@@ -220,12 +468,7 @@ impl ComputeGraph {
         let mut result: HashMap<u32, Vec<bool>> = self
             .nodes
             .values()
-            .map(|node| {
-                (
-                    node.id,
-                    vec![false; node.with_registry(&self.registry).get_output_count()],
-                )
-            })
+            .map(|node| (node.id, vec![false; defs[&node.id].outputs.len()]))
             .collect();
 
         for wire in all_wires {
@@ -236,40 +479,80 @@ impl ComputeGraph {
                 .unwrap() = true;
         }
 
-        return result;
+        result
     }
 
-    /// Executes the graph using at most the specified number of threads.
-    /// Returns None if execution could not complete.
+    /// Executes the graph using at most the specified number of threads, with the
+    /// elapsed time measured from when the graph was last prepared.
+    /// Returns None if execution could not complete. Outputs that were skipped
+    /// because nothing is wired to them are left out of the result.
     pub fn execute(&self) -> Result<HashMap<NodeOutputRef, NodeValue>, &str> {
+        let elapsed = match self.prepared_at {
+            Some(prepared_at) => NodeDuration::from(prepared_at.elapsed()),
+            None => NodeDuration::ZERO,
+        };
+        self.execute_at(elapsed)
+    }
+
+    /// Executes the graph as if the given amount of time has passed since it started,
+    /// which makes the results of time-dependent Nodes reproducible.
+    pub fn execute_at(
+        &self,
+        elapsed: NodeDuration,
+    ) -> Result<HashMap<NodeOutputRef, NodeValue>, &str> {
         if self.state != ComputeGraphState::Ready {
             return Err("Must call .prepare() before executing the graph.");
         }
-        let executors = &self.executors.as_ref().unwrap();
+        let prepared_nodes = &self.prepared_nodes.as_ref().unwrap();
+        let output_devices = &self.output_devices.as_ref().unwrap();
+        let tempo = {
+            let mut tempo = self.tempo.lock();
+            for change in self.pending_tempo_changes.lock().drain(..) {
+                tempo.apply(change, elapsed);
+            }
+            tempo.clone()
+        };
+        *self.last_elapsed.lock() = elapsed;
+        let context = ExecutionContext::new(
+            elapsed,
+            tempo,
+            self.shaders.clone(),
+            self.get_pixel_map(),
+        );
 
         let ret = RwLock::new(HashMap::<NodeOutputRef, NodeValue>::new());
         self.runner.as_ref().unwrap().install(|| {
             for wave in self.waves.as_ref().unwrap() {
-                let mut results = Vec::<Vec<NodeValue>>::new();
+                let mut results = Vec::<Vec<Option<NodeValue>>>::new();
                 {
                     let reader = ret.read();
                     wave.par_iter()
                         .map(|node_id: &u32| {
-                            self.nodes
-                                .get(node_id)
-                                .unwrap()
-                                .with_registry(&self.registry)
-                                .evaluate(&reader, executors.get(&node_id).unwrap())
+                            let prepared = prepared_nodes.get(node_id).unwrap();
+                            let device_queue = prepared
+                                .device_name
+                                .as_ref()
+                                .and_then(|name| output_devices.get_queue(name));
+                            self.nodes.get(node_id).unwrap().evaluate(
+                                &reader,
+                                prepared,
+                                device_queue,
+                                &context,
+                            )
                         })
                         .collect_into_vec(&mut results);
                 }
                 let mut writer = ret.write();
                 for (i, result) in results.into_iter().enumerate() {
                     let node_id = wave[i];
-                    for (j, val) in result.into_iter().enumerate() {
+                    for (j, maybe_val) in result.into_iter().enumerate() {
+                        let val = match maybe_val {
+                            Some(val) => val,
+                            None => continue,
+                        };
                         writer.insert(
                             NodeOutputRef {
-                                from_node_id: node_id as u32,
+                                from_node_id: node_id,
                                 node_output_index: j as u8,
                             },
                             val,
@@ -279,7 +562,11 @@ impl ComputeGraph {
             }
         });
 
-        return Ok(ret.into_inner());
+        let mut tempo = self.tempo.lock();
+        for change in context.into_tempo_changes() {
+            tempo.apply(change, elapsed);
+        }
+        Ok(ret.into_inner())
     }
 }
 
@@ -287,31 +574,34 @@ impl ComputeGraph {
 mod tests {
     use super::*;
     use crate::node::*;
+    use crate::output_device::OutputQueueOverflow;
+    use parking_lot::Mutex;
     use proton_shared::node_def::*;
-    use proton_shared::node_def_registry::NodeDefRegistry;
+    use proton_shared::node_def_registry::{NodeDefRegistry, NodeDefVersion};
+    use std::sync::Arc;
 
     #[test]
     fn executes_simple_graphs() {
         let registry = NodeDefRegistry::new();
 
         registry.register(
-            "output_1".to_owned(),
+            "test.output_1".to_owned(),
             node_def_from_fn!(|| -> (i64) {
                 return vec![NodeValue::Count(1)];
             }),
-        );
+        ).unwrap();
         registry.register(
-            "add".to_owned(),
+            "test.add".to_owned(),
             node_def_from_fn!(|count_1: i64, count_2: i64| -> (i64) {
                 return vec![NodeValue::Count(count_1 + count_2)];
             }),
-        );
+        ).unwrap();
 
         let nodes = make_nodes! {
-            1: output_1[],
-            2: add[Wire{1, 0}, i64{3}],
-            3: add[Wire{1, 0}, i64{5}],
-            4: add[Wire{2, 0}, Wire{3, 0}]
+            1: test.output_1[],
+            2: test.add[Wire{1, 0}, i64{3}],
+            3: test.add[Wire{1, 0}, i64{5}],
+            4: test.add[Wire{2, 0}, Wire{3, 0}]
         };
         let mut graph = ComputeGraph::new(registry, nodes);
 
@@ -327,4 +617,363 @@ mod tests {
             &NodeValue::Count(10)
         );
     }
+
+    #[test]
+    fn coerces_mismatched_wires() {
+        let registry = NodeDefRegistry::new();
+
+        registry.register(
+            "test.output_1".to_owned(),
+            node_def_from_fn!(|| -> (i64) {
+                return vec![NodeValue::Count(1)];
+            }),
+        ).unwrap();
+        registry.register(
+            "test.scale".to_owned(),
+            node_def_from_fn!(|magnitude: f64| -> (f64) {
+                return vec![NodeValue::UnconstrainedMagnitude(magnitude * 2.5)];
+            }),
+        ).unwrap();
+
+        let nodes = make_nodes! {
+            1: test.output_1[],
+            2: test.scale[Wire{1, 0}]
+        };
+        let mut graph = ComputeGraph::new(registry, nodes);
+
+        assert!(graph.prepare(2));
+        let result = graph.execute().unwrap();
+        assert_eq!(
+            result
+                .get(&NodeOutputRef {
+                    from_node_id: 2,
+                    node_output_index: 0
+                })
+                .unwrap(),
+            &NodeValue::UnconstrainedMagnitude(2.5)
+        );
+    }
+
+    #[test]
+    fn skips_inactive_outputs() {
+        let registry = NodeDefRegistry::new();
+
+        registry.register(
+            "test.split".to_owned(),
+            NodeDef {
+                desc: NodeDefBasicDescription {
+                    name: "Split".to_string(),
+                    description: "Splits a count in two".to_string(),
+                },
+                inputs: node_input_def_from_args!(count: i64),
+                outputs: node_output_def_from_tuple!(i64, i64),
+                runner: NodeDefRunner::PerOutputFunction(vec![
+                    |inputs: &[&NodeValue]| match inputs[0] {
+                        NodeValue::Count(count) => NodeValue::Count(count / 2),
+                        _ => panic!("Invalid type for NodeValue input count"),
+                    },
+                    |_: &[&NodeValue]| panic!("Inactive output should not be computed"),
+                ]),
+            },
+        ).unwrap();
+        registry.register(
+            "test.add".to_owned(),
+            node_def_from_fn!(|count_1: i64, count_2: i64| -> (i64) {
+                return vec![NodeValue::Count(count_1 + count_2)];
+            }),
+        ).unwrap();
+
+        let nodes = make_nodes! {
+            1: test.split[i64{6}],
+            2: test.add[Wire{1, 0}, i64{1}]
+        };
+        let mut graph = ComputeGraph::new(registry, nodes);
+
+        assert!(graph.prepare(2));
+        let result = graph.execute().unwrap();
+        assert_eq!(
+            result.get(&NodeOutputRef {
+                from_node_id: 2,
+                node_output_index: 0
+            }),
+            Some(&NodeValue::Count(4))
+        );
+        assert!(!result.contains_key(&NodeOutputRef {
+            from_node_id: 1,
+            node_output_index: 1
+        }));
+    }
+
+    #[test]
+    fn rejects_incompatible_wires() {
+        let registry = NodeDefRegistry::new();
+
+        registry.register(
+            "test.output_1".to_owned(),
+            node_def_from_fn!(|| -> (NodeColor) {
+                return vec![NodeValue::Color(NodeColor::opaque(1, 1, 1))];
+            }),
+        ).unwrap();
+        registry.register(
+            "test.add".to_owned(),
+            node_def_from_fn!(|count_1: i64, count_2: i64| -> (i64) {
+                return vec![NodeValue::Count(count_1 + count_2)];
+            }),
+        ).unwrap();
+
+        let nodes = make_nodes! {
+            1: test.output_1[],
+            2: test.add[i64{3}, Wire{1, 0}]
+        };
+        let mut graph = ComputeGraph::new(registry, nodes);
+
+        assert!(!graph.prepare(2));
+        assert_eq!(
+            graph.get_state(),
+            ComputeGraphState::ErrTypeMismatch {
+                from_node: 1,
+                to_node: 2,
+                input_index: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_enum_consts_of_other_enums() {
+        let registry = NodeDefRegistry::new();
+        let blend_mode = Arc::new(NodeEnumDef::new("blend mode", &["normal", "add"]));
+        let direction = Arc::new(NodeEnumDef::new("direction", &["up", "down"]));
+
+        let mut def = node_def_from_fn!(|mode: NodeEnumValue| -> (i64) {
+            return vec![NodeValue::Count(mode.index() as i64)];
+        });
+        def.inputs[0].enum_def = Some(blend_mode.clone());
+        registry.register("test.mode_index".to_owned(), def).unwrap();
+
+        let add = NodeEnumValue::from_option(blend_mode, "add").unwrap();
+        let mut graph = ComputeGraph::new(
+            registry.clone(),
+            vec![Node {
+                id: 1,
+                def_name: "test.mode_index".to_string(),
+                inputs: vec![NodeInput::Const(NodeValue::Enumeration(add))],
+            }],
+        );
+        assert!(graph.prepare(1));
+        assert_eq!(
+            graph
+                .execute()
+                .unwrap()
+                .get(&NodeOutputRef {
+                    from_node_id: 1,
+                    node_output_index: 0
+                })
+                .unwrap(),
+            &NodeValue::Count(1)
+        );
+
+        let down = NodeEnumValue::from_option(direction, "down").unwrap();
+        let mut graph = ComputeGraph::new(
+            registry,
+            vec![Node {
+                id: 1,
+                def_name: "test.mode_index".to_string(),
+                inputs: vec![NodeInput::Const(NodeValue::Enumeration(down))],
+            }],
+        );
+        assert!(!graph.prepare(1));
+        assert_eq!(
+            graph.get_state(),
+            ComputeGraphState::ErrInvalidConst {
+                node: 1,
+                input_index: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_node_defs() {
+        let registry = NodeDefRegistry::new();
+        registry
+            .register(
+                "test.output_1".to_owned(),
+                node_def_from_fn!(|| -> (i64) {
+                    return vec![NodeValue::Count(1)];
+                }),
+            )
+            .unwrap();
+
+        let mut graph = ComputeGraph::new(registry, make_nodes! { 1: test.ouptut_1[] });
+        assert!(!graph.prepare(1));
+        assert_eq!(
+            graph.get_state(),
+            ComputeGraphState::ErrUnknownNodeDef {
+                node: 1,
+                error: NodeDefRegistryError::NotFound("test.ouptut_1".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_missing_inputs() {
+        let registry = NodeDefRegistry::new();
+        let mut def = node_def_from_fn!(|count_1: i64, count_2: i64, count_3: i64| -> (i64) {
+            return vec![NodeValue::Count(count_1 + count_2 + count_3)];
+        });
+        def.inputs[2].required = false;
+        def.inputs[2].default = Some(NodeValue::Count(10));
+        registry.register("test.sum".to_owned(), def).unwrap();
+
+        // The defaulted input can be left out, but the required one before it can't.
+        let mut graph = ComputeGraph::new(registry.clone(), make_nodes! {
+            1: test.sum[i64{1}, i64{2}]
+        });
+        assert!(graph.prepare(1));
+        let result = graph.execute().unwrap();
+        assert_eq!(
+            result.get(&NodeOutputRef { from_node_id: 1, node_output_index: 0 }),
+            Some(&NodeValue::Count(13))
+        );
+
+        let mut graph = ComputeGraph::new(registry, make_nodes! { 1: test.sum[i64{1}] });
+        assert!(!graph.prepare(1));
+        assert_eq!(
+            graph.get_state(),
+            ComputeGraphState::ErrMissingInput {
+                node: 1,
+                input_index: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_too_many_inputs() {
+        let registry = NodeDefRegistry::new();
+        registry
+            .register(
+                "test.double".to_owned(),
+                node_def_from_fn!(|count: i64| -> (i64) {
+                    return vec![NodeValue::Count(count * 2)];
+                }),
+            )
+            .unwrap();
+
+        let mut graph = ComputeGraph::new(registry, make_nodes! {
+            1: test.double[i64{1}, i64{2}]
+        });
+        assert!(!graph.prepare(1));
+        assert_eq!(
+            graph.get_state(),
+            ComputeGraphState::ErrTooManyInputs { node: 1 }
+        );
+    }
+
+    #[test]
+    fn keeps_node_def_versions_it_was_prepared_with() {
+        let registry = NodeDefRegistry::new();
+        registry
+            .register(
+                "test.count".to_owned(),
+                node_def_from_fn!(|| -> (i64) {
+                    return vec![NodeValue::Count(1)];
+                }),
+            )
+            .unwrap();
+
+        let mut graph = ComputeGraph::new(registry.clone(), make_nodes! { 1: test.count[] });
+        assert!(graph.prepare(1));
+        registry
+            .register_version(
+                "test.count".to_owned(),
+                NodeDefVersion::new(2, 0, 0),
+                node_def_from_fn!(|| -> (i64) {
+                    return vec![NodeValue::Count(2)];
+                }),
+            )
+            .unwrap();
+        let output = NodeOutputRef {
+            from_node_id: 1,
+            node_output_index: 0,
+        };
+        assert_eq!(graph.execute().unwrap()[&output], NodeValue::Count(1));
+
+        let saved = graph.get_node(&1).unwrap().clone();
+        assert_eq!(saved.def_name, "test.count@1.0.0");
+        let mut reloaded = ComputeGraph::new(registry.clone(), vec![saved]);
+        assert!(reloaded.prepare(1));
+        assert_eq!(reloaded.execute().unwrap()[&output], NodeValue::Count(1));
+
+        registry.unregister("test.count", NodeDefVersion::INITIAL);
+        assert_eq!(graph.execute().unwrap()[&output], NodeValue::Count(1));
+    }
+
+    #[test]
+    fn sends_output_device_frames_to_device_thread() {
+        static RECEIVED: Mutex<Vec<i64>> = Mutex::new(Vec::new());
+        let registry = NodeDefRegistry::new();
+
+        registry.register(
+            "test.add".to_owned(),
+            node_def_from_fn!(|count_1: i64, count_2: i64| -> (i64) {
+                return vec![NodeValue::Count(count_1 + count_2)];
+            }),
+        ).unwrap();
+        registry.register(
+            "test.record".to_owned(),
+            NodeDef {
+                desc: NodeDefBasicDescription {
+                    name: "Record".to_string(),
+                    description: "Records counts".to_string(),
+                },
+                inputs: node_input_def_from_args!(count: i64),
+                outputs: vec![],
+                runner: NodeDefRunner::OutputDevice(NodeDefOutputRunner {
+                    run: Arc::new(|inputs: Vec<&NodeValue>| {
+                        if let NodeValue::Count(count) = inputs[0] {
+                            RECEIVED.lock().push(*count);
+                        }
+                    }),
+                    device: OutputDevice {
+                        name: "recorder".to_string(),
+                    },
+                }),
+            },
+        ).unwrap();
+
+        let nodes = make_nodes! {
+            1: test.add[i64{1}, i64{2}],
+            2: test.record[Wire{1, 0}]
+        };
+        let mut graph = ComputeGraph::new(registry, nodes);
+        graph.prepare(2);
+        graph.execute().unwrap();
+        graph.execute().unwrap();
+
+        // Dropping the graph waits for the device thread to drain its queue.
+        drop(graph);
+        assert_eq!(*RECEIVED.lock(), vec![3, 3]);
+    }
+
+    #[test]
+    fn prepares_again_after_queue_config_changes() {
+        let registry = NodeDefRegistry::new();
+
+        registry.register(
+            "test.output_1".to_owned(),
+            node_def_from_fn!(|| -> (i64) {
+                return vec![NodeValue::Count(1)];
+            }),
+        ).unwrap();
+
+        let mut graph = ComputeGraph::new(registry, make_nodes! { 1: test.output_1[] });
+        assert!(graph.prepare(1));
+        graph.set_output_queue_config(OutputQueueConfig {
+            capacity: 0,
+            overflow: OutputQueueOverflow::DropFrame,
+        });
+        assert_eq!(graph.get_state(), ComputeGraphState::Unprepared);
+        assert!(graph.prepare(1));
+        assert_eq!(graph.get_state(), ComputeGraphState::Ready);
+        assert!(graph.execute().is_ok());
+    }
 }
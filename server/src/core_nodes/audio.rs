@@ -0,0 +1,489 @@
+use super::*;
+use hound::{SampleFormat, WavReader};
+use parking_lot::{Condvar, Mutex, MutexGuard};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufReader;
+use std::thread;
+
+/// Longest block of audio the WAV file node outputs in a single execution. If more
+/// time than this has passed, the audio in between is skipped.
+const MAX_BLOCK_SECONDS: u32 = 1;
+
+/// How much audio is decoded ahead of the current position.
+const BUFFER_SECONDS: u32 = 2;
+
+/// Number of pieces the buffer is filled in, so that the graph can pick up some of
+/// the audio before all of it has been decoded.
+const DECODE_CHUNKS: usize = 20;
+
+pub fn register(registry: &NodeDefRegistry) -> Result<(), NodeDefRegistryError> {
+    registry.register(
+        "core.audio.wav_file".to_owned(),
+        NodeDef {
+            desc: describe(
+                "WAV File",
+                "Plays a WAV file in step with the graph's elapsed time. Each execution \
+                 outputs the audio between the previous execution and the current one. \
+                 The file is opened and decoded in the background, and outputs silence \
+                 until then.",
+            ),
+            inputs: vec![input(
+                "path",
+                "Path of the WAV file to play",
+                vec![NodeValueType::Text],
+            )],
+            outputs: vec![
+                output(
+                    "audio",
+                    "Audio since the previous execution",
+                    NodeValueType::Audio,
+                ),
+                output(
+                    "finished",
+                    "Whether the end of the file has been reached, or it could not be opened",
+                    NodeValueType::Toggle,
+                ),
+                output(
+                    "loaded",
+                    "Whether the file has been opened",
+                    NodeValueType::Toggle,
+                ),
+            ],
+            runner: NodeDefRunner::Executor(|| Box::new(WavFileExecutor::new())),
+        },
+    )?;
+    Ok(())
+}
+
+type Reader = WavReader<BufReader<File>>;
+
+/// Plays the file through a worker thread that opens it and decodes ahead, so that
+/// neither slow storage nor decoding hold up the graph.
+struct WavFileExecutor {
+    stream: Arc<WavStream>,
+}
+
+/// State shared between the executor and its worker thread.
+struct WavStream {
+    state: Mutex<WavStreamState>,
+
+    /// Signalled to wake the worker when there is something for it to do.
+    changed: Condvar,
+}
+
+struct WavStreamState {
+    /// Path of the file that the Node was last given.
+    path: Option<String>,
+
+    /// Bumped whenever the path changes, so the worker knows to open the new file.
+    opens: u64,
+
+    /// Bumped whenever the buffered samples are thrown away, so the worker knows to
+    /// discard anything it was decoding at the time.
+    reads: u64,
+
+    file: WavFileState,
+
+    /// Index of the next frame to be read, which is the first one in the buffer.
+    position: u32,
+
+    /// Interleaved samples decoded ahead of the position.
+    buffer: VecDeque<f32>,
+
+    /// Whether the worker must seek to the position before decoding any more.
+    seek: bool,
+
+    /// Whether the worker has decoded everything after the position.
+    end_of_file: bool,
+
+    /// Set when the executor goes away, to stop the worker.
+    closed: bool,
+}
+
+enum WavFileState {
+    /// Being opened and buffered by the worker.
+    Opening,
+    Open(OpenWavFile),
+
+    /// Could not be opened. Not tried again until the path changes.
+    Failed,
+}
+
+#[derive(Clone, Copy)]
+struct OpenWavFile {
+    sample_rate: u32,
+    channels: u16,
+
+    /// Length of the file in frames.
+    duration: u32,
+}
+
+impl WavFileExecutor {
+    fn new() -> WavFileExecutor {
+        let stream = Arc::new(WavStream {
+            state: Mutex::new(WavStreamState {
+                path: None,
+                opens: 0,
+                reads: 0,
+                file: WavFileState::Opening,
+                position: 0,
+                buffer: VecDeque::new(),
+                seek: false,
+                end_of_file: false,
+                closed: false,
+            }),
+            changed: Condvar::new(),
+        });
+        let worker_stream = stream.clone();
+        thread::spawn(move || decode_ahead(&worker_stream));
+        WavFileExecutor { stream }
+    }
+}
+
+impl Drop for WavFileExecutor {
+    fn drop(&mut self) {
+        self.stream.state.lock().closed = true;
+        self.stream.changed.notify_one();
+    }
+}
+
+impl NodeExecutor for WavFileExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, context: &ExecutionContext) -> Vec<NodeValue> {
+        let path = match inputs[0] {
+            NodeValue::Text(path) => path,
+            _ => invalid_input("path"),
+        };
+
+        let mut state = self.stream.state.lock();
+        if state.path.as_ref() != Some(path) {
+            state.open(path.to_string());
+            self.stream.changed.notify_one();
+        }
+
+        let silence = || NodeValue::default_for(NodeValueType::Audio).unwrap();
+        match state.file {
+            WavFileState::Open(open) => {
+                let block = state.read_until(open, context.elapsed);
+                self.stream.changed.notify_one();
+                vec![
+                    NodeValue::Audio(Arc::new(block)),
+                    NodeValue::Toggle(state.position >= open.duration),
+                    NodeValue::Toggle(true),
+                ]
+            }
+            WavFileState::Opening => vec![
+                silence(),
+                NodeValue::Toggle(false),
+                NodeValue::Toggle(false),
+            ],
+            WavFileState::Failed => {
+                vec![silence(), NodeValue::Toggle(true), NodeValue::Toggle(false)]
+            }
+        }
+    }
+}
+
+impl WavStreamState {
+    /// Asks the worker to open a different file.
+    fn open(&mut self, path: String) {
+        self.path = Some(path);
+        self.opens += 1;
+        self.file = WavFileState::Opening;
+        self.seek_to(0);
+        self.seek = false;
+    }
+
+    /// Throws away the buffer and asks the worker to continue from the given frame.
+    fn seek_to(&mut self, position: u32) {
+        self.position = position;
+        self.buffer.clear();
+        self.seek = true;
+        self.end_of_file = false;
+        self.reads += 1;
+    }
+
+    /// Takes every buffered frame from the current position up to the given time.
+    /// Seeks instead if the time has gone backwards or jumped too far ahead. If the
+    /// worker has fallen behind, the rest is picked up by later executions.
+    fn read_until(&mut self, open: OpenWavFile, elapsed: NodeDuration) -> AudioBlock {
+        let elapsed_micros = elapsed.as_micros().max(0) as u128;
+        let target = (elapsed_micros * open.sample_rate as u128 / 1_000_000)
+            .min(open.duration as u128) as u32;
+        let max_frames = open.sample_rate.saturating_mul(MAX_BLOCK_SECONDS);
+        if target < self.position {
+            self.seek_to(target);
+        } else if target - self.position > max_frames {
+            self.seek_to(target - max_frames);
+        }
+
+        let channels = open.channels as usize;
+        let buffered = (self.buffer.len() / channels) as u32;
+        let frames = (target - self.position).min(buffered);
+        let samples: Vec<f32> = self.buffer.drain(..frames as usize * channels).collect();
+        self.position = if self.end_of_file && frames == buffered {
+            // A truncated file has nothing more to give.
+            target
+        } else {
+            self.position + frames
+        };
+        AudioBlock::new(open.sample_rate, open.channels, samples).unwrap()
+    }
+}
+
+/// Runs on the worker thread until the executor goes away, opening files and keeping
+/// the buffer topped up.
+fn decode_ahead(stream: &WavStream) {
+    let mut reader: Option<Reader> = None;
+    let mut opens = 0;
+    let mut state = stream.state.lock();
+    while !state.closed {
+        if state.opens != opens {
+            opens = state.opens;
+            let path = state.path.clone().unwrap();
+            reader = MutexGuard::unlocked(&mut state, || WavReader::open(path).ok());
+            if state.opens == opens && reader.is_none() {
+                state.file = WavFileState::Failed;
+            }
+            continue;
+        }
+
+        let reader = match reader.as_mut() {
+            Some(reader) => reader,
+            None => {
+                stream.changed.wait(&mut state);
+                continue;
+            }
+        };
+        let spec = reader.spec();
+        let capacity = (spec.sample_rate * BUFFER_SECONDS) as usize * spec.channels as usize;
+        if state.end_of_file || state.buffer.len() >= capacity {
+            if let WavFileState::Opening = state.file {
+                state.file = WavFileState::Open(OpenWavFile {
+                    sample_rate: spec.sample_rate,
+                    channels: spec.channels,
+                    duration: reader.duration(),
+                });
+            }
+            stream.changed.wait(&mut state);
+            continue;
+        }
+
+        let reads = state.reads;
+        let seek_to = if state.seek {
+            state.seek = false;
+            Some(state.position)
+        } else {
+            None
+        };
+        let chunk_size = capacity / DECODE_CHUNKS;
+        let chunk = MutexGuard::unlocked(&mut state, || {
+            if let Some(position) = seek_to {
+                reader.seek(position).ok()?;
+            }
+            Some(decode(reader, chunk_size.max(1)))
+        });
+        if state.reads != reads {
+            continue;
+        }
+        match chunk {
+            Some(mut samples) => {
+                if samples.len() < chunk_size.max(1) {
+                    // A truncated file can end partway through a frame.
+                    samples.truncate(samples.len() - samples.len() % spec.channels as usize);
+                    state.end_of_file = true;
+                }
+                state.buffer.extend(samples);
+            }
+            None => state.end_of_file = true,
+        }
+    }
+}
+
+/// Reads up to the given number of samples, as floats from -1 to 1.
+fn decode(reader: &mut Reader, sample_count: usize) -> Vec<f32> {
+    let spec = reader.spec();
+    match spec.sample_format {
+        SampleFormat::Float => reader
+            .samples::<f32>()
+            .take(sample_count)
+            .map(|sample| sample.unwrap_or(0.0))
+            .collect(),
+        SampleFormat::Int => {
+            let scale = 1.0 / (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .take(sample_count)
+                .map(|sample| sample.unwrap_or(0) as f32 * scale)
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_graph::ComputeGraph;
+    use crate::node::*;
+    use hound::{WavSpec, WavWriter};
+
+    #[test]
+    fn streams_wav_file_with_elapsed_time() {
+        let path = std::env::temp_dir().join(format!("proton_wav_{}.wav", std::process::id()));
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for i in 0..200 {
+            writer.write_sample((i * 100) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let registry = NodeDefRegistry::new();
+        register(&registry).unwrap();
+        let path_value = NodeValue::Text(Arc::new(path.to_str().unwrap().to_string()));
+        let mut graph = ComputeGraph::new(
+            registry,
+            vec![Node {
+                id: 1,
+                def_name: "core.audio.wav_file".to_string(),
+                inputs: vec![NodeInput::Const(path_value)],
+            }],
+        );
+        assert!(graph.prepare(1));
+        fixtures::wait_until_loaded(&graph, 1);
+
+        let run = |millis: i64| {
+            let result = graph.execute_at(NodeDuration::from_millis(millis)).unwrap();
+            let output = |node_output_index| {
+                result
+                    .get(&NodeOutputRef {
+                        from_node_id: 1,
+                        node_output_index,
+                    })
+                    .unwrap()
+                    .clone()
+            };
+            match (output(0), output(1)) {
+                (NodeValue::Audio(block), NodeValue::Toggle(finished)) => (block, finished),
+                _ => panic!("Unexpected output types"),
+            }
+        };
+
+        let (block, finished) = run(100);
+        assert_eq!(block.frame_count(), 100);
+        assert_eq!(block.samples()[1], 100.0 / 32768.0);
+        assert!(!finished);
+
+        let (block, _) = run(150);
+        assert_eq!(block.frame_count(), 50);
+        assert_eq!(block.samples()[0], 10000.0 / 32768.0);
+
+        let (block, finished) = run(500);
+        assert_eq!(block.frame_count(), 50);
+        assert!(finished);
+
+        // Going back in time restarts playback from that point, once the worker has
+        // caught up with it.
+        let (block, _) = run(20);
+        assert_eq!(block.frame_count(), 0);
+        let mut block = run(30).0;
+        for _ in 0..5000 {
+            if block.frame_count() > 0 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            block = run(30).0;
+        }
+        assert_eq!(block.samples()[0], 2000.0 / 32768.0);
+
+        // Times far beyond the end don't overflow working out the position.
+        let (_, finished) = run(i64::MAX / 1000);
+        assert!(finished);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reports_files_that_cannot_be_opened() {
+        let executor = WavFileExecutor::new();
+        let path = NodeValue::Text(Arc::new("/nonexistent/proton.wav".to_string()));
+        let context = ExecutionContext::default();
+        let mut outputs = executor.execute(vec![&path], &context);
+        for _ in 0..5000 {
+            if outputs[1] == NodeValue::Toggle(true) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            outputs = executor.execute(vec![&path], &context);
+        }
+        assert_eq!(
+            outputs[1..],
+            [NodeValue::Toggle(true), NodeValue::Toggle(false)]
+        );
+        assert!(matches!(
+            executor.stream.state.lock().file,
+            WavFileState::Failed
+        ));
+    }
+}
+
+/// Helpers for building WAV files to test audio nodes against.
+#[cfg(test)]
+pub mod fixtures {
+    use crate::compute_graph::ComputeGraph;
+    use crate::node::NodeOutputRef;
+    use hound::{SampleFormat, WavSpec, WavWriter};
+    use proton_shared::node_value::{NodeDuration, NodeValue};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    /// Writes mono 32-bit float samples to a uniquely named file in the temp dir.
+    pub fn write_wav(name: &str, sample_rate: u32, samples: &[f32]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("proton_{}_{}.wav", name, std::process::id()));
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for sample in samples {
+            writer.write_sample(*sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    /// Executes the graph at its start until the WAV file node with the given id has
+    /// opened its file.
+    pub fn wait_until_loaded(graph: &ComputeGraph, node_id: u32) {
+        let loaded = NodeOutputRef {
+            from_node_id: node_id,
+            node_output_index: 2,
+        };
+        for _ in 0..5000 {
+            let result = graph.execute_at(NodeDuration::ZERO).unwrap();
+            if result.get(&loaded) == Some(&NodeValue::Toggle(true)) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        panic!("WAV file was not loaded");
+    }
+
+    /// A sine wave at the given frequency and amplitude.
+    pub fn sine(sample_rate: u32, frequency: f32, amplitude: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                amplitude * (t * frequency * std::f32::consts::PI * 2.0).sin()
+            })
+            .collect()
+    }
+}
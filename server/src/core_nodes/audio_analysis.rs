@@ -0,0 +1,567 @@
+use super::*;
+use parking_lot::Mutex;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::collections::VecDeque;
+
+/// Number of samples in each FFT window.
+const FFT_SIZE: usize = 1024;
+
+/// Number of new samples between each spectral flux measurement in onset detection.
+const ONSET_HOP_SIZE: usize = 512;
+
+/// Number of past flux measurements averaged to decide whether a new one is an onset.
+const ONSET_HISTORY: usize = 43;
+
+/// Flux that must be exceeded for an onset regardless of history, so that tiny
+/// changes in near-silence are not detected as onsets.
+const ONSET_MIN_FLUX: f32 = 0.5;
+
+/// Lowest frequency included in the spectrum bands, in Hz.
+const SPECTRUM_MIN_FREQUENCY: f32 = 20.0;
+
+/// Quietest level shown in the spectrum, in decibels relative to full scale.
+const SPECTRUM_FLOOR_DB: f32 = -60.0;
+
+const MAX_BANDS: i64 = 256;
+
+pub fn register(registry: &NodeDefRegistry) -> Result<(), NodeDefRegistryError> {
+    let audio_input = || input("audio", "Audio to analyze", vec![NodeValueType::Audio]);
+
+    let mut bands_output = output(
+        "bands",
+        "Level of each band, from lowest to highest frequency",
+        NodeValueType::List,
+    );
+    bands_output.composite_type = Some(NodeCompositeType::list_of(
+        NodeValueType::ConstrainedMagnitude.into(),
+    ));
+    registry.register(
+        "core.audio.spectrum".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Spectrum",
+                "Splits the most recent audio into logarithmically spaced frequency bands \
+                 and measures the level of each one.",
+            ),
+            inputs: vec![
+                audio_input(),
+                input(
+                    "band count",
+                    "Number of frequency bands",
+                    vec![NodeValueType::Count],
+                ),
+            ],
+            outputs: vec![
+                bands_output,
+                output(
+                    "spectrum",
+                    "Level of each band as a grayscale pixel",
+                    NodeValueType::Bitmap1D,
+                ),
+            ],
+            runner: NodeDefRunner::Executor(|| Box::new(SpectrumExecutor::default())),
+        },
+    )?;
+
+    registry.register(
+        "core.audio.level".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Level",
+                "Follows the loudness of audio, rising at the attack speed and falling at \
+                 the release speed.",
+            ),
+            inputs: vec![
+                audio_input(),
+                input(
+                    "attack",
+                    "Time taken to rise most of the way to a louder level",
+                    vec![NodeValueType::Duration],
+                ),
+                input(
+                    "release",
+                    "Time taken to fall most of the way to a quieter level",
+                    vec![NodeValueType::Duration],
+                ),
+            ],
+            outputs: vec![
+                output(
+                    "rms",
+                    "Root mean square level",
+                    NodeValueType::ConstrainedMagnitude,
+                ),
+                output(
+                    "peak",
+                    "Peak sample level",
+                    NodeValueType::ConstrainedMagnitude,
+                ),
+            ],
+            runner: NodeDefRunner::Executor(|| Box::new(LevelExecutor::default())),
+        },
+    )?;
+
+    registry.register(
+        "core.audio.onset".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Onset",
+                "Detects the start of notes and beats by looking for sudden increases in \
+                 the spectrum of the audio.",
+            ),
+            inputs: vec![
+                audio_input(),
+                input(
+                    "sensitivity",
+                    "How far above the recent average a change must be to count as an \
+                     onset, as a multiple. Lower values detect more onsets.",
+                    vec![NodeValueType::UnconstrainedMagnitude],
+                ),
+                input(
+                    "minimum interval",
+                    "Shortest time allowed between two onsets",
+                    vec![NodeValueType::Duration],
+                ),
+            ],
+            outputs: vec![
+                output(
+                    "onset",
+                    "Fires when an onset is detected",
+                    NodeValueType::Trigger,
+                ),
+                output(
+                    "flux",
+                    "Most recent measurement of spectral change",
+                    NodeValueType::UnconstrainedMagnitude,
+                ),
+            ],
+            runner: NodeDefRunner::Executor(|| Box::new(OnsetExecutor::default())),
+        },
+    )?;
+    Ok(())
+}
+
+fn audio_arg<'a>(value: &'a NodeValue, name: &str) -> &'a AudioBlock {
+    match value {
+        NodeValue::Audio(block) => block,
+        _ => invalid_input(name),
+    }
+}
+
+fn duration_arg(value: &NodeValue, name: &str) -> NodeDuration {
+    match value {
+        NodeValue::Duration(duration) => *duration,
+        _ => invalid_input(name),
+    }
+}
+
+/// Measures the magnitude of each frequency bin in a window of samples.
+struct SpectrumAnalyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+}
+
+impl Default for SpectrumAnalyzer {
+    fn default() -> SpectrumAnalyzer {
+        // Hann window, to reduce leakage between bins.
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                let phase = i as f32 / FFT_SIZE as f32 * std::f32::consts::PI * 2.0;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect();
+        SpectrumAnalyzer {
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+        }
+    }
+}
+
+impl SpectrumAnalyzer {
+    /// Returns the magnitude of the lower half of the bins, scaled so that a full
+    /// scale sine wave has a magnitude of about 1. Missing samples are treated as
+    /// silence.
+    fn magnitudes(&self, samples: &VecDeque<f32>) -> Vec<f32> {
+        let padding = FFT_SIZE.saturating_sub(samples.len());
+        let mut buffer: Vec<Complex<f32>> = std::iter::repeat_n(0.0, padding)
+            .chain(samples.iter().copied())
+            .zip(&self.window)
+            .map(|(sample, weight)| Complex::new(sample * weight, 0.0))
+            .collect();
+        self.fft.process(&mut buffer);
+
+        let scale = 4.0 / FFT_SIZE as f32;
+        buffer[..FFT_SIZE / 2]
+            .iter()
+            .map(|bin| bin.norm() * scale)
+            .collect()
+    }
+}
+
+/// Keeps the most recent FFT window's worth of mono samples.
+fn push_recent(recent: &mut VecDeque<f32>, samples: impl Iterator<Item = f32>) {
+    recent.extend(samples);
+    while recent.len() > FFT_SIZE {
+        recent.pop_front();
+    }
+}
+
+#[derive(Default)]
+struct SpectrumExecutor {
+    analyzer: SpectrumAnalyzer,
+    recent: Mutex<VecDeque<f32>>,
+}
+
+impl SpectrumExecutor {
+    /// Groups bins into bands spaced evenly on a logarithmic scale, taking the
+    /// loudest bin in each band and converting it to a 0 to 1 level in decibels.
+    fn band_levels(magnitudes: &[f32], sample_rate: u32, band_count: usize) -> Vec<f32> {
+        let nyquist = sample_rate as f32 / 2.0;
+        let bin_width = nyquist / magnitudes.len() as f32;
+        let ratio = nyquist / SPECTRUM_MIN_FREQUENCY;
+        let edge_bin = |band: usize| {
+            let frequency = SPECTRUM_MIN_FREQUENCY * ratio.powf(band as f32 / band_count as f32);
+            ((frequency / bin_width).round() as usize).min(magnitudes.len())
+        };
+
+        (0..band_count)
+            .map(|band| {
+                let start = edge_bin(band);
+                let end = edge_bin(band + 1).max(start + 1).min(magnitudes.len());
+                let magnitude = magnitudes[start.min(end)..end]
+                    .iter()
+                    .fold(0.0f32, |max, magnitude| max.max(*magnitude));
+                let db = 20.0 * magnitude.max(1e-9).log10();
+                (1.0 - db / SPECTRUM_FLOOR_DB).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+impl NodeExecutor for SpectrumExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, _context: &ExecutionContext) -> Vec<NodeValue> {
+        let audio = audio_arg(inputs[0], "audio");
+        let band_count = match inputs[1] {
+            NodeValue::Count(count) => (*count).clamp(1, MAX_BANDS) as usize,
+            _ => invalid_input("band count"),
+        };
+
+        let mut recent = self.recent.lock();
+        push_recent(&mut recent, audio.to_mono().into_iter());
+        let magnitudes = self.analyzer.magnitudes(&recent);
+        let levels = SpectrumExecutor::band_levels(&magnitudes, audio.sample_rate(), band_count);
+
+        let bands = levels
+            .iter()
+            .map(|level| {
+                NodeValue::ConstrainedMagnitude(NodeMagnitude::from_f64(*level as f64).to_bits())
+            })
+            .collect();
+        let pixels = levels
+            .iter()
+            .map(|level| {
+                let value = (level * u16::MAX as f32) as u16;
+                NodeColor::opaque(value, value, value)
+            })
+            .collect();
+        vec![
+            NodeValue::List(Arc::new(
+                NodeList::new(NodeValueType::ConstrainedMagnitude.into(), bands).unwrap(),
+            )),
+            NodeValue::Bitmap1D(Arc::new(Bitmap1D::from_pixels(pixels))),
+        ]
+    }
+}
+
+#[derive(Default)]
+struct LevelExecutor {
+    /// Smoothed square of the samples, and smoothed absolute value of the samples.
+    envelopes: Mutex<(f32, f32)>,
+}
+
+/// Multiplier applied each sample to the distance between an envelope and its
+/// target, so that it covers 1 - 1/e of the distance in the given time.
+fn envelope_coefficient(time: NodeDuration, sample_rate: u32) -> f32 {
+    let samples = time.as_secs_f64() * sample_rate as f64;
+    if samples <= 0.0 {
+        return 0.0;
+    }
+    (-1.0 / samples).exp() as f32
+}
+
+fn follow(envelope: &mut f32, target: f32, attack: f32, release: f32) {
+    let coefficient = if target > *envelope { attack } else { release };
+    *envelope = target + coefficient * (*envelope - target);
+}
+
+impl NodeExecutor for LevelExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, _context: &ExecutionContext) -> Vec<NodeValue> {
+        let audio = audio_arg(inputs[0], "audio");
+        let attack = envelope_coefficient(duration_arg(inputs[1], "attack"), audio.sample_rate());
+        let release = envelope_coefficient(duration_arg(inputs[2], "release"), audio.sample_rate());
+
+        let mut envelopes = self.envelopes.lock();
+        let (mean_square, peak) = &mut *envelopes;
+        for sample in audio.to_mono() {
+            follow(mean_square, sample * sample, attack, release);
+            follow(peak, sample.abs(), attack, release);
+        }
+        vec![
+            NodeValue::ConstrainedMagnitude(
+                NodeMagnitude::from_f64(mean_square.sqrt() as f64).to_bits(),
+            ),
+            NodeValue::ConstrainedMagnitude(NodeMagnitude::from_f64(*peak as f64).to_bits()),
+        ]
+    }
+}
+
+#[derive(Default)]
+struct OnsetExecutor {
+    analyzer: SpectrumAnalyzer,
+    state: Mutex<OnsetState>,
+}
+
+#[derive(Default)]
+struct OnsetState {
+    recent: VecDeque<f32>,
+
+    /// Samples received since the last flux measurement.
+    since_hop: usize,
+    previous_magnitudes: Option<Vec<f32>>,
+    flux_history: VecDeque<f32>,
+    flux: f32,
+
+    /// Samples received since the last onset, or None if there has not been one.
+    since_onset: Option<u64>,
+}
+
+impl NodeExecutor for OnsetExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, _context: &ExecutionContext) -> Vec<NodeValue> {
+        let audio = audio_arg(inputs[0], "audio");
+        let sensitivity = match inputs[1] {
+            NodeValue::UnconstrainedMagnitude(sensitivity) => *sensitivity as f32,
+            _ => invalid_input("sensitivity"),
+        };
+        let minimum_interval = (duration_arg(inputs[2], "minimum interval").as_secs_f64()
+            * audio.sample_rate() as f64)
+            .max(0.0) as u64;
+
+        let mut state = self.state.lock();
+        let mut onset = false;
+        let mono = audio.to_mono();
+        for chunk in mono.chunks(ONSET_HOP_SIZE) {
+            // Only measure at hop boundaries, so results do not depend on block size.
+            let until_hop = ONSET_HOP_SIZE - state.since_hop;
+            let (before, after) = chunk.split_at(until_hop.min(chunk.len()));
+            push_recent(&mut state.recent, before.iter().copied());
+            state.since_hop += before.len();
+            state.since_onset = state.since_onset.map(|since| since + before.len() as u64);
+            if state.since_hop == ONSET_HOP_SIZE {
+                state.since_hop = 0;
+                onset |= self.measure(&mut state, sensitivity, minimum_interval);
+            }
+            push_recent(&mut state.recent, after.iter().copied());
+            state.since_hop += after.len();
+            state.since_onset = state.since_onset.map(|since| since + after.len() as u64);
+        }
+
+        vec![
+            NodeValue::Trigger(onset),
+            NodeValue::UnconstrainedMagnitude(state.flux as f64),
+        ]
+    }
+}
+
+impl OnsetExecutor {
+    /// Measures spectral flux, the total increase in magnitude across all bins since
+    /// the previous measurement, and returns whether it is an onset.
+    fn measure(&self, state: &mut OnsetState, sensitivity: f32, minimum_interval: u64) -> bool {
+        let magnitudes = self.analyzer.magnitudes(&state.recent);
+        let flux = match &state.previous_magnitudes {
+            Some(previous) => magnitudes
+                .iter()
+                .zip(previous)
+                .map(|(magnitude, previous)| (magnitude - previous).max(0.0))
+                .sum(),
+            None => 0.0,
+        };
+        state.previous_magnitudes = Some(magnitudes);
+
+        let average = if state.flux_history.is_empty() {
+            0.0
+        } else {
+            state.flux_history.iter().sum::<f32>() / state.flux_history.len() as f32
+        };
+        let threshold = (average * sensitivity).max(ONSET_MIN_FLUX);
+        let interval_passed = state
+            .since_onset
+            .is_none_or(|since| since >= minimum_interval);
+        let onset = flux > threshold && interval_passed;
+        if onset {
+            state.since_onset = Some(0);
+        }
+
+        state.flux = flux;
+        state.flux_history.push_back(flux);
+        if state.flux_history.len() > ONSET_HISTORY {
+            state.flux_history.pop_front();
+        }
+        onset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::audio::{self, fixtures};
+    use super::*;
+    use crate::compute_graph::ComputeGraph;
+    use crate::node::*;
+    use std::path::Path;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    /// Builds a graph that plays a WAV file into a single analysis node with id 2.
+    fn analysis_graph(path: &Path, def_name: &str, mut inputs: Vec<NodeInput>) -> ComputeGraph {
+        let registry = NodeDefRegistry::new();
+        audio::register(&registry).unwrap();
+        register(&registry).unwrap();
+        inputs.insert(
+            0,
+            NodeInput::Wire(NodeOutputRef {
+                from_node_id: 1,
+                node_output_index: 0,
+            }),
+        );
+        let path = NodeValue::Text(Arc::new(path.to_str().unwrap().to_string()));
+        let mut graph = ComputeGraph::new(
+            registry,
+            vec![
+                Node {
+                    id: 1,
+                    def_name: "core.audio.wav_file".to_string(),
+                    inputs: vec![NodeInput::Const(path)],
+                },
+                Node {
+                    id: 2,
+                    def_name: def_name.to_string(),
+                    inputs,
+                },
+            ],
+        );
+        assert!(graph.prepare(1));
+        fixtures::wait_until_loaded(&graph, 1);
+        graph
+    }
+
+    fn output_at(graph: &ComputeGraph, millis: i64, node_output_index: u8) -> NodeValue {
+        graph
+            .execute_at(NodeDuration::from_millis(millis))
+            .unwrap()
+            .remove(&NodeOutputRef {
+                from_node_id: 2,
+                node_output_index,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn finds_sine_wave_in_spectrum() {
+        let samples = fixtures::sine(SAMPLE_RATE, 1000.0, 0.5, SAMPLE_RATE as usize);
+        let path = fixtures::write_wav("spectrum", SAMPLE_RATE, &samples);
+        let graph = analysis_graph(
+            &path,
+            "core.audio.spectrum",
+            vec![NodeInput::Const(NodeValue::Count(8))],
+        );
+
+        let levels: Vec<u32> = match output_at(&graph, 500, 0) {
+            NodeValue::List(bands) => bands
+                .items()
+                .iter()
+                .map(|band| match band {
+                    NodeValue::ConstrainedMagnitude(level) => *level,
+                    _ => panic!("Bands should be magnitudes"),
+                })
+                .collect(),
+            _ => panic!("Expected a list of bands"),
+        };
+        std::fs::remove_file(path).unwrap();
+
+        // With bands from 20Hz to 4kHz, 1kHz falls in the sixth band.
+        assert_eq!(levels.len(), 8);
+        assert!(levels[5] > NodeMagnitude::from_f64(0.8).to_bits());
+        for (band, level) in levels.iter().enumerate() {
+            if band != 5 {
+                assert!(
+                    *level < NodeMagnitude::from_f64(0.5).to_bits(),
+                    "Band {} is too loud",
+                    band
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn follows_level_with_attack_and_release() {
+        let half = SAMPLE_RATE as usize / 2;
+        let mut samples = vec![0.0; half];
+        samples.extend((0..half).map(|i| if i % 2 == 0 { 0.5 } else { -0.5 }));
+        samples.extend(vec![0.0; half]);
+        let path = fixtures::write_wav("level", SAMPLE_RATE, &samples);
+        let graph = analysis_graph(
+            &path,
+            "core.audio.level",
+            vec![
+                NodeInput::Const(NodeValue::Duration(NodeDuration::from_millis(10))),
+                NodeInput::Const(NodeValue::Duration(NodeDuration::from_millis(100))),
+            ],
+        );
+
+        let level_at =
+            |millis, node_output_index| match output_at(&graph, millis, node_output_index) {
+                NodeValue::ConstrainedMagnitude(level) => NodeMagnitude::from_bits(level).to_f64(),
+                _ => panic!("Expected a magnitude"),
+            };
+        assert_eq!(level_at(400, 0), 0.0);
+        let rms = level_at(900, 0);
+        assert!((rms - 0.5).abs() < 0.01, "RMS was {}", rms);
+        let peak = level_at(950, 1);
+        assert!((peak - 0.5).abs() < 0.01, "Peak was {}", peak);
+
+        // 100ms into the silence, the release has covered most of the distance to 0.
+        let peak = level_at(1100, 1);
+        std::fs::remove_file(path).unwrap();
+        assert!(peak > 0.1 && peak < 0.25, "Peak was {}", peak);
+    }
+
+    #[test]
+    fn triggers_on_onsets() {
+        let mut samples = vec![0.0; SAMPLE_RATE as usize * 2];
+        for start in &[SAMPLE_RATE as usize / 2, SAMPLE_RATE as usize * 3 / 2] {
+            let burst = fixtures::sine(SAMPLE_RATE, 1000.0, 0.5, SAMPLE_RATE as usize / 20);
+            samples[*start..*start + burst.len()].copy_from_slice(&burst);
+        }
+        let path = fixtures::write_wav("onset", SAMPLE_RATE, &samples);
+        let graph = analysis_graph(
+            &path,
+            "core.audio.onset",
+            vec![
+                NodeInput::Const(NodeValue::UnconstrainedMagnitude(1.5)),
+                NodeInput::Const(NodeValue::Duration(NodeDuration::from_millis(100))),
+            ],
+        );
+
+        let onsets: Vec<i64> = (1..=20)
+            .map(|step| step * 100)
+            .filter(|millis| output_at(&graph, *millis, 0) == NodeValue::Trigger(true))
+            .collect();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(onsets, vec![600, 1600]);
+    }
+}
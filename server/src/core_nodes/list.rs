@@ -0,0 +1,553 @@
+use super::*;
+
+/// Longest list that the repeat nodes build, in items.
+const MAX_REPEAT_COUNT: i64 = 65536;
+
+pub fn register(registry: &NodeDefRegistry) -> Result<(), NodeDefRegistryError> {
+    for element_type in types_with_defaults().filter(|t| *t != NodeValueType::Record) {
+        register_typed(registry, element_type)?;
+    }
+
+    registry.register(
+        "core.list.length".to_owned(),
+        NodeDef {
+            desc: describe("List Length", "Counts the items in a list."),
+            inputs: vec![input("list", "Any list", vec![NodeValueType::List])],
+            outputs: vec![output(
+                "length",
+                "Number of items in the list",
+                NodeValueType::Count,
+            )],
+            runner: NodeDefRunner::Function(length),
+        },
+    )?;
+
+    registry.register(
+        "core.list.zip".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Zip Lists",
+                "Pairs up the items of two lists into a list of records with 'first' and \
+                 'second' fields. Stops at the end of the shorter list.",
+            ),
+            inputs: vec![
+                input("first", "Any list", vec![NodeValueType::List]),
+                input("second", "Any list", vec![NodeValueType::List]),
+            ],
+            outputs: vec![output(
+                "pairs",
+                "List of records pairing up the items",
+                NodeValueType::List,
+            )],
+            runner: NodeDefRunner::Function(zip),
+        },
+    )?;
+
+    let magnitudes = NodeCompositeType::list_of(NodeValueType::UnconstrainedMagnitude.into());
+    let mut operation = input(
+        "operation",
+        "Math to apply",
+        vec![NodeValueType::Enumeration],
+    );
+    operation.enum_def = Some(math_operation_enum());
+    registry.register(
+        "core.list.map.magnitude".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Map Magnitudes",
+                "Applies the same math operation to every magnitude in a list.",
+            ),
+            inputs: vec![
+                list_input(&magnitudes),
+                operation,
+                input(
+                    "operand",
+                    "Value to combine with each item",
+                    vec![NodeValueType::UnconstrainedMagnitude],
+                ),
+            ],
+            outputs: vec![list_output(&magnitudes)],
+            runner: NodeDefRunner::Function(map_magnitude),
+        },
+    )?;
+
+    let colors = NodeCompositeType::list_of(NodeValueType::Color.into());
+    let mut mode = input(
+        "mode",
+        "How to blend the color",
+        vec![NodeValueType::Enumeration],
+    );
+    mode.enum_def = Some(blend_mode_enum());
+    registry.register(
+        "core.list.map.color".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Map Colors",
+                "Blends the same color over every color in a list.",
+            ),
+            inputs: vec![
+                list_input(&colors),
+                mode,
+                input(
+                    "color",
+                    "Color to blend over each item",
+                    vec![NodeValueType::Color],
+                ),
+            ],
+            outputs: vec![list_output(&colors)],
+            runner: NodeDefRunner::Function(map_color),
+        },
+    )?;
+    Ok(())
+}
+
+/// Registers the nodes that need to know the type of the list items, one set per type.
+fn register_typed(
+    registry: &NodeDefRegistry,
+    element_type: NodeValueType,
+) -> Result<(), NodeDefRegistryError> {
+    let list_type = NodeCompositeType::list_of(element_type.into());
+    let mut fallback = input(
+        "fallback",
+        "Item to output when the list is empty",
+        vec![element_type],
+    );
+    fallback.required = false;
+    fallback.default = NodeValue::default_for(element_type);
+
+    registry.register(
+        format!("core.list.index.{:?}", element_type),
+        NodeDef {
+            desc: describe(
+                "Index List",
+                "Gets a single item from a list. Indices wrap around, so -1 is the last \
+                 item. An empty list produces the fallback item.",
+            ),
+            inputs: vec![
+                list_input(&list_type),
+                input("index", "Position of the item", vec![NodeValueType::Count]),
+                fallback,
+            ],
+            outputs: vec![output("item", "Item at the index", element_type)],
+            runner: NodeDefRunner::Function(index),
+        },
+    )?;
+
+    registry.register(
+        format!("core.list.repeat.{:?}", element_type),
+        NodeDef {
+            desc: describe(
+                "Repeat",
+                "Builds a list containing the same item many times.",
+            ),
+            inputs: vec![
+                input("item", "Item to repeat", vec![element_type]),
+                input(
+                    "count",
+                    "Length of the list, up to 65536",
+                    vec![NodeValueType::Count],
+                ),
+            ],
+            outputs: vec![list_output(&list_type)],
+            runner: NodeDefRunner::Function(repeat),
+        },
+    )?;
+
+    registry.register(
+        format!("core.list.push.{:?}", element_type),
+        NodeDef {
+            desc: describe(
+                "Push",
+                "Builds a list by adding an item to the end of another.",
+            ),
+            inputs: vec![
+                list_input(&list_type),
+                input("item", "Item to add", vec![element_type]),
+            ],
+            outputs: vec![list_output(&list_type)],
+            runner: NodeDefRunner::Function(push),
+        },
+    )?;
+    Ok(())
+}
+
+fn list_input(list_type: &NodeCompositeType) -> NodeInputDef {
+    let mut list = input("list", "Input list", vec![NodeValueType::List]);
+    list.composite_type = Some(list_type.clone());
+    list
+}
+
+fn list_output(list_type: &NodeCompositeType) -> NodeOutputDef {
+    let mut list = output("list", "Output list", NodeValueType::List);
+    list.composite_type = Some(list_type.clone());
+    list
+}
+
+fn math_operation_enum() -> Arc<NodeEnumDef> {
+    Arc::new(NodeEnumDef::new(
+        "math operation",
+        &["add", "multiply", "min", "max"],
+    ))
+}
+
+fn list_arg<'a>(value: &'a NodeValue, name: &str) -> &'a Arc<NodeList> {
+    match value {
+        NodeValue::List(list) => list,
+        _ => invalid_input(name),
+    }
+}
+
+fn count_arg(value: &NodeValue, name: &str) -> i64 {
+    match value {
+        NodeValue::Count(count) => *count,
+        _ => invalid_input(name),
+    }
+}
+
+fn length(inputs: Vec<&NodeValue>) -> Vec<NodeValue> {
+    let list = list_arg(inputs[0], "list");
+    vec![NodeValue::Count(list.len() as i64)]
+}
+
+fn index(inputs: Vec<&NodeValue>) -> Vec<NodeValue> {
+    let list = list_arg(inputs[0], "list");
+    let index = count_arg(inputs[1], "index");
+    if list.is_empty() {
+        return vec![inputs[2].clone()];
+    }
+    let wrapped = index.rem_euclid(list.len() as i64) as usize;
+    vec![list.get(wrapped).unwrap().clone()]
+}
+
+fn repeat(inputs: Vec<&NodeValue>) -> Vec<NodeValue> {
+    let count = count_arg(inputs[1], "count").clamp(0, MAX_REPEAT_COUNT) as usize;
+    let list = NodeList::new(
+        NodeCompositeType::of(inputs[0]),
+        vec![inputs[0].clone(); count],
+    );
+    vec![NodeValue::List(Arc::new(list.unwrap()))]
+}
+
+fn push(inputs: Vec<&NodeValue>) -> Vec<NodeValue> {
+    let mut list = list_arg(inputs[0], "list").clone();
+    if Arc::make_mut(&mut list).push(inputs[1].clone()).is_err() {
+        invalid_input("item");
+    }
+    vec![NodeValue::List(list)]
+}
+
+fn zip(inputs: Vec<&NodeValue>) -> Vec<NodeValue> {
+    let first = list_arg(inputs[0], "first");
+    let second = list_arg(inputs[1], "second");
+    let pair_type = NodeCompositeType::Record(vec![
+        ("first".to_string(), first.element_type().clone()),
+        ("second".to_string(), second.element_type().clone()),
+    ]);
+    let pairs = first
+        .items()
+        .iter()
+        .zip(second.items())
+        .map(|(a, b)| {
+            NodeValue::Record(Arc::new(NodeRecord::from_fields(vec![
+                ("first".to_string(), a.clone()),
+                ("second".to_string(), b.clone()),
+            ])))
+        })
+        .collect();
+    vec![NodeValue::List(Arc::new(
+        NodeList::new(pair_type, pairs).unwrap(),
+    ))]
+}
+
+fn map_magnitude(inputs: Vec<&NodeValue>) -> Vec<NodeValue> {
+    let list = list_arg(inputs[0], "list");
+    let operation: fn(f64, f64) -> f64 = match inputs[1] {
+        NodeValue::Enumeration(operation) => match operation.option() {
+            "multiply" => |a, b| a * b,
+            "min" => f64::min,
+            "max" => f64::max,
+            _ => |a, b| a + b,
+        },
+        _ => invalid_input("operation"),
+    };
+    let operand = match inputs[2] {
+        NodeValue::UnconstrainedMagnitude(operand) => *operand,
+        _ => invalid_input("operand"),
+    };
+    let items = list
+        .items()
+        .iter()
+        .map(|item| match item {
+            NodeValue::UnconstrainedMagnitude(value) => {
+                NodeValue::UnconstrainedMagnitude(operation(*value, operand))
+            }
+            _ => invalid_input("list"),
+        })
+        .collect();
+    vec![NodeValue::List(Arc::new(
+        NodeList::new(list.element_type().clone(), items).unwrap(),
+    ))]
+}
+
+fn map_color(inputs: Vec<&NodeValue>) -> Vec<NodeValue> {
+    let list = list_arg(inputs[0], "list");
+    let mode = match inputs[1] {
+        NodeValue::Enumeration(mode) => blend_mode_of(mode),
+        _ => invalid_input("mode"),
+    };
+    let color = match inputs[2] {
+        NodeValue::Color(color) => *color,
+        _ => invalid_input("color"),
+    };
+    let items = list
+        .items()
+        .iter()
+        .map(|item| match item {
+            NodeValue::Color(backdrop) => NodeValue::Color(color.blend_over(backdrop, mode)),
+            _ => invalid_input("list"),
+        })
+        .collect();
+    vec![NodeValue::List(Arc::new(
+        NodeList::new(list.element_type().clone(), items).unwrap(),
+    ))]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_graph::{ComputeGraph, ComputeGraphState};
+    use crate::node::*;
+
+    fn output_of(
+        result: &std::collections::HashMap<NodeOutputRef, NodeValue>,
+        id: u32,
+    ) -> NodeValue {
+        result
+            .get(&NodeOutputRef {
+                from_node_id: id,
+                node_output_index: 0,
+            })
+            .unwrap()
+            .clone()
+    }
+
+    fn node(id: u32, def_name: &str, inputs: Vec<NodeInput>) -> Node {
+        Node {
+            id,
+            def_name: def_name.to_string(),
+            inputs,
+        }
+    }
+
+    fn wire(from_node_id: u32) -> NodeInput {
+        NodeInput::Wire(NodeOutputRef {
+            from_node_id,
+            node_output_index: 0,
+        })
+    }
+
+    #[test]
+    fn builds_and_indexes_lists() {
+        let registry = NodeDefRegistry::new();
+        register(&registry).unwrap();
+
+        let red = NodeColor::opaque(u16::MAX, 0, 0);
+        let nodes = vec![
+            node(
+                1,
+                "core.list.repeat.Color",
+                vec![
+                    NodeInput::Const(NodeValue::Color(NodeColor::WHITE)),
+                    NodeInput::Const(NodeValue::Count(47)),
+                ],
+            ),
+            node(
+                2,
+                "core.list.push.Color",
+                vec![wire(1), NodeInput::Const(NodeValue::Color(red))],
+            ),
+            node(3, "core.list.length", vec![wire(2)]),
+            node(
+                4,
+                "core.list.index.Color",
+                vec![wire(2), NodeInput::Const(NodeValue::Count(-1))],
+            ),
+            node(5, "core.list.zip", vec![wire(1), wire(2)]),
+            node(6, "core.list.length", vec![wire(5)]),
+        ];
+        let mut graph = ComputeGraph::new(registry, nodes);
+        assert!(graph.prepare(4));
+        let result = graph.execute().unwrap();
+
+        assert_eq!(output_of(&result, 3), NodeValue::Count(48));
+        assert_eq!(output_of(&result, 4), NodeValue::Color(red));
+        assert_eq!(output_of(&result, 6), NodeValue::Count(47));
+    }
+
+    #[test]
+    fn clamps_repeat_counts() {
+        let registry = NodeDefRegistry::new();
+        register(&registry).unwrap();
+
+        let nodes = vec![
+            node(
+                1,
+                "core.list.repeat.Count",
+                vec![
+                    NodeInput::Const(NodeValue::Count(1)),
+                    NodeInput::Const(NodeValue::Count(i64::MAX)),
+                ],
+            ),
+            node(2, "core.list.length", vec![wire(1)]),
+        ];
+        let mut graph = ComputeGraph::new(registry, nodes);
+        assert!(graph.prepare(1));
+        let result = graph.execute().unwrap();
+        assert_eq!(output_of(&result, 2), NodeValue::Count(MAX_REPEAT_COUNT));
+    }
+
+    #[test]
+    fn maps_lists() {
+        let registry = NodeDefRegistry::new();
+        register(&registry).unwrap();
+
+        let multiply = NodeEnumValue::from_option(math_operation_enum(), "multiply").unwrap();
+        let nodes = vec![
+            node(
+                1,
+                "core.list.repeat.UnconstrainedMagnitude",
+                vec![
+                    NodeInput::Const(NodeValue::UnconstrainedMagnitude(0.5)),
+                    NodeInput::Const(NodeValue::Count(3)),
+                ],
+            ),
+            node(
+                2,
+                "core.list.map.magnitude",
+                vec![
+                    wire(1),
+                    NodeInput::Const(NodeValue::Enumeration(multiply)),
+                    NodeInput::Const(NodeValue::UnconstrainedMagnitude(3.0)),
+                ],
+            ),
+        ];
+        let mut graph = ComputeGraph::new(registry, nodes);
+        assert!(graph.prepare(2));
+        let result = graph.execute().unwrap();
+
+        let expected = NodeList::new(
+            NodeValueType::UnconstrainedMagnitude.into(),
+            vec![NodeValue::UnconstrainedMagnitude(1.5); 3],
+        );
+        assert_eq!(
+            output_of(&result, 2),
+            NodeValue::List(Arc::new(expected.unwrap()))
+        );
+    }
+
+    #[test]
+    fn rejects_lists_of_the_wrong_type() {
+        let registry = NodeDefRegistry::new();
+        register(&registry).unwrap();
+
+        let nodes = vec![
+            node(
+                1,
+                "core.list.repeat.Count",
+                vec![
+                    NodeInput::Const(NodeValue::Count(1)),
+                    NodeInput::Const(NodeValue::Count(3)),
+                ],
+            ),
+            node(
+                2,
+                "core.list.index.Color",
+                vec![wire(1), NodeInput::Const(NodeValue::Count(0))],
+            ),
+        ];
+        let mut graph = ComputeGraph::new(registry, nodes);
+        assert!(!graph.prepare(2));
+        assert_eq!(
+            graph.get_state(),
+            ComputeGraphState::ErrTypeMismatch {
+                from_node: 1,
+                to_node: 2,
+                input_index: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn indexes_empty_lists_with_the_fallback() {
+        let registry = NodeDefRegistry::new();
+        register(&registry).unwrap();
+
+        let red = NodeColor::opaque(u16::MAX, 0, 0);
+        let nodes = vec![
+            node(
+                1,
+                "core.list.repeat.Color",
+                vec![
+                    NodeInput::Const(NodeValue::Color(red)),
+                    NodeInput::Const(NodeValue::Count(0)),
+                ],
+            ),
+            node(
+                2,
+                "core.list.index.Color",
+                vec![
+                    wire(1),
+                    NodeInput::Const(NodeValue::Count(3)),
+                    NodeInput::Const(NodeValue::Color(red)),
+                ],
+            ),
+            node(
+                3,
+                "core.list.index.Color",
+                vec![wire(1), NodeInput::Const(NodeValue::Count(3))],
+            ),
+        ];
+        let mut graph = ComputeGraph::new(registry, nodes);
+        assert!(graph.prepare(2));
+        let result = graph.execute().unwrap();
+
+        assert_eq!(output_of(&result, 2), NodeValue::Color(red));
+        assert_eq!(
+            output_of(&result, 3),
+            NodeValue::default_for(NodeValueType::Color).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_untyped_lists_on_typed_inputs() {
+        let registry = NodeDefRegistry::new();
+        register(&registry).unwrap();
+
+        let nodes = vec![
+            node(
+                1,
+                "core.list.repeat.Count",
+                vec![
+                    NodeInput::Const(NodeValue::Count(1)),
+                    NodeInput::Const(NodeValue::Count(3)),
+                ],
+            ),
+            node(2, "core.list.zip", vec![wire(1), wire(1)]),
+            node(
+                3,
+                "core.list.index.Count",
+                vec![wire(2), NodeInput::Const(NodeValue::Count(0))],
+            ),
+        ];
+        let mut graph = ComputeGraph::new(registry, nodes);
+        assert!(!graph.prepare(3));
+        assert_eq!(
+            graph.get_state(),
+            ComputeGraphState::ErrTypeMismatch {
+                from_node: 2,
+                to_node: 3,
+                input_index: 0,
+            }
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use super::*;
+use proton_node_macros::proton_node;
+
+pub fn register(registry: &NodeDefRegistry) -> Result<(), NodeDefRegistryError> {
+    MultiplyNode::register(registry)?;
+    AddNode::register(registry)?;
+    SubtractNode::register(registry)?;
+    LerpNode::register(registry)?;
+    InvertNode::register(registry)?;
+    SmoothstepNode::register(registry)?;
+    GammaNode::register(registry)?;
+    Ok(())
+}
+
+/// Multiplies two magnitudes, such as to scale a brightness by a master level.
+#[proton_node(
+    id = "core.magnitude.multiply",
+    name = "Multiply Magnitudes",
+    outputs(result = "Resulting magnitude")
+)]
+fn multiply(
+    #[input(description = "First magnitude")] first: NodeMagnitude,
+    #[input(description = "Second magnitude")] second: NodeMagnitude,
+) -> NodeMagnitude {
+    first * second
+}
+
+/// Adds two magnitudes, stopping at 1.
+#[proton_node(
+    id = "core.magnitude.add",
+    name = "Add Magnitudes",
+    outputs(result = "Resulting magnitude")
+)]
+fn add(
+    #[input(description = "First magnitude")] first: NodeMagnitude,
+    #[input(description = "Second magnitude")] second: NodeMagnitude,
+) -> NodeMagnitude {
+    first.saturating_add(second)
+}
+
+/// Subtracts one magnitude from another, stopping at 0.
+#[proton_node(
+    id = "core.magnitude.subtract",
+    name = "Subtract Magnitudes",
+    outputs(result = "Resulting magnitude")
+)]
+fn subtract(
+    #[input(description = "First magnitude")] first: NodeMagnitude,
+    #[input(description = "Second magnitude")] second: NodeMagnitude,
+) -> NodeMagnitude {
+    first.saturating_sub(second)
+}
+
+/// Blends from one magnitude to another by an amount.
+#[proton_node(
+    id = "core.magnitude.lerp",
+    name = "Interpolate Magnitudes",
+    outputs(result = "Resulting magnitude")
+)]
+fn lerp(
+    #[input(description = "Result when the amount is 0")] from: NodeMagnitude,
+    #[input(description = "Result when the amount is 1")] to: NodeMagnitude,
+    #[input(description = "How far to blend towards the second magnitude")] amount: NodeMagnitude,
+) -> NodeMagnitude {
+    from.lerp(to, amount)
+}
+
+/// Subtracts a magnitude from 1, so 0 becomes 1 and 1 becomes 0.
+#[proton_node(
+    id = "core.magnitude.invert",
+    name = "Invert Magnitude",
+    outputs(result = "Resulting magnitude")
+)]
+fn invert(#[input(description = "Magnitude to change")] magnitude: NodeMagnitude) -> NodeMagnitude {
+    magnitude.invert()
+}
+
+/// Eases a magnitude in and out of 0 and 1 along an S-shaped curve.
+#[proton_node(
+    id = "core.magnitude.smoothstep",
+    name = "Smoothstep Magnitude",
+    outputs(result = "Resulting magnitude")
+)]
+fn smoothstep(
+    #[input(description = "Magnitude to change")] magnitude: NodeMagnitude,
+) -> NodeMagnitude {
+    magnitude.smoothstep()
+}
+
+/// Raises a magnitude to a power, bending the middle of the range while leaving 0 and
+/// 1 in place. Gammas above 1 make it darker.
+#[proton_node(
+    id = "core.magnitude.gamma",
+    name = "Gamma",
+    outputs(result = "Resulting magnitude")
+)]
+fn gamma(
+    #[input(description = "Magnitude to change")] magnitude: NodeMagnitude,
+    #[input(description = "Power to raise the magnitude to", default = "umag:2.2")] gamma: f64,
+) -> NodeMagnitude {
+    magnitude.gamma(gamma)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(def: NodeDef, inputs: &[&NodeValue]) -> Vec<NodeValue> {
+        match def.runner {
+            NodeDefRunner::Function(function) => function(inputs.to_vec()),
+            _ => panic!("Magnitude nodes should be functions"),
+        }
+    }
+
+    #[test]
+    fn applies_fixed_point_math() {
+        let half = NodeValue::ConstrainedMagnitude(u32::MAX / 2 + 1);
+        let one = NodeValue::ConstrainedMagnitude(u32::MAX);
+        let quarter = NodeValue::ConstrainedMagnitude(NodeMagnitude::from_f64(0.25).to_bits());
+
+        assert_eq!(
+            run(MultiplyNode::node_def(), &[&half, &half]),
+            vec![quarter.clone()]
+        );
+        assert_eq!(run(AddNode::node_def(), &[&one, &half]), vec![one.clone()]);
+        assert_eq!(
+            run(SubtractNode::node_def(), &[&half, &one]),
+            vec![NodeValue::ConstrainedMagnitude(0)]
+        );
+        assert_eq!(
+            run(LerpNode::node_def(), &[&quarter, &one, &one]),
+            vec![one.clone()]
+        );
+        assert_eq!(
+            run(
+                GammaNode::node_def(),
+                &[&half, &NodeValue::UnconstrainedMagnitude(2.0)]
+            ),
+            vec![quarter]
+        );
+    }
+
+    #[test]
+    fn describes_defs_from_attributes() {
+        let def = GammaNode::node_def();
+        assert_eq!(GammaNode::ID, "core.magnitude.gamma");
+        assert_eq!(def.desc.name, "Gamma");
+        assert_eq!(
+            def.desc.description,
+            "Raises a magnitude to a power, bending the middle of the range while leaving \
+             0 and 1 in place. Gammas above 1 make it darker."
+        );
+        assert_eq!(def.inputs[0].desc.name, "magnitude");
+        assert_eq!(
+            def.inputs[0].allowed_types,
+            vec![NodeValueType::ConstrainedMagnitude]
+        );
+        assert!(def.inputs[0].required);
+        assert!(!def.inputs[1].required);
+        assert_eq!(
+            def.inputs[1].default,
+            Some(NodeValue::UnconstrainedMagnitude(2.2))
+        );
+        assert_eq!(def.outputs[0].desc.name, "result");
+        assert_eq!(
+            def.outputs[0].output_type,
+            NodeValueType::ConstrainedMagnitude
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Input 1 should be of type ConstrainedMagnitude but is Count")]
+    fn describes_invalid_inputs() {
+        run(
+            MultiplyNode::node_def(),
+            &[&NodeValue::ConstrainedMagnitude(0), &NodeValue::Count(1)],
+        );
+    }
+}
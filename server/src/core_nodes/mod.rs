@@ -0,0 +1,88 @@
+use proton_shared::node_def::*;
+use proton_shared::node_def_registry::{NodeDefRegistry, NodeDefRegistryError};
+use proton_shared::node_value::*;
+use std::sync::Arc;
+use strum::IntoEnumIterator;
+
+pub mod audio;
+pub mod audio_analysis;
+pub mod list;
+pub mod magnitude;
+pub mod pixel_map;
+pub mod raster;
+pub mod record;
+pub mod shader;
+pub mod tempo;
+
+/// Registers the standard library of NodeDefs that ship with the server.
+pub fn register_core_nodes(registry: &NodeDefRegistry) -> Result<(), NodeDefRegistryError> {
+    audio::register(registry)?;
+    audio_analysis::register(registry)?;
+    list::register(registry)?;
+    magnitude::register(registry)?;
+    pixel_map::register(registry)?;
+    raster::register(registry)?;
+    record::register(registry)?;
+    shader::register(registry)?;
+    tempo::register(registry)?;
+    Ok(())
+}
+
+/// Every NodeValueType, for inputs that accept any kind of value.
+fn all_types() -> Vec<NodeValueType> {
+    NodeValueType::iter().collect()
+}
+
+/// Types that have a default value, which is required by nodes that need to produce
+/// a value of the type even when given nothing to work with.
+fn types_with_defaults() -> impl Iterator<Item = NodeValueType> {
+    NodeValueType::iter().filter(|value_type| NodeValue::default_for(*value_type).is_some())
+}
+
+fn describe(name: &str, description: &str) -> NodeDefBasicDescription {
+    NodeDefBasicDescription {
+        name: name.to_string(),
+        description: description.to_string(),
+    }
+}
+
+fn input(name: &str, description: &str, allowed_types: Vec<NodeValueType>) -> NodeInputDef {
+    NodeInputDef {
+        desc: describe(name, description),
+        allowed_types,
+        required: true,
+        enum_def: None,
+        composite_type: None,
+        default: None,
+    }
+}
+
+fn output(name: &str, description: &str, output_type: NodeValueType) -> NodeOutputDef {
+    NodeOutputDef {
+        desc: describe(name, description),
+        output_type,
+        enum_def: None,
+        composite_type: None,
+    }
+}
+
+/// Options for nodes that blend colors, matching the BlendMode enum.
+fn blend_mode_enum() -> Arc<NodeEnumDef> {
+    Arc::new(NodeEnumDef::new(
+        "blend mode",
+        &["normal", "add", "multiply", "screen"],
+    ))
+}
+
+fn blend_mode_of(value: &NodeEnumValue) -> BlendMode {
+    match value.option() {
+        "add" => BlendMode::Add,
+        "multiply" => BlendMode::Multiply,
+        "screen" => BlendMode::Screen,
+        _ => BlendMode::Normal,
+    }
+}
+
+fn invalid_input(name: &str) -> ! {
+    panic!("Invalid type for NodeValue input {}", name);
+}
@@ -0,0 +1,239 @@
+use super::raster::average;
+use super::*;
+use proton_shared::shader_registry::ShaderDimension;
+
+pub fn register(registry: &NodeDefRegistry) -> Result<(), NodeDefRegistryError> {
+    let fixture_input = || {
+        input(
+            "fixture",
+            "Name of the fixture in the pixel map",
+            vec![NodeValueType::Text],
+        )
+    };
+    let outputs = || {
+        vec![
+            output(
+                "pixels",
+                "Color of each of the fixture's LEDs, in the order they are addressed",
+                NodeValueType::Bitmap1D,
+            ),
+            output(
+                "color",
+                "Average color of the fixture's LEDs, for fixtures with a single light",
+                NodeValueType::Color,
+            ),
+        ]
+    };
+
+    for dimension in &[ShaderDimension::Two, ShaderDimension::Three] {
+        registry.register(
+            format!("core.pixel_map.shader.{:?}", dimension.value_type()),
+            NodeDef {
+                desc: describe(
+                    "Map Shader",
+                    "Samples a shader at the position of each of a fixture's LEDs. \
+                     Positions are scaled so the whole pixel map goes from 0 to 1 along \
+                     each axis.",
+                ),
+                inputs: vec![
+                    fixture_input(),
+                    input("shader", "Shader to sample", vec![dimension.value_type()]),
+                ],
+                outputs: outputs(),
+                runner: NodeDefRunner::Executor(shader_executor(*dimension)),
+            },
+        )?;
+    }
+
+    registry.register(
+        "core.pixel_map.bitmap".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Map Bitmap",
+                "Samples a bitmap at the position of each of a fixture's LEDs. The bitmap \
+                 is stretched over the pixel map as seen from the front, along the x and \
+                 y axes, with its top row at the lowest y.",
+            ),
+            inputs: vec![
+                fixture_input(),
+                input("bitmap", "Bitmap to sample", vec![NodeValueType::Bitmap2D]),
+            ],
+            outputs: outputs(),
+            runner: NodeDefRunner::Executor(|| Box::new(MapBitmapExecutor)),
+        },
+    )?;
+    Ok(())
+}
+
+/// Executor constructors are plain function pointers, so each dimension needs its own.
+fn shader_executor(dimension: ShaderDimension) -> fn() -> Box<dyn NodeExecutor> {
+    match dimension {
+        ShaderDimension::Two => || Box::new(MapShaderExecutor(ShaderDimension::Two)),
+        _ => || Box::new(MapShaderExecutor(ShaderDimension::Three)),
+    }
+}
+
+fn fixture_of(value: &NodeValue) -> &str {
+    match value {
+        NodeValue::Text(name) => name.as_str(),
+        _ => invalid_input("fixture"),
+    }
+}
+
+/// Outputs for a fixture's LED colors. Unknown fixtures have no LEDs, so they
+/// produce an empty bitmap and a transparent color.
+fn fixture_outputs(pixels: Vec<NodeColor>) -> Vec<NodeValue> {
+    let color = average(pixels.iter().copied());
+    vec![
+        NodeValue::Bitmap1D(Arc::new(Bitmap1D::from_pixels(pixels))),
+        NodeValue::Color(color),
+    ]
+}
+
+struct MapShaderExecutor(ShaderDimension);
+
+impl NodeExecutor for MapShaderExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, context: &ExecutionContext) -> Vec<NodeValue> {
+        let positions = context
+            .pixel_map
+            .normalized_positions(fixture_of(inputs[0]))
+            .unwrap_or_default();
+        let shader = match ShaderDimension::of(inputs[1]) {
+            Some((dimension, _)) if dimension == self.0 => context.shaders.get(inputs[1]),
+            _ => invalid_input("shader"),
+        };
+        let pixels = positions
+            .into_iter()
+            .map(|position| match &shader {
+                Some(shader) => {
+                    let position = match self.0 {
+                        ShaderDimension::Three => position,
+                        _ => NodeVector3::new(position.x, position.y, 0.0),
+                    };
+                    shader.color_at(position, context.elapsed)
+                }
+                None => NodeColor::TRANSPARENT,
+            })
+            .collect();
+        fixture_outputs(pixels)
+    }
+}
+
+struct MapBitmapExecutor;
+
+impl NodeExecutor for MapBitmapExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, context: &ExecutionContext) -> Vec<NodeValue> {
+        let positions = context
+            .pixel_map
+            .normalized_positions(fixture_of(inputs[0]))
+            .unwrap_or_default();
+        let bitmap = match inputs[1] {
+            NodeValue::Bitmap2D(bitmap) => bitmap,
+            _ => invalid_input("bitmap"),
+        };
+        // Each LED takes the color of the pixel it falls within.
+        let cell = |value: f64, size: usize| ((value * size as f64) as usize).min(size - 1);
+        let pixels = positions
+            .into_iter()
+            .map(|position| {
+                if bitmap.width() == 0 || bitmap.height() == 0 {
+                    return NodeColor::TRANSPARENT;
+                }
+                let x = cell(position.x, bitmap.width());
+                let y = cell(position.y, bitmap.height());
+                *bitmap.get(x, y).unwrap()
+            })
+            .collect();
+        fixture_outputs(pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_graph::ComputeGraph;
+    use crate::node::*;
+    use proton_shared::pixel_map::{Fixture, PixelMap};
+
+    #[test]
+    fn samples_effects_at_fixture_positions() {
+        let registry = NodeDefRegistry::new();
+        shader::register(&registry).unwrap();
+        register(&registry).unwrap();
+        let text = |text: &str| NodeInput::Const(NodeValue::Text(Arc::new(text.to_string())));
+        let magnitude = |value| NodeInput::Const(NodeValue::UnconstrainedMagnitude(value));
+        let red = NodeColor::opaque(u16::MAX, 0, 0);
+        let blue = NodeColor::opaque(0, 0, u16::MAX);
+        let bitmap = Bitmap2D::from_rows(&[vec![red, blue]]).unwrap();
+        let nodes = vec![
+            Node {
+                id: 1,
+                def_name: "core.shader.expression.Shader3D".to_string(),
+                inputs: vec![
+                    text("rgb(x, 0, z)"),
+                    magnitude(0.0),
+                    magnitude(0.0),
+                    magnitude(0.0),
+                    magnitude(0.0),
+                ],
+            },
+            Node {
+                id: 2,
+                def_name: "core.pixel_map.shader.Shader3D".to_string(),
+                inputs: vec![
+                    text("strip"),
+                    NodeInput::Wire(NodeOutputRef {
+                        from_node_id: 1,
+                        node_output_index: 0,
+                    }),
+                ],
+            },
+            Node {
+                id: 3,
+                def_name: "core.pixel_map.bitmap".to_string(),
+                inputs: vec![
+                    text("spot"),
+                    NodeInput::Const(NodeValue::Bitmap2D(Arc::new(bitmap))),
+                ],
+            },
+        ];
+        let mut graph = ComputeGraph::new(registry, nodes);
+        // A strip along the x axis and a spotlight at the far corner, so that the
+        // bounds of the map go from 0 to 10 on every axis.
+        graph.set_pixel_map(PixelMap::new(vec![
+            Fixture::line(
+                "strip",
+                NodeVector3::ZERO,
+                NodeVector3::new(10.0, 0.0, 0.0),
+                3,
+            ),
+            Fixture::point("spot", NodeVector3::new(10.0, 10.0, 10.0)),
+        ]));
+        assert!(graph.prepare(1));
+        let result = graph.execute_at(NodeDuration::ZERO).unwrap();
+        let output = |from_node_id, node_output_index| {
+            result
+                .get(&NodeOutputRef {
+                    from_node_id,
+                    node_output_index,
+                })
+                .unwrap()
+                .clone()
+        };
+
+        let half = u16::MAX / 2 + 1;
+        assert_eq!(
+            output(2, 0),
+            NodeValue::Bitmap1D(Arc::new(Bitmap1D::from_pixels(vec![
+                NodeColor::BLACK,
+                NodeColor::opaque(half, 0, 0),
+                red,
+            ])))
+        );
+        assert_eq!(output(3, 1), NodeValue::Color(blue));
+    }
+}
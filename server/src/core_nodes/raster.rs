@@ -0,0 +1,356 @@
+use super::*;
+use proton_shared::shader_registry::BoundShader;
+use rayon::prelude::*;
+
+/// Largest width or height of a rasterized bitmap, in pixels.
+const MAX_RESOLUTION: i64 = 4096;
+
+/// Most samples taken along each axis of a pixel when supersampling.
+const MAX_SUPERSAMPLING: i64 = 8;
+
+pub fn register(registry: &NodeDefRegistry) -> Result<(), NodeDefRegistryError> {
+    let supersampling_input = || {
+        input(
+            "supersampling",
+            "Samples taken along each axis of a pixel and averaged together, \
+             or 1 to take a single sample at the center",
+            vec![NodeValueType::Count],
+        )
+    };
+
+    registry.register(
+        "core.shader.rasterize.Shader2D".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Rasterize",
+                "Draws a shader into a bitmap by sampling it at each pixel over a \
+                 rectangle of the shader's coordinates.",
+            ),
+            inputs: vec![
+                input("shader", "Shader to draw", vec![NodeValueType::Shader2D]),
+                input("width", "Width of the bitmap", vec![NodeValueType::Count]),
+                input("height", "Height of the bitmap", vec![NodeValueType::Count]),
+                input(
+                    "origin",
+                    "Shader position at the top left corner of the bitmap",
+                    vec![NodeValueType::Vector2D],
+                ),
+                input(
+                    "size",
+                    "Shader distance covered by the whole bitmap along each axis",
+                    vec![NodeValueType::Vector2D],
+                ),
+                supersampling_input(),
+            ],
+            outputs: vec![output("bitmap", "Drawn bitmap", NodeValueType::Bitmap2D)],
+            runner: NodeDefRunner::Executor(|| Box::new(Rasterize2DExecutor)),
+        },
+    )?;
+
+    registry.register(
+        "core.shader.rasterize.Shader1D".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Rasterize",
+                "Draws a shader into a bitmap by sampling it at each pixel over a span \
+                 of the shader's coordinates.",
+            ),
+            inputs: vec![
+                input("shader", "Shader to draw", vec![NodeValueType::Shader1D]),
+                input("width", "Width of the bitmap", vec![NodeValueType::Count]),
+                input(
+                    "start",
+                    "Shader position at the first pixel's edge",
+                    vec![NodeValueType::UnconstrainedMagnitude],
+                ),
+                input(
+                    "length",
+                    "Shader distance covered by the whole bitmap",
+                    vec![NodeValueType::UnconstrainedMagnitude],
+                ),
+                supersampling_input(),
+            ],
+            outputs: vec![output("bitmap", "Drawn bitmap", NodeValueType::Bitmap1D)],
+            runner: NodeDefRunner::Executor(|| Box::new(Rasterize1DExecutor)),
+        },
+    )?;
+    Ok(())
+}
+
+/// Which part of a shader's coordinates a bitmap covers, and how finely each pixel
+/// is sampled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RasterMapping {
+    pub origin: NodeVector2,
+    pub size: NodeVector2,
+
+    /// Samples along each axis of a pixel. The pixel is the average of all of them.
+    pub samples: usize,
+}
+
+/// Samples a 2D shader at every pixel of a bitmap, drawing rows in parallel.
+pub fn rasterize_2d(
+    shader: &BoundShader,
+    width: usize,
+    height: usize,
+    mapping: &RasterMapping,
+    elapsed: NodeDuration,
+) -> Bitmap2D {
+    let mut bitmap = Bitmap2D::new(width, height);
+    if width == 0 || height == 0 {
+        return bitmap;
+    }
+    let samples = mapping.samples.max(1);
+    let step_x = mapping.size.x / (width * samples) as f64;
+    let step_y = mapping.size.y / (height * samples) as f64;
+    bitmap
+        .pixels_mut()
+        .par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = average((0..samples * samples).map(|sample| {
+                    let sub_x = (x * samples + sample % samples) as f64 + 0.5;
+                    let sub_y = (y * samples + sample / samples) as f64 + 0.5;
+                    let position = NodeVector3::new(
+                        mapping.origin.x + sub_x * step_x,
+                        mapping.origin.y + sub_y * step_y,
+                        0.0,
+                    );
+                    shader.color_at(position, elapsed)
+                }));
+            }
+        });
+    bitmap
+}
+
+/// Samples a 1D shader at every pixel of a bitmap, using only the x axis of the
+/// mapping.
+pub fn rasterize_1d(
+    shader: &BoundShader,
+    width: usize,
+    mapping: &RasterMapping,
+    elapsed: NodeDuration,
+) -> Bitmap1D {
+    let samples = mapping.samples.max(1);
+    let step = mapping.size.x / (width * samples).max(1) as f64;
+    let pixels = (0..width)
+        .into_par_iter()
+        .map(|x| {
+            average((0..samples).map(|sample| {
+                let position = (x * samples + sample) as f64 + 0.5;
+                let position = NodeVector3::new(mapping.origin.x + position * step, 0.0, 0.0);
+                shader.color_at(position, elapsed)
+            }))
+        })
+        .collect();
+    Bitmap1D::from_pixels(pixels)
+}
+
+/// Averages colors in linear light, weighting each by its alpha so that transparent
+/// samples do not darken the result.
+pub(super) fn average(colors: impl Iterator<Item = NodeColor>) -> NodeColor {
+    let mut sum = LinearColor::TRANSPARENT;
+    let mut count = 0;
+    for color in colors {
+        let color = color.to_linear().premultiplied();
+        sum.r += color.r;
+        sum.g += color.g;
+        sum.b += color.b;
+        sum.a += color.a;
+        count += 1;
+    }
+    if sum.a <= 0.0 {
+        return NodeColor::TRANSPARENT;
+    }
+    NodeColor::from_linear(&LinearColor {
+        r: sum.r / sum.a,
+        g: sum.g / sum.a,
+        b: sum.b / sum.a,
+        a: sum.a / count as f32,
+    })
+}
+
+fn resolution_of(value: &NodeValue, name: &str) -> usize {
+    match value {
+        NodeValue::Count(count) => (*count).clamp(0, MAX_RESOLUTION) as usize,
+        _ => invalid_input(name),
+    }
+}
+
+fn supersampling_of(value: &NodeValue) -> usize {
+    match value {
+        NodeValue::Count(count) => (*count).clamp(1, MAX_SUPERSAMPLING) as usize,
+        _ => invalid_input("supersampling"),
+    }
+}
+
+struct Rasterize2DExecutor;
+
+impl NodeExecutor for Rasterize2DExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, context: &ExecutionContext) -> Vec<NodeValue> {
+        if !matches!(inputs[0], NodeValue::Shader2D(_)) {
+            invalid_input("shader");
+        }
+        let width = resolution_of(inputs[1], "width");
+        let height = resolution_of(inputs[2], "height");
+        let mapping = match (inputs[3], inputs[4]) {
+            (NodeValue::Vector2D(origin), NodeValue::Vector2D(size)) => RasterMapping {
+                origin: *origin,
+                size: *size,
+                samples: supersampling_of(inputs[5]),
+            },
+            (NodeValue::Vector2D(_), _) => invalid_input("size"),
+            _ => invalid_input("origin"),
+        };
+
+        // Shaders that are not registered, such as ones from a Node that failed to
+        // create its program, draw nothing.
+        let bitmap = match context.shaders.get(inputs[0]) {
+            Some(shader) => rasterize_2d(&shader, width, height, &mapping, context.elapsed),
+            None => Bitmap2D::new(width, height),
+        };
+        vec![NodeValue::Bitmap2D(Arc::new(bitmap))]
+    }
+}
+
+struct Rasterize1DExecutor;
+
+impl NodeExecutor for Rasterize1DExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, context: &ExecutionContext) -> Vec<NodeValue> {
+        if !matches!(inputs[0], NodeValue::Shader1D(_)) {
+            invalid_input("shader");
+        }
+        let width = resolution_of(inputs[1], "width");
+        let mapping = match (inputs[2], inputs[3]) {
+            (
+                NodeValue::UnconstrainedMagnitude(start),
+                NodeValue::UnconstrainedMagnitude(length),
+            ) => RasterMapping {
+                origin: NodeVector2::new(*start, 0.0),
+                size: NodeVector2::new(*length, 0.0),
+                samples: supersampling_of(inputs[4]),
+            },
+            (NodeValue::UnconstrainedMagnitude(_), _) => invalid_input("length"),
+            _ => invalid_input("start"),
+        };
+
+        let bitmap = match context.shaders.get(inputs[0]) {
+            Some(shader) => rasterize_1d(&shader, width, &mapping, context.elapsed),
+            None => Bitmap1D::new(width),
+        };
+        vec![NodeValue::Bitmap1D(Arc::new(bitmap))]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_graph::ComputeGraph;
+    use crate::node::*;
+    use proton_shared::shader_registry::{ShaderContext, ShaderDimension, ShaderRegistry};
+
+    #[test]
+    fn rasterizes_expression_shaders() {
+        let registry = NodeDefRegistry::new();
+        shader::register(&registry).unwrap();
+        register(&registry).unwrap();
+        let value = |value| NodeInput::Const(value);
+        let magnitude = |value| NodeInput::Const(NodeValue::UnconstrainedMagnitude(value));
+        let rasterize = |id, samples| Node {
+            id,
+            def_name: "core.shader.rasterize.Shader2D".to_string(),
+            inputs: vec![
+                NodeInput::Wire(NodeOutputRef {
+                    from_node_id: 1,
+                    node_output_index: 0,
+                }),
+                value(NodeValue::Count(4)),
+                value(NodeValue::Count(2)),
+                value(NodeValue::Vector2D(NodeVector2::ZERO)),
+                value(NodeValue::Vector2D(NodeVector2::new(1.0, 1.0))),
+                value(NodeValue::Count(samples)),
+            ],
+        };
+        let nodes = vec![
+            Node {
+                id: 1,
+                def_name: "core.shader.expression.Shader2D".to_string(),
+                inputs: vec![
+                    value(NodeValue::Text(Arc::new("step(0.6, x)".to_string()))),
+                    magnitude(0.0),
+                    magnitude(0.0),
+                    magnitude(0.0),
+                    magnitude(0.0),
+                ],
+            },
+            rasterize(2, 1),
+            rasterize(3, 2),
+        ];
+        let mut graph = ComputeGraph::new(registry, nodes);
+        assert!(graph.prepare(1));
+        let result = graph.execute_at(NodeDuration::ZERO).unwrap();
+        let bitmap = |from_node_id| match result.get(&NodeOutputRef {
+            from_node_id,
+            node_output_index: 0,
+        }) {
+            Some(NodeValue::Bitmap2D(bitmap)) => bitmap.clone(),
+            other => panic!("Expected a bitmap, got {:?}", other),
+        };
+
+        // Pixel centers are at x = 0.125, 0.375, 0.625 and 0.875.
+        let (black, white) = (NodeColor::BLACK, NodeColor::WHITE);
+        let row = vec![black, black, white, white];
+        assert_eq!(bitmap(2).to_rows(), vec![row.clone(), row]);
+
+        // With 2x2 supersampling, the third pixel is sampled at x = 0.5625 and 0.6875,
+        // which straddle the edge, so it is half lit in linear light.
+        let half = NodeColor::from_linear(&LinearColor {
+            r: 0.5,
+            g: 0.5,
+            b: 0.5,
+            a: 1.0,
+        });
+        assert_eq!(bitmap(3).row(1).unwrap(), &[black, black, half, white][..]);
+    }
+
+    #[test]
+    fn averages_transparent_samples_into_alpha() {
+        let shaders = ShaderRegistry::new();
+        let id = shaders
+            .register(
+                ShaderDimension::One,
+                Arc::new(|position: NodeVector3, _: &ShaderContext<'_>| {
+                    if position.x >= 10.5 {
+                        NodeColor::WHITE
+                    } else {
+                        NodeColor::TRANSPARENT
+                    }
+                }),
+            )
+            .unwrap();
+        let shader = shaders.get(&NodeValue::Shader1D(id)).unwrap();
+        let mapping = RasterMapping {
+            origin: NodeVector2::new(8.0, 0.0),
+            size: NodeVector2::new(4.0, 0.0),
+            samples: 4,
+        };
+        let bitmap = rasterize_1d(&shader, 4, &mapping, NodeDuration::ZERO);
+
+        // Transparent samples lower the alpha of a pixel, not its brightness.
+        let half_white = NodeColor::new(u16::MAX, u16::MAX, u16::MAX, u16::MAX / 2 + 1);
+        assert_eq!(
+            bitmap.pixels(),
+            &[
+                NodeColor::TRANSPARENT,
+                NodeColor::TRANSPARENT,
+                half_white,
+                NodeColor::WHITE
+            ][..]
+        );
+    }
+}
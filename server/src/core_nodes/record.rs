@@ -0,0 +1,196 @@
+use super::*;
+
+pub fn register(registry: &NodeDefRegistry) -> Result<(), NodeDefRegistryError> {
+    registry.register(
+        "core.record.empty".to_owned(),
+        NodeDef {
+            desc: describe("Empty Record", "A record with no fields."),
+            inputs: vec![],
+            outputs: vec![output("record", "Empty record", NodeValueType::Record)],
+            runner: NodeDefRunner::Function(empty),
+        },
+    )?;
+
+    registry.register(
+        "core.record.with_field".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Set Field",
+                "Builds a record by setting a field on another record. New fields are \
+                 added after the existing ones.",
+            ),
+            inputs: vec![
+                input("record", "Record to add to", vec![NodeValueType::Record]),
+                input("name", "Name of the field", vec![NodeValueType::Text]),
+                input("value", "Value of the field", all_types()),
+            ],
+            outputs: vec![output("record", "Updated record", NodeValueType::Record)],
+            runner: NodeDefRunner::Function(with_field),
+        },
+    )?;
+
+    for field_type in types_with_defaults() {
+        registry.register(
+            format!("core.record.get.{:?}", field_type),
+            NodeDef {
+                desc: describe(
+                    "Get Field",
+                    "Gets a single field from a record. Produces a default value if the \
+                     field is missing or has a different type.",
+                ),
+                inputs: vec![
+                    input("record", "Record to read", vec![NodeValueType::Record]),
+                    input("name", "Name of the field", vec![NodeValueType::Text]),
+                ],
+                outputs: vec![
+                    output("value", "Value of the field", field_type),
+                    output(
+                        "found",
+                        "Whether the field exists with the expected type",
+                        NodeValueType::Toggle,
+                    ),
+                ],
+                runner: NodeDefRunner::Function(get_field_runner(field_type)),
+            },
+        )?;
+    }
+    Ok(())
+}
+
+fn record_arg<'a>(value: &'a NodeValue, name: &str) -> &'a Arc<NodeRecord> {
+    match value {
+        NodeValue::Record(record) => record,
+        _ => invalid_input(name),
+    }
+}
+
+fn text_arg<'a>(value: &'a NodeValue, name: &str) -> &'a str {
+    match value {
+        NodeValue::Text(text) => text,
+        _ => invalid_input(name),
+    }
+}
+
+fn empty(_inputs: Vec<&NodeValue>) -> Vec<NodeValue> {
+    vec![NodeValue::Record(Arc::new(NodeRecord::new()))]
+}
+
+fn with_field(inputs: Vec<&NodeValue>) -> Vec<NodeValue> {
+    let mut record = record_arg(inputs[0], "record").clone();
+    let name = text_arg(inputs[1], "name");
+    Arc::make_mut(&mut record).set(name, inputs[2].clone());
+    vec![NodeValue::Record(record)]
+}
+
+/// Reads a field, falling back to a default value if the field is missing or is not
+/// of the expected type.
+fn get_field(inputs: Vec<&NodeValue>, field_type: NodeValueType) -> Vec<NodeValue> {
+    let record = record_arg(inputs[0], "record");
+    let name = text_arg(inputs[1], "name");
+    match record.get(name) {
+        Some(value) if NodeValueType::from(value) == field_type => {
+            vec![value.clone(), NodeValue::Toggle(true)]
+        }
+        _ => vec![
+            NodeValue::default_for(field_type).unwrap(),
+            NodeValue::Toggle(false),
+        ],
+    }
+}
+
+/// Runners are plain function pointers, so each field type needs its own function
+/// that knows which type to read.
+macro_rules! get_field_runners {
+    ($($field_type:ident),*) => {
+        fn get_field_runner(field_type: NodeValueType) -> fn(Vec<&NodeValue>) -> Vec<NodeValue> {
+            match field_type {
+                $(NodeValueType::$field_type => {
+                    |inputs| get_field(inputs, NodeValueType::$field_type)
+                })*
+                _ => panic!("No default value for field type {:?}", field_type),
+            }
+        }
+    };
+}
+
+get_field_runners!(
+    Trigger,
+    Toggle,
+    Count,
+    ConstrainedMagnitude,
+    UnconstrainedMagnitude,
+    Color,
+    Text,
+    Bitmap1D,
+    Bitmap2D,
+    Vector2D,
+    Vector3D,
+    Duration,
+    Timestamp,
+    Record,
+    Audio
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_graph::ComputeGraph;
+    use crate::node::*;
+
+    #[test]
+    fn builds_and_reads_records() {
+        let registry = NodeDefRegistry::new();
+        register(&registry).unwrap();
+
+        let text = |text: &str| NodeInput::Const(NodeValue::Text(Arc::new(text.to_string())));
+        let wire = |from_node_id: u32| {
+            NodeInput::Wire(NodeOutputRef {
+                from_node_id,
+                node_output_index: 0,
+            })
+        };
+        let position = NodeValue::Vector2D(NodeVector2::new(1.0, 2.0));
+        let nodes = vec![
+            Node {
+                id: 1,
+                def_name: "core.record.empty".to_string(),
+                inputs: vec![],
+            },
+            Node {
+                id: 2,
+                def_name: "core.record.with_field".to_string(),
+                inputs: vec![
+                    wire(1),
+                    text("position"),
+                    NodeInput::Const(position.clone()),
+                ],
+            },
+            Node {
+                id: 3,
+                def_name: "core.record.get.Vector2D".to_string(),
+                inputs: vec![wire(2), text("position")],
+            },
+            Node {
+                id: 4,
+                def_name: "core.record.get.Color".to_string(),
+                inputs: vec![wire(2), text("position")],
+            },
+        ];
+        let mut graph = ComputeGraph::new(registry, nodes);
+        assert!(graph.prepare(2));
+        let result = graph.execute().unwrap();
+        let output = |from_node_id: u32, node_output_index: u8| {
+            result
+                .get(&NodeOutputRef {
+                    from_node_id,
+                    node_output_index,
+                })
+                .unwrap()
+        };
+
+        assert_eq!(output(3, 0), &position);
+        assert_eq!(output(3, 1), &NodeValue::Toggle(true));
+        assert_eq!(output(4, 0), &NodeValue::Color(NodeColor::TRANSPARENT));
+        assert_eq!(output(4, 1), &NodeValue::Toggle(false));
+    }
+}
@@ -0,0 +1,285 @@
+use super::*;
+use parking_lot::Mutex;
+use proton_shared::shader_expression::ShaderExpression;
+use proton_shared::shader_registry::{
+    ShaderContext, ShaderDimension, ShaderProgram, ShaderRegistry, ShaderRegistryError,
+};
+
+/// Names of the inputs that expressions can read as uniforms.
+const UNIFORM_NAMES: [&str; 4] = ["a", "b", "c", "d"];
+
+pub fn register(registry: &NodeDefRegistry) -> Result<(), NodeDefRegistryError> {
+    for dimension in &[
+        ShaderDimension::One,
+        ShaderDimension::Two,
+        ShaderDimension::Three,
+    ] {
+        let mut inputs = vec![input(
+            "expression",
+            "Shader expression, such as hsv(x + t*0.1, 1, sin(y*6.28))",
+            vec![NodeValueType::Text],
+        )];
+        for name in &UNIFORM_NAMES {
+            let mut uniform = input(
+                name,
+                "Value the expression can read by this input's name, 0 if left out",
+                vec![NodeValueType::UnconstrainedMagnitude],
+            );
+            uniform.required = false;
+            uniform.default = Some(NodeValue::UnconstrainedMagnitude(0.0));
+            inputs.push(uniform);
+        }
+        registry.register(
+            format!("core.shader.expression.{:?}", dimension.value_type()),
+            NodeDef {
+                desc: describe(
+                    "Shader Expression",
+                    "Compiles a shader from a math expression over the position (x, y, z), \
+                     the time in seconds (t) and the values of inputs a to d.",
+                ),
+                inputs,
+                outputs: vec![
+                    output("shader", "Compiled shader", dimension.value_type()),
+                    output(
+                        "error",
+                        "Why the expression could not be compiled, or empty if it was",
+                        NodeValueType::Text,
+                    ),
+                ],
+                runner: NodeDefRunner::Executor(expression_executor(*dimension)),
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Executor constructors are plain function pointers, so each dimension needs its own.
+fn expression_executor(dimension: ShaderDimension) -> fn() -> Box<dyn NodeExecutor> {
+    match dimension {
+        ShaderDimension::One => || Box::new(ExpressionExecutor::new(ShaderDimension::One)),
+        ShaderDimension::Two => || Box::new(ExpressionExecutor::new(ShaderDimension::Two)),
+        ShaderDimension::Three => || Box::new(ExpressionExecutor::new(ShaderDimension::Three)),
+    }
+}
+
+struct ExpressionExecutor {
+    dimension: ShaderDimension,
+    state: Mutex<ExpressionState>,
+}
+
+#[derive(Default)]
+struct ExpressionState {
+    /// Source of the most recently compiled expression.
+    source: Option<String>,
+    error: String,
+
+    /// Registry the program was added to, and its id there.
+    program: Option<(ShaderRegistry, u16)>,
+}
+
+impl ExpressionExecutor {
+    fn new(dimension: ShaderDimension) -> ExpressionExecutor {
+        ExpressionExecutor {
+            dimension,
+            state: Mutex::new(ExpressionState::default()),
+        }
+    }
+}
+
+impl NodeExecutor for ExpressionExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, context: &ExecutionContext) -> Vec<NodeValue> {
+        let source = match inputs[0] {
+            NodeValue::Text(source) => source.as_str(),
+            _ => invalid_input("expression"),
+        };
+        let uniforms: Vec<f64> = inputs[1..]
+            .iter()
+            .zip(&UNIFORM_NAMES)
+            .map(|(value, name)| match value {
+                NodeValue::UnconstrainedMagnitude(value) => *value,
+                _ => invalid_input(name),
+            })
+            .collect();
+
+        let mut state = self.state.lock();
+        let lost = state
+            .program
+            .as_ref()
+            .is_some_and(|(registry, id)| registry.get_by_id(self.dimension, *id).is_none());
+        if lost {
+            // The registry was reset, so the expression needs registering again.
+            state.program = None;
+            state.source = None;
+        }
+        if state.source.as_deref() != Some(source) {
+            state.source = Some(source.to_string());
+            // A broken edit keeps the last working program.
+            state.error = match ShaderExpression::compile(source, &UNIFORM_NAMES) {
+                Ok(expression) => {
+                    match state.set_program(self.dimension, Arc::new(expression), context) {
+                        Ok(()) => String::new(),
+                        Err(err) => {
+                            // Try again on the next execution.
+                            state.source = None;
+                            err.to_string()
+                        }
+                    }
+                }
+                Err(err) => err.to_string(),
+            };
+        }
+
+        // Until an expression compiles, or if its program could not be kept, the
+        // shader draws nothing.
+        if state.program.is_none() {
+            let transparent = |_: NodeVector3, _: &ShaderContext<'_>| NodeColor::TRANSPARENT;
+            if let Err(err) = state.set_program(self.dimension, Arc::new(transparent), context) {
+                state.error = err.to_string();
+            }
+        }
+
+        let id = match &state.program {
+            Some((registry, id)) => match registry.set_uniforms(self.dimension, *id, uniforms) {
+                Ok(()) => Some(*id),
+                Err(err) => {
+                    state.error = err.to_string();
+                    state.program = None;
+                    state.source = None;
+                    None
+                }
+            },
+            None => None,
+        };
+        vec![
+            self.dimension
+                .value(id.unwrap_or(ShaderRegistry::NO_PROGRAM)),
+            NodeValue::Text(Arc::new(state.error.clone())),
+        ]
+    }
+}
+
+impl ExpressionState {
+    /// Swaps in a new program under the current id, or registers it under a new one
+    /// if there is no current id or it has gone, such as after the registry was reset.
+    fn set_program(
+        &mut self,
+        dimension: ShaderDimension,
+        program: Arc<dyn ShaderProgram>,
+        context: &ExecutionContext,
+    ) -> Result<(), ShaderRegistryError> {
+        if let Some((registry, id)) = &self.program {
+            if registry.replace(dimension, *id, program.clone()).is_ok() {
+                return Ok(());
+            }
+            self.program = None;
+        }
+        let id = context.shaders.register(dimension, program)?;
+        self.program = Some((context.shaders.clone(), id));
+        Ok(())
+    }
+}
+
+impl Drop for ExpressionExecutor {
+    fn drop(&mut self) {
+        if let Some((registry, id)) = self.state.get_mut().program.take() {
+            let _ = registry.release(self.dimension, id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_graph::ComputeGraph;
+    use crate::node::*;
+
+    #[test]
+    fn compiles_expressions_into_shaders() {
+        let registry = NodeDefRegistry::new();
+        register(&registry).unwrap();
+        let text = |text: &str| NodeInput::Const(NodeValue::Text(Arc::new(text.to_string())));
+        let magnitude = |value| NodeInput::Const(NodeValue::UnconstrainedMagnitude(value));
+        let node = |source: &str| Node {
+            id: 1,
+            def_name: "core.shader.expression.Shader2D".to_string(),
+            // Uniforms b to d are left out, so they read as 0.
+            inputs: vec![text(source), magnitude(0.5)],
+        };
+        let mut graph = ComputeGraph::new(registry, vec![node("rgb(x, a, y)")]);
+        assert!(graph.prepare(1));
+        let shaders = graph.get_shader_registry();
+
+        let run = |graph: &ComputeGraph| {
+            let result = graph.execute_at(NodeDuration::ZERO).unwrap();
+            let output = |node_output_index| {
+                result
+                    .get(&NodeOutputRef {
+                        from_node_id: 1,
+                        node_output_index,
+                    })
+                    .unwrap()
+                    .clone()
+            };
+            (output(0), output(1))
+        };
+        let (shader, error) = run(&graph);
+        assert_eq!(error, NodeValue::Text(Arc::new(String::new())));
+        let color = shaders
+            .get(&shader)
+            .unwrap()
+            .color_at(NodeVector3::new(1.0, 0.0, 0.0), NodeDuration::ZERO);
+        assert_eq!(color, NodeColor::opaque(u16::MAX, u16::MAX / 2 + 1, 0));
+
+        // A Node whose expression does not compile reports why and draws nothing.
+        graph.set_node(node("rgb(x, a"));
+        assert!(graph.prepare(1));
+        let (shader, error) = run(&graph);
+        assert_eq!(
+            error,
+            NodeValue::Text(Arc::new("Expected ',' or ')' at column 9".to_string()))
+        );
+        assert_eq!(
+            shaders
+                .get(&shader)
+                .unwrap()
+                .color_at(NodeVector3::ZERO, NodeDuration::ZERO),
+            NodeColor::TRANSPARENT
+        );
+
+        drop(graph);
+        assert!(shaders.is_empty());
+    }
+
+    #[test]
+    fn recovers_from_missing_programs() {
+        let executor = ExpressionExecutor::new(ShaderDimension::One);
+        let source = NodeValue::Text(Arc::new("gray(x)".to_string()));
+        let uniform = NodeValue::UnconstrainedMagnitude(0.0);
+        let inputs = || vec![&source, &uniform, &uniform, &uniform, &uniform];
+        let context = |shaders: &ShaderRegistry| {
+            ExecutionContext::new(
+                NodeDuration::ZERO,
+                Default::default(),
+                shaders.clone(),
+                Default::default(),
+            )
+        };
+
+        let full = ShaderRegistry::with_capacity(0);
+        let outputs = executor.execute(inputs(), &context(&full));
+        assert_eq!(outputs[0], NodeValue::Shader1D(ShaderRegistry::NO_PROGRAM));
+        assert_eq!(
+            outputs[1],
+            NodeValue::Text(Arc::new("No free Shader1D shader ids".to_string()))
+        );
+
+        // Once the registry has room, the Node gets a program again.
+        let shaders = ShaderRegistry::new();
+        executor.execute(inputs(), &context(&shaders));
+        shaders.reset();
+        let outputs = executor.execute(inputs(), &context(&shaders));
+        assert!(shaders.get(&outputs[0]).is_some());
+    }
+}
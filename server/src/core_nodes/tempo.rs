@@ -0,0 +1,375 @@
+use super::*;
+use parking_lot::Mutex;
+use proton_node_macros::proton_node;
+use proton_shared::tempo::TempoChange;
+
+pub fn register(registry: &NodeDefRegistry) -> Result<(), NodeDefRegistryError> {
+    ClockExecutor::register(registry)?;
+
+    let mut division = input(
+        "division",
+        "Whether to wait for the next beat or the next bar",
+        vec![NodeValueType::Enumeration],
+    );
+    division.enum_def = Some(tempo_division_enum());
+    registry.register(
+        "core.tempo.quantize".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Quantize",
+                "Delays a trigger until the start of the next beat or bar, so that \
+                 changes land in time with the music.",
+            ),
+            inputs: vec![
+                input("trigger", "Trigger to delay", vec![NodeValueType::Trigger]),
+                division,
+            ],
+            outputs: vec![output(
+                "trigger",
+                "Fires on the first beat or bar after the input fires",
+                NodeValueType::Trigger,
+            )],
+            runner: NodeDefRunner::Executor(|| Box::new(QuantizeExecutor::default())),
+        },
+    )?;
+
+    registry.register(
+        "core.tempo.tap".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Tap Tempo",
+                "Sets the tempo by tapping in time with the music. Each tap also lines \
+                 the beat up with the tap.",
+            ),
+            inputs: vec![input("tap", "Tap", vec![NodeValueType::Trigger])],
+            outputs: vec![],
+            runner: NodeDefRunner::Executor(|| Box::new(TempoControlExecutor { change: tap })),
+        },
+    )?;
+
+    registry.register(
+        "core.tempo.set_bpm".to_owned(),
+        NodeDef {
+            desc: describe("Set Tempo", "Sets the tempo to an exact value."),
+            inputs: vec![
+                input("set", "Applies the tempo", vec![NodeValueType::Trigger]),
+                input(
+                    "bpm",
+                    "Tempo in beats per minute",
+                    vec![NodeValueType::UnconstrainedMagnitude],
+                ),
+            ],
+            outputs: vec![],
+            runner: NodeDefRunner::Executor(|| Box::new(TempoControlExecutor { change: set_bpm })),
+        },
+    )?;
+
+    registry.register(
+        "core.tempo.nudge".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Nudge Tempo",
+                "Moves the beat earlier or later without changing the tempo, to line it \
+                 up with the music.",
+            ),
+            inputs: vec![
+                input("nudge", "Applies the nudge", vec![NodeValueType::Trigger]),
+                input(
+                    "amount",
+                    "How far to move the clock ahead, or behind if negative",
+                    vec![NodeValueType::Duration],
+                ),
+            ],
+            outputs: vec![],
+            runner: NodeDefRunner::Executor(|| Box::new(TempoControlExecutor { change: nudge })),
+        },
+    )?;
+
+    registry.register(
+        "core.tempo.downbeat".to_owned(),
+        NodeDef {
+            desc: describe(
+                "Downbeat",
+                "Marks the current moment as the start of a bar.",
+            ),
+            inputs: vec![input(
+                "downbeat",
+                "Marks the downbeat",
+                vec![NodeValueType::Trigger],
+            )],
+            outputs: vec![],
+            runner: NodeDefRunner::Executor(|| Box::new(TempoControlExecutor { change: downbeat })),
+        },
+    )?;
+    Ok(())
+}
+
+fn tempo_division_enum() -> Arc<NodeEnumDef> {
+    Arc::new(NodeEnumDef::new("tempo division", &["beat", "bar"]))
+}
+
+fn trigger_arg(value: &NodeValue, name: &str) -> bool {
+    match value {
+        NodeValue::Trigger(fired) => *fired,
+        _ => invalid_input(name),
+    }
+}
+
+/// Whether a position moved into a new division since the previous execution. On
+/// the first execution, only a position exactly at the start of a division counts.
+fn crossed_division(previous: Option<f64>, position: f64, length: f64) -> bool {
+    match previous {
+        Some(previous) => (previous / length).floor() != (position / length).floor(),
+        None => position.rem_euclid(length) == 0.0,
+    }
+}
+
+#[derive(Default)]
+struct ClockExecutor {
+    previous_beat: Mutex<Option<f64>>,
+}
+
+/// Follows the graph's musical clock, firing on every beat and bar.
+#[proton_node(
+    id = "core.tempo.clock",
+    name = "Tempo Clock",
+    outputs(
+        bpm = "Tempo in beats per minute",
+        beat_phase = "How far through the current beat the clock is",
+        bar_phase = "How far through the current bar the clock is",
+        beat = "Fires on every beat",
+        bar = "Fires on every bar",
+        beat_in_bar = "Index of the current beat within its bar, starting at 0",
+    )
+)]
+impl ClockExecutor {
+    fn run(
+        &self,
+        context: &ExecutionContext,
+    ) -> (
+        f64,
+        NodeMagnitude,
+        NodeMagnitude,
+        NodeTrigger,
+        NodeTrigger,
+        i64,
+    ) {
+        let tempo = &context.tempo;
+        let beat = tempo.beat_at(context.elapsed);
+        let beats_per_bar = tempo.beats_per_bar() as f64;
+
+        let mut previous_beat = self.previous_beat.lock();
+        let on_beat = crossed_division(*previous_beat, beat, 1.0);
+        let on_bar = crossed_division(*previous_beat, beat, beats_per_bar);
+        *previous_beat = Some(beat);
+
+        (
+            tempo.bpm(),
+            NodeMagnitude::from_f64(tempo.beat_phase_at(context.elapsed)),
+            NodeMagnitude::from_f64(tempo.bar_phase_at(context.elapsed)),
+            NodeTrigger(on_beat),
+            NodeTrigger(on_bar),
+            beat.floor().rem_euclid(beats_per_bar) as i64,
+        )
+    }
+}
+
+#[derive(Default)]
+struct QuantizeExecutor {
+    /// Whether a trigger is waiting to be released, and the beat position at the
+    /// previous execution.
+    state: Mutex<(bool, Option<f64>)>,
+}
+
+impl NodeExecutor for QuantizeExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, context: &ExecutionContext) -> Vec<NodeValue> {
+        let triggered = trigger_arg(inputs[0], "trigger");
+        let length = match inputs[1] {
+            NodeValue::Enumeration(division) if division.option() == "bar" => {
+                context.tempo.beats_per_bar() as f64
+            }
+            NodeValue::Enumeration(_) => 1.0,
+            _ => invalid_input("division"),
+        };
+        let beat = context.tempo.beat_at(context.elapsed);
+
+        let mut state = self.state.lock();
+        let (pending, previous_beat) = &mut *state;
+
+        // Triggers that arrive during this execution wait for the next division,
+        // even if one was crossed since the previous execution.
+        let fire = *pending && crossed_division(*previous_beat, beat, length);
+        *pending = (*pending && !fire) || triggered;
+        *previous_beat = Some(beat);
+        vec![NodeValue::Trigger(fire)]
+    }
+}
+
+/// Requests a tempo change when its inputs call for one. The change takes effect
+/// after the current execution.
+struct TempoControlExecutor {
+    change: fn(&[&NodeValue]) -> Option<TempoChange>,
+}
+
+impl NodeExecutor for TempoControlExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, context: &ExecutionContext) -> Vec<NodeValue> {
+        if let Some(change) = (self.change)(&inputs) {
+            context.request_tempo_change(change);
+        }
+        vec![]
+    }
+}
+
+fn tap(inputs: &[&NodeValue]) -> Option<TempoChange> {
+    if trigger_arg(inputs[0], "tap") {
+        Some(TempoChange::Tap)
+    } else {
+        None
+    }
+}
+
+fn set_bpm(inputs: &[&NodeValue]) -> Option<TempoChange> {
+    if !trigger_arg(inputs[0], "set") {
+        return None;
+    }
+    match inputs[1] {
+        NodeValue::UnconstrainedMagnitude(bpm) => Some(TempoChange::SetBpm(*bpm)),
+        _ => invalid_input("bpm"),
+    }
+}
+
+fn nudge(inputs: &[&NodeValue]) -> Option<TempoChange> {
+    if !trigger_arg(inputs[0], "nudge") {
+        return None;
+    }
+    match inputs[1] {
+        NodeValue::Duration(amount) => Some(TempoChange::Nudge(*amount)),
+        _ => invalid_input("amount"),
+    }
+}
+
+fn downbeat(inputs: &[&NodeValue]) -> Option<TempoChange> {
+    if trigger_arg(inputs[0], "downbeat") {
+        Some(TempoChange::Downbeat)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_graph::ComputeGraph;
+    use crate::node::*;
+    use proton_shared::pixel_map::PixelMap;
+    use proton_shared::shader_registry::ShaderRegistry;
+    use proton_shared::tempo::TempoClock;
+
+    fn ms(millis: i64) -> NodeDuration {
+        NodeDuration::from_millis(millis)
+    }
+
+    #[test]
+    fn fires_on_beats_and_bars() {
+        let registry = NodeDefRegistry::new();
+        register(&registry).unwrap();
+        let mut graph = ComputeGraph::new(
+            registry,
+            vec![Node {
+                id: 1,
+                def_name: "core.tempo.clock".to_string(),
+                inputs: vec![],
+            }],
+        );
+        assert!(graph.prepare(1));
+
+        // At the default 120 BPM in 4/4, beats are 500ms apart and bars are 2s apart.
+        let mut beats = vec![];
+        let mut bars = vec![];
+        for step in 0..=25 {
+            let millis = step * 100;
+            let result = graph.execute_at(ms(millis)).unwrap();
+            let output = |node_output_index| {
+                result
+                    .get(&NodeOutputRef {
+                        from_node_id: 1,
+                        node_output_index,
+                    })
+                    .unwrap()
+            };
+            if output(3) == &NodeValue::Trigger(true) {
+                beats.push(millis);
+            }
+            if output(4) == &NodeValue::Trigger(true) {
+                bars.push(millis);
+            }
+            if millis == 1250 {
+                assert_eq!(
+                    output(1),
+                    &NodeValue::ConstrainedMagnitude(u32::MAX / 2 + 1)
+                );
+                assert_eq!(output(5), &NodeValue::Count(2));
+            }
+        }
+        assert_eq!(beats, vec![0, 500, 1000, 1500, 2000, 2500]);
+        assert_eq!(bars, vec![0, 2000]);
+    }
+
+    #[test]
+    fn applies_tempo_changes_after_execution() {
+        let registry = NodeDefRegistry::new();
+        register(&registry).unwrap();
+        let mut graph = ComputeGraph::new(
+            registry,
+            vec![Node {
+                id: 1,
+                def_name: "core.tempo.tap".to_string(),
+                inputs: vec![NodeInput::Const(NodeValue::Trigger(true))],
+            }],
+        );
+        assert!(graph.prepare(1));
+
+        for millis in &[100, 500, 900, 1300] {
+            graph.execute_at(ms(*millis)).unwrap();
+        }
+        assert!((graph.get_tempo().bpm() - 150.0).abs() < 1e-9);
+        assert_eq!(graph.get_tempo().beat_phase_at(ms(1300)), 0.0);
+
+        graph.request_tempo_change(TempoChange::SetBpm(90.0));
+        graph.remove_node(&1);
+        assert!(graph.prepare(1));
+        graph.execute_at(ms(0)).unwrap();
+        assert_eq!(graph.get_tempo().bpm(), 90.0);
+
+        // The clock picks up from the last execution, not from the wall clock.
+        assert_eq!(graph.get_tempo().beat_phase_at(ms(0)), 0.0);
+    }
+
+    #[test]
+    fn quantizes_triggers_to_next_division() {
+        let executor = QuantizeExecutor::default();
+        let beat = NodeValue::Enumeration(
+            NodeEnumValue::from_option(tempo_division_enum(), "beat").unwrap(),
+        );
+        let run = |millis, triggered| {
+            let context = ExecutionContext::new(
+                ms(millis),
+                TempoClock::default(),
+                ShaderRegistry::new(),
+                Arc::new(PixelMap::default()),
+            );
+            let trigger = NodeValue::Trigger(triggered);
+            executor.execute(vec![&trigger, &beat], &context)[0].clone()
+        };
+
+        assert_eq!(run(100, true), NodeValue::Trigger(false));
+        assert_eq!(run(300, false), NodeValue::Trigger(false));
+        assert_eq!(run(520, false), NodeValue::Trigger(true));
+        assert_eq!(run(700, false), NodeValue::Trigger(false));
+        assert_eq!(run(1100, false), NodeValue::Trigger(false));
+    }
+}
@@ -7,8 +7,53 @@ extern crate strum_macros;
 pub mod test_macros;
 
 pub mod compute_graph;
+pub mod core_nodes;
 pub mod node;
+pub mod output_device;
+pub mod plugin_host;
+pub mod script_node;
+pub mod wasm_node;
+
+use plugin_host::PluginHost;
+use proton_shared::node_def_catalog;
+use proton_shared::node_def_registry::NodeDefRegistry;
+use proton_shared::serialization;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Directory that plugins are loaded from, relative to the working directory.
+const PLUGINS_DIR: &str = "plugins";
+
+/// Writes the catalog of core and plugin NodeDefs to `node_catalog.json` in a
+/// directory, along with its JSON Schema in `node_catalog.schema.json`, for node
+/// editors to load.
+fn export_node_catalog(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let registry = NodeDefRegistry::new();
+    core_nodes::register_core_nodes(&registry)?;
+    let mut plugins = PluginHost::new(registry.clone());
+    if Path::new(PLUGINS_DIR).is_dir() {
+        for err in plugins.load_dir(Path::new(PLUGINS_DIR))? {
+            eprintln!("{}", err);
+        }
+    }
+    let schema = serde_json::to_string_pretty(&node_def_catalog::schema())?;
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("node_catalog.json"), serialization::to_json(&registry.catalog())?)?;
+    fs::write(dir.join("node_catalog.schema.json"), schema)?;
+    Ok(())
+}
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let [flag, dir] = args.as_slice() {
+        if flag == "--export-node-catalog" {
+            if let Err(err) = export_node_catalog(Path::new(dir)) {
+                eprintln!("Could not export the node catalog: {}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
     println!("Hello, world!");
 }
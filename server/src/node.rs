@@ -1,14 +1,17 @@
+use crate::output_device::OutputDeviceQueue;
+use proton_shared::coercion::Coercion;
 use proton_shared::node_def::*;
-use proton_shared::node_def_registry::NodeDefRegistry;
 use proton_shared::node_value::*;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 /// Instance of an executable function as represented in a compute graph.
 /// Each Node has a type (a NodeDef) that defines what inputs to take, what outputs
 /// to provide, and how to execute. Each Node instance can attach to other Nodes to
 /// drive its inputs and outputs. Nodes are composed into a directed acyclic
 /// ComputeGraph that can then be evaluated in parallel.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node {
     pub id: u32,
     pub def_name: String,
@@ -17,107 +20,293 @@ pub struct Node {
     pub inputs: Vec<NodeInput>,
 }
 
-#[derive(Debug, EnumDiscriminants, PartialEq, Clone)]
+#[derive(Debug, EnumDiscriminants, PartialEq, Clone, Serialize, Deserialize)]
 pub enum NodeInput {
     Const(NodeValue),
     Wire(NodeOutputRef),
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct NodeOutputRef {
     pub from_node_id: u32,
     pub node_output_index: u8,
 }
 
-pub struct NodeWithRegistry<'a> {
-    node: &'a Node,
-    registry: &'a NodeDefRegistry,
-}
+/// Everything ComputeGraph::prepare works out ahead of time for a single Node, so
+/// that evaluating it on every execution of the graph is cheap.
+pub struct PreparedNode {
+    pub executor: Option<Box<dyn NodeExecutor>>,
 
-impl Node {
-    /// Most Node functionality can only be processed using a NodeDefRegistry,
-    /// which gives the Node access to the underlying implementation of its
-    /// node_def. This method allows the registry to be passed in once instead
-    /// of requiring it as an arg for every single function.
-    pub fn with_registry<'a>(&'a self, registry: &'a NodeDefRegistry) -> NodeWithRegistry<'a> {
-        NodeWithRegistry {
-            node: self,
-            registry: registry,
-        }
-    }
-}
+    /// The NodeDef the Node was prepared against. The Node keeps evaluating with it
+    /// even if the registry has changed since, until the graph is prepared again.
+    pub def: Arc<NodeDef>,
 
-impl<'a> NodeWithRegistry<'a> {
-    pub fn get_input_count(&self) -> usize {
-        self.registry.get_def(&self.node.def_name).inputs.len()
-    }
+    /// Which outputs are wired to another Node. Outputs that are not in use may be
+    /// skipped during evaluation.
+    pub active_outputs: Vec<bool>,
 
-    pub fn get_output_count(&self) -> usize {
-        self.registry.get_def(&self.node.def_name).outputs.len()
-    }
+    /// Conversion to apply to each input, for wires whose output type is not directly
+    /// accepted by the input they connect to.
+    pub input_coercions: Vec<Option<Coercion>>,
 
-    pub fn prepare(&self, enabled_outputs: &Vec<bool>) -> Option<Box<dyn NodeExecutor>> {
-        let def = self.registry.get_def(&self.node.def_name);
+    /// Name of the output device this Node drives, if any.
+    pub device_name: Option<String>,
+}
+
+impl Node {
+    /// Creates the Node's executor from its NodeDef, if it has one.
+    pub fn prepare(
+        &self,
+        def: Arc<NodeDef>,
+        active_outputs: Vec<bool>,
+        input_coercions: Vec<Option<Coercion>>,
+    ) -> PreparedNode {
         let maybe_executor = match &def.runner {
             NodeDefRunner::Executor(ctor) => Some(ctor()),
+            NodeDefRunner::ExecutorFactory(factory) => Some(factory.create_executor()),
             _ => None,
         };
-        if !maybe_executor.is_none() {
-            maybe_executor.as_ref().unwrap().prepare(enabled_outputs);
+        if let Some(executor) = &maybe_executor {
+            executor.prepare(&active_outputs);
         };
-        return maybe_executor;
+        PreparedNode {
+            executor: maybe_executor,
+            device_name: output_device_name(&def),
+            def,
+            active_outputs,
+            input_coercions,
+        }
     }
 
+    /// Runs the Node's NodeDef against its current input values. Outputs that are
+    /// not marked as active in `prepared` may be skipped, in which case they are None.
+    /// Output device Nodes do not run here; their input values are pushed onto
+    /// `device_queue` to be handled by the device's own thread.
     pub fn evaluate(
         &self,
         evaluated_outputs: &HashMap<NodeOutputRef, NodeValue>,
-        executor: &Option<Box<dyn NodeExecutor>>,
-    ) -> Vec<NodeValue> {
-        let mut input_vals = Vec::<&NodeValue>::with_capacity(self.node.inputs.len());
-        for input in &self.node.inputs {
+        prepared: &PreparedNode,
+        device_queue: Option<&OutputDeviceQueue>,
+        context: &ExecutionContext,
+    ) -> Vec<Option<NodeValue>> {
+        let def = &prepared.def;
+        let mut input_vals = Vec::<&NodeValue>::with_capacity(def.inputs.len());
+        for input in &self.inputs {
             let input_val = match input {
                 NodeInput::Const(val) => val,
-                NodeInput::Wire(output_ref) => evaluated_outputs.get(&output_ref).unwrap(),
+                NodeInput::Wire(output_ref) => evaluated_outputs.get(output_ref).unwrap(),
             };
             input_vals.push(input_val);
         }
+        // Inputs the Node leaves out take their NodeDef's defaults, by position. The
+        // registry only accepts defs where every input after an optional one without a
+        // default has none either, so stopping there drops no defaults.
+        for input_def in &def.inputs[input_vals.len().min(def.inputs.len())..] {
+            match &input_def.default {
+                Some(default) => input_vals.push(default),
+                None => break,
+            }
+        }
+        let coerced_vals: Vec<Option<NodeValue>> = input_vals
+            .iter()
+            .enumerate()
+            .map(|(i, val)| {
+                let coercion = prepared.input_coercions.get(i).copied().flatten();
+                coercion.map(|c| c(val))
+            })
+            .collect();
+        let input_vals: Vec<&NodeValue> = input_vals
+            .into_iter()
+            .zip(&coerced_vals)
+            .map(|(val, coerced)| coerced.as_ref().unwrap_or(val))
+            .collect();
 
-        let def = self.registry.get_def(&self.node.def_name);
         match &def.runner {
-            NodeDefRunner::Function(func) => func(input_vals),
-            NodeDefRunner::Executor(_) => executor.as_ref().unwrap().execute(input_vals),
+            NodeDefRunner::Function(func) => func(input_vals).into_iter().map(Some).collect(),
+            NodeDefRunner::PerOutputFunction(funcs) => funcs
+                .iter()
+                .zip(&prepared.active_outputs)
+                .map(|(func, active)| {
+                    if *active {
+                        Some(func(&input_vals))
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+            NodeDefRunner::Executor(_) | NodeDefRunner::ExecutorFactory(_) => prepared
+                .executor
+                .as_ref()
+                .unwrap()
+                .execute(input_vals, context)
+                .into_iter()
+                .map(Some)
+                .collect(),
             NodeDefRunner::OutputDevice(od) => {
-                (od.run)(input_vals);
+                let frame = input_vals.into_iter().cloned().collect();
+                device_queue.unwrap().push(od.run.clone(), frame);
                 vec![]
             }
         }
     }
 }
 
+/// Name of the output device driven by Nodes of the given NodeDef, if it is one.
+pub fn output_device_name(def: &NodeDef) -> Option<String> {
+    match &def.runner {
+        NodeDefRunner::OutputDevice(od) => Some(od.device.name.clone()),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use proton_shared::node_def_registry::NodeDefRegistry;
+    use proton_shared::serialization::*;
+
+    fn prepared(registry: &NodeDefRegistry, active_outputs: Vec<bool>) -> PreparedNode {
+        PreparedNode {
+            executor: None,
+            def: registry.get_def("test.def").unwrap(),
+            active_outputs,
+            input_coercions: vec![],
+            device_name: None,
+        }
+    }
 
     #[test]
     fn evaluates_function() {
         let registry = NodeDefRegistry::new();
         registry.register(
-            "test_def".to_owned(),
+            "test.def".to_owned(),
             node_def_from_fn!(|count_1: i64, count_2: i64| -> (i64) {
                 return vec![NodeValue::Count(count_1 + count_2)];
             }),
-        );
+        ).unwrap();
 
         let node = make_node! {
-            1: test_def[
+            1: test.def[
                 i64{1},
                 Wire{2, 0}
             ]
         };
         let map = map! {super::NodeOutputRef {from_node_id: 2, node_output_index: 0} => NodeValue::Count(2)};
-        let result = node.with_registry(&registry).evaluate(&map, &None);
+        let result = node.evaluate(
+            &map,
+            &prepared(&registry, vec![true]),
+            None,
+            &ExecutionContext::default(),
+        );
         assert_eq!(result.len(), 1);
-        assert_eq!(result[0], NodeValue::Count(3));
+        assert_eq!(result[0], Some(NodeValue::Count(3)));
+    }
+
+    #[test]
+    fn evaluates_defaults_of_left_out_inputs() {
+        let registry = NodeDefRegistry::new();
+        let mut def = node_def_from_fn!(|count_1: i64, count_2: i64| -> (i64) {
+            return vec![NodeValue::Count(count_1 + count_2)];
+        });
+        def.inputs[1].default = Some(NodeValue::Count(10));
+        registry.register("test.def".to_owned(), def).unwrap();
+
+        let node = make_node! {
+            1: test.def[i64{1}]
+        };
+        let result = node.evaluate(
+            &HashMap::new(),
+            &prepared(&registry, vec![true]),
+            None,
+            &ExecutionContext::default(),
+        );
+        assert_eq!(result, vec![Some(NodeValue::Count(11))]);
+    }
+
+    #[test]
+    fn evaluates_vector_function() {
+        let registry = NodeDefRegistry::new();
+        registry.register(
+            "test.def".to_owned(),
+            node_def_from_fn!(|a: NodeVector2, b: NodeVector2| -> (NodeVector2) {
+                return vec![NodeValue::Vector2D(a + b)];
+            }),
+        ).unwrap();
+
+        let node = make_node! {
+            1: test.def[Wire{2, 0}, Wire{2, 1}]
+        };
+        let map = map! {
+            super::NodeOutputRef {from_node_id: 2, node_output_index: 0} =>
+                NodeValue::Vector2D(NodeVector2::new(1.0, 2.0)),
+            super::NodeOutputRef {from_node_id: 2, node_output_index: 1} =>
+                NodeValue::Vector2D(NodeVector2::new(0.5, -1.0))
+        };
+        let result = node.evaluate(
+            &map,
+            &prepared(&registry, vec![true]),
+            None,
+            &ExecutionContext::default(),
+        );
+        assert_eq!(
+            result,
+            vec![Some(NodeValue::Vector2D(NodeVector2::new(1.5, 1.0)))]
+        );
+    }
+
+    #[test]
+    fn evaluates_only_active_outputs() {
+        let registry = NodeDefRegistry::new();
+        registry.register(
+            "test.def".to_owned(),
+            NodeDef {
+                desc: NodeDefBasicDescription {
+                    name: "Test Node".to_string(),
+                    description: "Test Description".to_string(),
+                },
+                inputs: node_input_def_from_args!(count: i64),
+                outputs: node_output_def_from_tuple!(i64, i64),
+                runner: NodeDefRunner::PerOutputFunction(vec![
+                    |inputs: &[&NodeValue]| match inputs[0] {
+                        NodeValue::Count(count) => NodeValue::Count(count + 1),
+                        _ => panic!("Invalid type for NodeValue input count"),
+                    },
+                    |_: &[&NodeValue]| panic!("Inactive output should not be computed"),
+                ]),
+            },
+        ).unwrap();
+
+        let node = make_node! {
+            1: test.def[i64{1}]
+        };
+        let result = node.evaluate(
+            &HashMap::new(),
+            &prepared(&registry, vec![true, false]),
+            None,
+            &ExecutionContext::default(),
+        );
+        assert_eq!(result, vec![Some(NodeValue::Count(2)), None]);
+    }
+
+    #[test]
+    fn serializes_nodes() {
+        let node = Node {
+            id: 3,
+            def_name: "core.list.length".to_string(),
+            inputs: vec![
+                NodeInput::Wire(NodeOutputRef {
+                    from_node_id: 1,
+                    node_output_index: 2,
+                }),
+                NodeInput::Const(NodeValue::Count(4)),
+            ],
+        };
+        let from_json: Node = from_json(&to_json(&node).unwrap()).unwrap();
+        let from_binary: Node = from_binary(&to_binary(&node).unwrap()).unwrap();
+        for decoded in &[from_json, from_binary] {
+            assert_eq!(decoded.id, node.id);
+            assert_eq!(decoded.def_name, node.def_name);
+            assert_eq!(decoded.inputs, node.inputs);
+        }
     }
 }
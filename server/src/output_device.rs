@@ -0,0 +1,207 @@
+use proton_shared::node_def::OutputDeviceFn;
+use proton_shared::node_value::NodeValue;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// What to do with a new frame when an output device's queue is already full.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputQueueOverflow {
+    /// Wait for the device to catch up, slowing down the compute graph.
+    Block,
+
+    /// Throw away the new frame so that the compute graph is never held up.
+    DropFrame,
+}
+
+/// Controls how many frames can be waiting for each output device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputQueueConfig {
+    /// Number of frames that can be buffered per device. A capacity of 0 means
+    /// every frame is handed directly to the device thread.
+    pub capacity: usize,
+    pub overflow: OutputQueueOverflow,
+}
+
+impl Default for OutputQueueConfig {
+    fn default() -> Self {
+        OutputQueueConfig {
+            capacity: 2,
+            overflow: OutputQueueOverflow::Block,
+        }
+    }
+}
+
+/// Input values for a single output device Node for a single execution of the graph.
+struct OutputFrame {
+    run: OutputDeviceFn,
+    values: Vec<NodeValue>,
+}
+
+/// Sending half of the queue that feeds frames to an output device thread.
+pub struct OutputDeviceQueue {
+    sender: SyncSender<OutputFrame>,
+    overflow: OutputQueueOverflow,
+}
+
+impl OutputDeviceQueue {
+    /// Queues up a frame of input values to be passed to `run` on the device thread.
+    /// Frames for a device whose thread has stopped are dropped.
+    pub fn push(&self, run: OutputDeviceFn, values: Vec<NodeValue>) {
+        let frame = OutputFrame { run, values };
+        // The device thread catches panics from the device, so it should only stop
+        // once every queue has been dropped. Either way, one device going away must
+        // not take the rest of the graph down with it.
+        match self.overflow {
+            OutputQueueOverflow::Block => {
+                let _ = self.sender.send(frame);
+            }
+            OutputQueueOverflow::DropFrame => {
+                let _ = self.sender.try_send(frame);
+            }
+        }
+    }
+}
+
+/// Owns one dedicated thread per output device. Each thread runs the device's
+/// frames in order, so slow device I/O overlaps with computing the next frame
+/// instead of delaying it. Dropping this waits for all queued frames to finish.
+pub struct OutputDeviceThreads {
+    queues: HashMap<String, OutputDeviceQueue>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl OutputDeviceThreads {
+    /// Spawns a thread for each of the named devices.
+    pub fn new<I: IntoIterator<Item = String>>(
+        device_names: I,
+        config: &OutputQueueConfig,
+    ) -> OutputDeviceThreads {
+        let mut queues = HashMap::new();
+        let mut handles = Vec::new();
+        for name in device_names {
+            if queues.contains_key(&name) {
+                continue;
+            }
+            let (sender, receiver) = sync_channel(config.capacity);
+            handles.push(
+                thread::Builder::new()
+                    .name(format!("output-device-{}", name))
+                    .spawn({
+                        let name = name.clone();
+                        move || run_device_thread(&name, receiver)
+                    })
+                    .unwrap(),
+            );
+            queues.insert(
+                name,
+                OutputDeviceQueue {
+                    sender,
+                    overflow: config.overflow,
+                },
+            );
+        }
+        OutputDeviceThreads { queues, handles }
+    }
+
+    pub fn get_queue(&self, device_name: &str) -> Option<&OutputDeviceQueue> {
+        self.queues.get(device_name)
+    }
+}
+
+impl Drop for OutputDeviceThreads {
+    fn drop(&mut self) {
+        // Closing the queues lets each thread exit once it has drained its frames.
+        self.queues.clear();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Runs a device's frames until its queue is closed. If the device panics, it is
+/// reported once and the device's remaining frames are dropped, since it may have been
+/// left in a broken state.
+fn run_device_thread(name: &str, receiver: Receiver<OutputFrame>) {
+    let mut failed = false;
+    for frame in receiver {
+        if failed {
+            continue;
+        }
+        let run = AssertUnwindSafe(|| (frame.run)(frame.values.iter().collect()));
+        if panic::catch_unwind(run).is_err() {
+            eprintln!(
+                "Output device {} panicked, and is not sent any more frames until the graph \
+                 is prepared again",
+                name
+            );
+            failed = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    #[test]
+    fn runs_frames_in_order_on_device_thread() {
+        static RECEIVED: Mutex<Vec<(i64, Option<String>)>> = Mutex::new(Vec::new());
+        fn record(inputs: Vec<&NodeValue>) {
+            if let NodeValue::Count(count) = inputs[0] {
+                let thread_name = thread::current().name().map(|name| name.to_string());
+                RECEIVED.lock().push((*count, thread_name));
+            }
+        }
+
+        let threads =
+            OutputDeviceThreads::new(vec!["strip".to_string()], &OutputQueueConfig::default());
+        let queue = threads.get_queue("strip").unwrap();
+        for i in 0..5 {
+            queue.push(Arc::new(record), vec![NodeValue::Count(i)]);
+        }
+        drop(threads);
+
+        let expected_thread = Some("output-device-strip".to_string());
+        assert_eq!(
+            *RECEIVED.lock(),
+            (0..5)
+                .map(|i| (i, expected_thread.clone()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn survives_devices_that_panic() {
+        static RUNS: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+        let config = OutputQueueConfig {
+            capacity: 0,
+            overflow: OutputQueueOverflow::Block,
+        };
+        let threads =
+            OutputDeviceThreads::new(vec!["broken".to_string(), "lamp".to_string()], &config);
+        let broken: OutputDeviceFn = Arc::new(|_: Vec<&NodeValue>| {
+            RUNS.lock().push("broken");
+            panic!("Device unplugged");
+        });
+        let lamp: OutputDeviceFn = Arc::new(|_: Vec<&NodeValue>| RUNS.lock().push("lamp"));
+        for _ in 0..3 {
+            threads
+                .get_queue("broken")
+                .unwrap()
+                .push(broken.clone(), vec![]);
+            threads
+                .get_queue("lamp")
+                .unwrap()
+                .push(lamp.clone(), vec![]);
+        }
+        drop(threads);
+
+        let runs = RUNS.lock();
+        assert_eq!(runs.iter().filter(|run| **run == "broken").count(), 1);
+        assert_eq!(runs.iter().filter(|run| **run == "lamp").count(), 3);
+    }
+}
@@ -0,0 +1,678 @@
+use super::script_node::{load_script_node, ScriptError};
+use super::wasm_node::{load_wasm_node, WasmLimits, WasmNodeError};
+use libloading::Library;
+use parking_lot::Mutex;
+use proton_shared::node_def::{
+    ExecutionContext, NodeDef, NodeDefOutputRunner, NodeDefRunner, NodeExecutor,
+    NodeExecutorFactory, OutputDeviceFn,
+};
+use proton_shared::node_def_registry::{NodeDefRegistry, NodeDefRegistryError, NodeDefVersion};
+use proton_shared::node_value::{NodeValue, NodeValueType};
+use proton_shared::plugin::{
+    PluginDeclaration, PluginRegistrar, PLUGIN_ABI_VERSION, PLUGIN_DECLARATION_SYMBOL,
+    RUSTC_VERSION, SHARED_BUILD_ID, SHARED_VERSION,
+};
+use std::env::consts::DLL_EXTENSION;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum PluginError {
+    /// The file is not a shared library that can be loaded on this platform.
+    Load {
+        path: PathBuf,
+        error: libloading::Error,
+    },
+
+    /// The library does not export a PluginDeclaration. See `declare_plugin!`.
+    MissingDeclaration(PathBuf),
+    IncompatibleAbi {
+        path: PathBuf,
+        abi_version: u32,
+    },
+
+    /// The plugin was built by a different compiler or against a different build of
+    /// proton_shared than the server.
+    IncompatibleBuild {
+        path: PathBuf,
+        rustc_version: String,
+        shared_version: String,
+        shared_build_id: String,
+    },
+
+    /// The plugin tried to register a def that can't be registered. None of its defs
+    /// are kept.
+    Registry {
+        path: PathBuf,
+        error: NodeDefRegistryError,
+    },
+
+    /// The file could not be read.
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+
+    /// The file is not a WebAssembly node that follows the ABI in wasm_node.
+    Wasm {
+        path: PathBuf,
+        error: WasmNodeError,
+    },
+
+    /// The file is not a script node as described in script_node.
+    Script {
+        path: PathBuf,
+        error: ScriptError,
+    },
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginError::Load { path, error } => {
+                write!(f, "Could not load plugin {}: {}", path.display(), error)
+            }
+            PluginError::MissingDeclaration(path) => write!(
+                f,
+                "{} is not a Proton plugin (no PROTON_PLUGIN_DECLARATION)",
+                path.display()
+            ),
+            PluginError::IncompatibleAbi { path, abi_version } => write!(
+                f,
+                "Plugin {} uses plugin ABI version {}, but the server uses {}",
+                path.display(),
+                abi_version,
+                PLUGIN_ABI_VERSION
+            ),
+            PluginError::IncompatibleBuild {
+                path,
+                rustc_version,
+                shared_version,
+                shared_build_id,
+            } => write!(
+                f,
+                "Plugin {} was built with {} and proton_shared {} ({}), but the server was built with {} and proton_shared {} ({})",
+                path.display(),
+                rustc_version,
+                shared_version,
+                shared_build_id,
+                RUSTC_VERSION,
+                SHARED_VERSION,
+                SHARED_BUILD_ID
+            ),
+            PluginError::Registry { path, error } => {
+                write!(f, "Plugin {} failed to register: {}", path.display(), error)
+            }
+            PluginError::Io { path, error } => {
+                write!(f, "Could not read plugin {}: {}", path.display(), error)
+            }
+            PluginError::Wasm { path, error } => {
+                write!(f, "Could not load plugin {}: {}", path.display(), error)
+            }
+            PluginError::Script { path, error } => {
+                write!(f, "Could not load plugin {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+/// A plugin whose defs are in the registry. Dropping it removes them again. Its library
+/// is unloaded once every def and executor that came from it has been dropped too.
+pub struct LoadedPlugin {
+    name: String,
+    path: PathBuf,
+    defs: Vec<(String, NodeDefVersion)>,
+    output_devices: Vec<String>,
+    registry: NodeDefRegistry,
+    library: Option<Arc<Library>>,
+}
+
+impl LoadedPlugin {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Defs the plugin registered, in the order it registered them.
+    pub fn defs(&self) -> &[(String, NodeDefVersion)] {
+        &self.defs
+    }
+
+    /// Names of the output devices that the plugin's defs send frames to.
+    pub fn output_devices(&self) -> &[String] {
+        &self.output_devices
+    }
+}
+
+impl Drop for LoadedPlugin {
+    fn drop(&mut self) {
+        for (name, version) in &self.defs {
+            self.registry.unregister(name, *version);
+        }
+    }
+}
+
+/// Registers defs into the server's registry on behalf of a plugin, keeping track of
+/// them so that they can be removed again.
+struct HostRegistrar<'a> {
+    plugin: &'a mut LoadedPlugin,
+}
+
+impl<'a> PluginRegistrar for HostRegistrar<'a> {
+    fn register_node_def(
+        &mut self,
+        id: &str,
+        version: NodeDefVersion,
+        def: NodeDef,
+    ) -> Result<(), NodeDefRegistryError> {
+        let output_device = match &def.runner {
+            NodeDefRunner::OutputDevice(runner) => Some(runner.device.name.clone()),
+            _ => None,
+        };
+        let def = match &self.plugin.library {
+            Some(library) => keep_loaded(def, library),
+            None => def,
+        };
+        self.plugin
+            .registry
+            .register_version(id.to_string(), version, def)?;
+        self.plugin.defs.push((id.to_string(), version));
+        if let Some(device) = output_device {
+            if !self.plugin.output_devices.contains(&device) {
+                self.plugin.output_devices.push(device);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Loads plugins into a registry, either native shared libraries, WebAssembly modules
+/// or scripts. Plugins stay loaded until the host is dropped, which unloads them in the
+/// opposite order to how they were loaded.
+///
+/// Defs and NodeExecutors from a native plugin run code inside its library, so each of
+/// them keeps the library loaded. A ComputeGraph prepared with a plugin's defs keeps
+/// running them after the host is dropped, until it is prepared again.
+pub struct PluginHost {
+    registry: NodeDefRegistry,
+    plugins: Vec<LoadedPlugin>,
+
+    /// Limits for WebAssembly plugins loaded from then on.
+    pub wasm_limits: WasmLimits,
+}
+
+impl PluginHost {
+    pub fn new(registry: NodeDefRegistry) -> PluginHost {
+        PluginHost {
+            registry,
+            plugins: vec![],
+            wasm_limits: WasmLimits::default(),
+        }
+    }
+
+    pub fn plugins(&self) -> &[LoadedPlugin] {
+        &self.plugins
+    }
+
+    /// Loads every shared library, `.wasm` module and `.rhai` script in a directory, in
+    /// order of file name. A plugin that fails to load does not stop the others from
+    /// loading, so this returns every plugin's error rather than just the first.
+    pub fn load_dir(&mut self, dir: &Path) -> io::Result<Vec<PluginError>> {
+        let mut paths = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<PathBuf>>>()?;
+        paths.sort();
+        let mut errors = vec![];
+        for path in paths {
+            let result = match path.extension().and_then(|ext| ext.to_str()) {
+                Some(DLL_EXTENSION) => self.load(&path).map(|_| ()),
+                Some("wasm") => self.load_wasm(&path).map(|_| ()),
+                Some("rhai") => self.load_script(&path).map(|_| ()),
+                _ => Ok(()),
+            };
+            errors.extend(result.err());
+        }
+        Ok(errors)
+    }
+
+    /// Loads a single WebAssembly module and registers the def it implements. The
+    /// module runs sandboxed within the host's wasm_limits, and its def must be in the
+    /// namespace named after its file, such as `lasers.strobe` for `lasers.wasm`.
+    pub fn load_wasm(&mut self, path: &Path) -> Result<&LoadedPlugin, PluginError> {
+        let bytes = fs::read(path).map_err(|error| PluginError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let namespace = path.file_stem().unwrap_or_default().to_string_lossy();
+        let (id, version, def) = load_wasm_node(&bytes, &namespace, self.wasm_limits.clone())
+            .map_err(|error| PluginError::Wasm {
+                path: path.to_path_buf(),
+                error,
+            })?;
+        self.register_def(path, id, version, def)
+    }
+
+    /// Loads a single script and registers the def it implements. The def picks up
+    /// changes to the script as they are saved.
+    pub fn load_script(&mut self, path: &Path) -> Result<&LoadedPlugin, PluginError> {
+        let (id, version, def) = load_script_node(path).map_err(|error| PluginError::Script {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        self.register_def(path, id, version, def)
+    }
+
+    /// Registers the single def of a plugin that isn't a native library.
+    fn register_def(
+        &mut self,
+        path: &Path,
+        id: String,
+        version: NodeDefVersion,
+        def: NodeDef,
+    ) -> Result<&LoadedPlugin, PluginError> {
+        let mut plugin = LoadedPlugin {
+            name: id.clone(),
+            path: path.to_path_buf(),
+            defs: vec![],
+            output_devices: vec![],
+            registry: self.registry.clone(),
+            library: None,
+        };
+        HostRegistrar {
+            plugin: &mut plugin,
+        }
+        .register_node_def(&id, version, def)
+        .map_err(|error| PluginError::Registry {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        self.plugins.push(plugin);
+        Ok(self.plugins.last().unwrap())
+    }
+
+    /// Loads a single plugin library and registers its defs.
+    pub fn load(&mut self, path: &Path) -> Result<&LoadedPlugin, PluginError> {
+        // Loading a library runs its initializers, and there is no way to check that
+        // a library is well-behaved before doing so. Only trusted plugins should be
+        // put in the plugins directory.
+        let library = unsafe { Library::new(path) }.map_err(|error| PluginError::Load {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let declaration = unsafe {
+            match library.get::<*const PluginDeclaration>(PLUGIN_DECLARATION_SYMBOL) {
+                Ok(symbol) => &**symbol,
+                Err(_) => return Err(PluginError::MissingDeclaration(path.to_path_buf())),
+            }
+        };
+        self.register_plugin(path, declaration, Some(library))
+    }
+
+    /// Checks the handshake of a plugin's declaration, and then lets it register its
+    /// defs. Takes ownership of the library the declaration came from, if any.
+    fn register_plugin(
+        &mut self,
+        path: &Path,
+        declaration: &PluginDeclaration,
+        library: Option<Library>,
+    ) -> Result<&LoadedPlugin, PluginError> {
+        if declaration.abi_version != PLUGIN_ABI_VERSION {
+            return Err(PluginError::IncompatibleAbi {
+                path: path.to_path_buf(),
+                abi_version: declaration.abi_version,
+            });
+        }
+        if declaration.rustc_version != RUSTC_VERSION
+            || declaration.shared_version != SHARED_VERSION
+            || declaration.shared_build_id != SHARED_BUILD_ID
+        {
+            return Err(PluginError::IncompatibleBuild {
+                path: path.to_path_buf(),
+                rustc_version: declaration.rustc_version.to_string(),
+                shared_version: declaration.shared_version.to_string(),
+                shared_build_id: declaration.shared_build_id.to_string(),
+            });
+        }
+
+        let mut plugin = LoadedPlugin {
+            name: declaration.name.to_string(),
+            path: path.to_path_buf(),
+            defs: vec![],
+            output_devices: vec![],
+            registry: self.registry.clone(),
+            library: library.map(Arc::new),
+        };
+        let result = (declaration.register)(&mut HostRegistrar {
+            plugin: &mut plugin,
+        });
+        if let Err(error) = result {
+            // Dropping the plugin removes whatever defs it did manage to register.
+            return Err(PluginError::Registry {
+                path: path.to_path_buf(),
+                error,
+            });
+        }
+        self.plugins.push(plugin);
+        Ok(self.plugins.last().unwrap())
+    }
+}
+
+/// Rewraps a def from a native plugin so that its runner, and every executor and output
+/// device frame that comes from it, holds on to the plugin's library.
+fn keep_loaded(def: NodeDef, library: &Arc<Library>) -> NodeDef {
+    let runner = match def.runner {
+        NodeDefRunner::OutputDevice(runner) => {
+            let run = PluginOutputRun {
+                run: runner.run,
+                _library: library.clone(),
+            };
+            NodeDefRunner::OutputDevice(NodeDefOutputRunner {
+                run: Arc::new(move |inputs: Vec<&NodeValue>| (run.run)(inputs)),
+                device: runner.device,
+            })
+        }
+        runner => NodeDefRunner::ExecutorFactory(Arc::new(PluginExecutorFactory {
+            runner,
+            output_types: def
+                .outputs
+                .iter()
+                .map(|output| output.output_type)
+                .collect(),
+            _library: library.clone(),
+        })),
+    };
+    NodeDef { runner, ..def }
+}
+
+/// Output device function of a native plugin.
+struct PluginOutputRun {
+    run: OutputDeviceFn,
+
+    /// Declared last so that it is dropped last, once nothing points into it.
+    _library: Arc<Library>,
+}
+
+/// Creates executors for any other kind of runner from a native plugin.
+struct PluginExecutorFactory {
+    runner: NodeDefRunner,
+    output_types: Vec<NodeValueType>,
+
+    /// Declared last so that it is dropped last, once nothing points into it.
+    _library: Arc<Library>,
+}
+
+struct PluginExecutor {
+    runner: PluginExecutorRunner,
+
+    /// Declared last so that it is dropped last, once nothing points into it.
+    _library: Arc<Library>,
+}
+
+enum PluginExecutorRunner {
+    Function(fn(Vec<&NodeValue>) -> Vec<NodeValue>),
+
+    /// Outputs that no other Node is wired to are not computed, and are given the
+    /// default value of their type instead, if it has one.
+    PerOutputFunction {
+        funcs: Vec<fn(&[&NodeValue]) -> NodeValue>,
+        defaults: Vec<Option<NodeValue>>,
+        active_outputs: Mutex<Vec<bool>>,
+    },
+    Executor(Box<dyn NodeExecutor>),
+}
+
+impl NodeExecutorFactory for PluginExecutorFactory {
+    fn create_executor(&self) -> Box<dyn NodeExecutor> {
+        let runner = match &self.runner {
+            NodeDefRunner::Function(func) => PluginExecutorRunner::Function(*func),
+            NodeDefRunner::PerOutputFunction(funcs) => PluginExecutorRunner::PerOutputFunction {
+                funcs: funcs.clone(),
+                defaults: self
+                    .output_types
+                    .iter()
+                    .map(|value_type| NodeValue::default_for(*value_type))
+                    .collect(),
+                active_outputs: Mutex::new(vec![true; funcs.len()]),
+            },
+            NodeDefRunner::Executor(ctor) => PluginExecutorRunner::Executor(ctor()),
+            NodeDefRunner::ExecutorFactory(factory) => {
+                PluginExecutorRunner::Executor(factory.create_executor())
+            }
+            NodeDefRunner::OutputDevice(_) => unreachable!("Output devices are not wrapped"),
+        };
+        Box::new(PluginExecutor {
+            runner,
+            _library: self._library.clone(),
+        })
+    }
+}
+
+impl NodeExecutor for PluginExecutor {
+    fn prepare(&self, enabled_outputs: &[bool]) {
+        match &self.runner {
+            PluginExecutorRunner::Function(_) => {}
+            PluginExecutorRunner::PerOutputFunction { active_outputs, .. } => {
+                *active_outputs.lock() = enabled_outputs.to_vec()
+            }
+            PluginExecutorRunner::Executor(executor) => executor.prepare(enabled_outputs),
+        }
+    }
+
+    fn execute(&self, inputs: Vec<&NodeValue>, context: &ExecutionContext) -> Vec<NodeValue> {
+        match &self.runner {
+            PluginExecutorRunner::Function(func) => func(inputs),
+            PluginExecutorRunner::PerOutputFunction {
+                funcs,
+                defaults,
+                active_outputs,
+            } => funcs
+                .iter()
+                .zip(defaults)
+                .zip(active_outputs.lock().iter())
+                .map(|((func, default), active)| match default {
+                    Some(default) if !*active => default.clone(),
+                    _ => func(&inputs),
+                })
+                .collect(),
+            PluginExecutorRunner::Executor(executor) => executor.execute(inputs, context),
+        }
+    }
+}
+
+impl Drop for PluginHost {
+    fn drop(&mut self) {
+        while let Some(plugin) = self.plugins.pop() {
+            drop(plugin);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_shared::node_def::{
+        NodeDefBasicDescription, NodeDefOutputRunner, NodeDefRunner, NodeOutputDef, OutputDevice,
+    };
+
+    fn def(runner: NodeDefRunner) -> NodeDef {
+        NodeDef {
+            desc: NodeDefBasicDescription {
+                name: "Test".to_string(),
+                description: String::new(),
+            },
+            inputs: vec![],
+            outputs: vec![],
+            runner,
+        }
+    }
+
+    fn register_lasers(registrar: &mut dyn PluginRegistrar) -> Result<(), NodeDefRegistryError> {
+        registrar.register_node_def(
+            "acme.lasers.scan",
+            NodeDefVersion::INITIAL,
+            def(NodeDefRunner::Function(|_| vec![])),
+        )?;
+        registrar.register_node_def(
+            "acme.lasers.projector",
+            NodeDefVersion::INITIAL,
+            def(NodeDefRunner::OutputDevice(NodeDefOutputRunner {
+                run: Arc::new(|_: Vec<&NodeValue>| {}),
+                device: OutputDevice {
+                    name: "projector".to_string(),
+                },
+            })),
+        )
+    }
+
+    fn declaration(
+        register: fn(&mut dyn PluginRegistrar) -> Result<(), NodeDefRegistryError>,
+    ) -> PluginDeclaration {
+        PluginDeclaration {
+            abi_version: PLUGIN_ABI_VERSION,
+            rustc_version: RUSTC_VERSION,
+            shared_version: SHARED_VERSION,
+            shared_build_id: SHARED_BUILD_ID,
+            name: "acme_lasers",
+            register,
+        }
+    }
+
+    #[test]
+    fn registers_defs_until_unloaded() {
+        let registry = NodeDefRegistry::new();
+        let mut host = PluginHost::new(registry.clone());
+        let plugin = host
+            .register_plugin(Path::new("lasers"), &declaration(register_lasers), None)
+            .unwrap();
+        assert_eq!(plugin.name(), "acme_lasers");
+        assert_eq!(plugin.defs().len(), 2);
+        assert_eq!(plugin.output_devices(), &["projector".to_string()]);
+        assert!(registry.get_def("acme.lasers.scan").is_ok());
+
+        drop(host);
+        assert!(registry.get_def("acme.lasers.scan").is_err());
+        assert!(registry.get_def("acme.lasers.projector").is_err());
+    }
+
+    #[test]
+    fn rejects_incompatible_plugins() {
+        let registry = NodeDefRegistry::new();
+        let mut host = PluginHost::new(registry.clone());
+        let path = Path::new("lasers");
+
+        let mut old_abi = declaration(register_lasers);
+        old_abi.abi_version = 0;
+        match host.register_plugin(path, &old_abi, None) {
+            Err(PluginError::IncompatibleAbi { abi_version: 0, .. }) => {}
+            result => panic!("Expected an ABI error, got {:?}", result.err()),
+        }
+
+        let mut other_compiler = declaration(register_lasers);
+        other_compiler.rustc_version = "rustc 1.0.0";
+        match host.register_plugin(path, &other_compiler, None) {
+            Err(PluginError::IncompatibleBuild { .. }) => {}
+            result => panic!("Expected a build error, got {:?}", result.err()),
+        }
+
+        let mut changed_shared = declaration(register_lasers);
+        changed_shared.shared_build_id = "x86_64-unknown-linux-gnu-0000000000000000";
+        match host.register_plugin(path, &changed_shared, None) {
+            Err(PluginError::IncompatibleBuild { .. }) => {}
+            result => panic!("Expected a build error, got {:?}", result.err()),
+        }
+
+        fn register_twice(registrar: &mut dyn PluginRegistrar) -> Result<(), NodeDefRegistryError> {
+            register_lasers(registrar)?;
+            register_lasers(registrar)
+        }
+        match host.register_plugin(path, &declaration(register_twice), None) {
+            Err(PluginError::Registry {
+                error: NodeDefRegistryError::AlreadyRegistered { .. },
+                ..
+            }) => {}
+            result => panic!("Expected a registry error, got {:?}", result.err()),
+        }
+        assert!(host.plugins().is_empty());
+        assert!(registry.get_def("acme.lasers.scan").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_library_loaded_while_defs_are_in_use() {
+        fn register_split(registrar: &mut dyn PluginRegistrar) -> Result<(), NodeDefRegistryError> {
+            let mut split = def(NodeDefRunner::PerOutputFunction(vec![
+                |_: &[&NodeValue]| NodeValue::Count(1),
+                |_: &[&NodeValue]| NodeValue::Count(2),
+            ]));
+            split.outputs = node_output_def_from_tuple!(i64, i64);
+            registrar.register_node_def("acme.lasers.split", NodeDefVersion::INITIAL, split)?;
+            register_lasers(registrar)
+        }
+
+        let registry = NodeDefRegistry::new();
+        let mut host = PluginHost::new(registry.clone());
+        let library = Library::from(libloading::os::unix::Library::this());
+        host.register_plugin(
+            Path::new("lasers"),
+            &declaration(register_split),
+            Some(library),
+        )
+        .unwrap();
+        let split = registry.get_def("acme.lasers.split").unwrap();
+        let projector = registry.get_def("acme.lasers.projector").unwrap();
+        drop(host);
+        assert!(registry.get_def("acme.lasers.split").is_err());
+
+        let executor = match &split.runner {
+            NodeDefRunner::ExecutorFactory(factory) => factory.create_executor(),
+            _ => panic!("Expected the runner to be wrapped"),
+        };
+        executor.prepare(&[false, true]);
+        assert_eq!(
+            executor.execute(vec![], &ExecutionContext::default()),
+            vec![NodeValue::Count(0), NodeValue::Count(2)]
+        );
+        match &projector.runner {
+            NodeDefRunner::OutputDevice(runner) => (runner.run)(vec![]),
+            _ => panic!("Expected an output device"),
+        }
+    }
+
+    #[test]
+    fn loads_only_plugins_from_dir() {
+        let dir = std::env::temp_dir().join(format!("proton-plugins-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("notes.txt"), "not a plugin").unwrap();
+        fs::write(
+            dir.join(format!("broken.{}", DLL_EXTENSION)),
+            "not a library",
+        )
+        .unwrap();
+        fs::write(dir.join("broken.wasm"), "not a module").unwrap();
+        fs::write(dir.join("broken.rhai"), "fn describe(").unwrap();
+
+        let mut host = PluginHost::new(NodeDefRegistry::new());
+        let errors = host.load_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(errors.len(), 3);
+        for error in &errors {
+            match error {
+                PluginError::Load { path, .. } => {
+                    assert!(path.ends_with(format!("broken.{}", DLL_EXTENSION)))
+                }
+                PluginError::Wasm { path, .. } => assert!(path.ends_with("broken.wasm")),
+                PluginError::Script { path, .. } => assert!(path.ends_with("broken.rhai")),
+                error => panic!("Expected a load error, got {:?}", error),
+            }
+        }
+        assert!(host.plugins().is_empty());
+    }
+}
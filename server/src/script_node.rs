@@ -0,0 +1,680 @@
+//! NodeDefs written as Rhai scripts, so that artists can write quick logic nodes
+//! without a Rust toolchain.
+//!
+//! A script declares its def with a `describe` function, and runs with a `run` function
+//! that takes the Node's inputs and information about the current frame, and returns
+//! its outputs. `this` in `run` is the Node's own state, which starts as whatever an
+//! optional `init` function returns and is kept from one frame to the next:
+//!
+//! ```text
+//! fn describe() {
+//!     #{
+//!         id: "artist.counter",
+//!         name: "Counter",
+//!         description: "Counts the frames where the input is on",
+//!         inputs: [#{ name: "on", type: "Toggle", default_value: true }],
+//!         outputs: [#{ name: "count", type: "Count" }],
+//!     }
+//! }
+//!
+//! fn init() { #{ count: 0 } }
+//!
+//! fn run(inputs, frame) {
+//!     if inputs[0] { this.count += 1; }
+//!     [this.count]
+//! }
+//! ```
+//!
+//! `frame` has the `elapsed` time in seconds, and the `beat` and `beat_phase` of the
+//! graph's clock at that time. Magnitudes are floats, Counts are integers, Triggers and
+//! Toggles are bools, Text is a string, Colors are maps of `r`, `g`, `b` and `a`
+//! integers, and Vector2Ds are maps of `x` and `y` floats. Other types can't be used.
+//!
+//! Scripts are reloaded in the background when their file changes, and Nodes keep their
+//! state across the reload. A reload that fails to compile, or that changes anything
+//! the def was registered with (its id, version, name, description, inputs or outputs),
+//! is ignored until the file changes again. A frame where the script fails or runs for
+//! too long repeats the Node's last good outputs (or the defaults of its output types).
+//! Only the first of a run of failed frames is reported, so that a broken script doesn't
+//! flood the log every frame.
+
+use parking_lot::{Mutex, RwLock};
+use proton_shared::node_def::{
+    ExecutionContext, NodeDef, NodeDefBasicDescription, NodeDefRunner, NodeExecutor,
+    NodeExecutorFactory, NodeInputDef, NodeOutputDef,
+};
+use proton_shared::node_def_registry::NodeDefVersion;
+use proton_shared::node_value::{NodeColor, NodeMagnitude, NodeValue, NodeValueType, NodeVector2};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, ParseError, Scope, AST};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often each script's file is checked for changes.
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Most operations a script may run in a single call, which keeps a script from
+/// hanging the server.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(io::Error),
+    Parse(ParseError),
+
+    /// The script failed while running, including running for too long.
+    Runtime(Box<EvalAltResult>),
+
+    /// `describe` returned something that doesn't describe a def.
+    InvalidDescription(String),
+    UnsupportedType(NodeValueType),
+
+    /// A value from the script can't be converted to the type it is meant to have.
+    WrongValue {
+        value: String,
+        expected: NodeValueType,
+    },
+    WrongOutputCount {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Io(err) => write!(f, "Could not read script: {}", err),
+            ScriptError::Parse(err) => write!(f, "Invalid script: {}", err),
+            ScriptError::Runtime(err) => write!(f, "Script failed: {}", err),
+            ScriptError::InvalidDescription(err) => {
+                write!(f, "Invalid description from script: {}", err)
+            }
+            ScriptError::UnsupportedType(value_type) => {
+                write!(f, "Scripts can't use values of type {:?}", value_type)
+            }
+            ScriptError::WrongValue { value, expected } => {
+                write!(f, "Script value {} should be of type {:?}", value, expected)
+            }
+            ScriptError::WrongOutputCount { expected, found } => write!(
+                f,
+                "Script returned {} outputs instead of {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<io::Error> for ScriptError {
+    fn from(err: io::Error) -> ScriptError {
+        ScriptError::Io(err)
+    }
+}
+
+impl From<ParseError> for ScriptError {
+    fn from(err: ParseError) -> ScriptError {
+        ScriptError::Parse(err)
+    }
+}
+
+impl From<Box<EvalAltResult>> for ScriptError {
+    fn from(err: Box<EvalAltResult>) -> ScriptError {
+        ScriptError::Runtime(err)
+    }
+}
+
+/// What a script's `describe` function returns.
+#[derive(Deserialize)]
+struct ScriptDescription {
+    id: String,
+    #[serde(default = "initial_version")]
+    version: NodeDefVersion,
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    inputs: Vec<ScriptPort>,
+    outputs: Vec<ScriptPort>,
+}
+
+#[derive(Deserialize)]
+struct ScriptPort {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(rename = "type")]
+    value_type: NodeValueType,
+
+    /// Named so because `default` is a keyword in Rhai.
+    default_value: Option<Dynamic>,
+}
+
+fn initial_version() -> NodeDefVersion {
+    NodeDefVersion::INITIAL
+}
+
+/// A script compiled from one version of its file.
+struct CompiledScript {
+    ast: AST,
+    id: String,
+    version: NodeDefVersion,
+    desc: NodeDefBasicDescription,
+    inputs: Vec<NodeInputDef>,
+    outputs: Vec<NodeOutputDef>,
+    has_init: bool,
+}
+
+/// A script file, along with its latest compiled version that still matches the def it
+/// was registered as. A thread started by `watch` keeps it up to date.
+struct ScriptSource {
+    path: PathBuf,
+    engine: Engine,
+    script: RwLock<Arc<CompiledScript>>,
+}
+
+struct ScriptNodeFactory {
+    source: Arc<ScriptSource>,
+}
+
+struct ScriptNodeExecutor {
+    source: Arc<ScriptSource>,
+
+    /// None until the first run calls the script's `init`.
+    state: Mutex<Option<Dynamic>>,
+    last_outputs: Mutex<Vec<NodeValue>>,
+
+    /// Whether the last run failed, in which case further failures aren't reported.
+    failing: AtomicBool,
+}
+
+fn script_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(32)
+        .set_max_string_size(1024 * 1024)
+        .set_max_array_size(64 * 1024)
+        .set_max_map_size(64 * 1024);
+    engine
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn check_type(value_type: NodeValueType) -> Result<NodeValueType, ScriptError> {
+    match value_type {
+        NodeValueType::Trigger
+        | NodeValueType::Toggle
+        | NodeValueType::Count
+        | NodeValueType::ConstrainedMagnitude
+        | NodeValueType::UnconstrainedMagnitude
+        | NodeValueType::Text
+        | NodeValueType::Color
+        | NodeValueType::Vector2D => Ok(value_type),
+        _ => Err(ScriptError::UnsupportedType(value_type)),
+    }
+}
+
+/// Converts a NodeValue of one of the types scripts can use to a script value.
+fn to_dynamic(value: &NodeValue) -> Dynamic {
+    match value {
+        NodeValue::Trigger(on) | NodeValue::Toggle(on) => Dynamic::from(*on),
+        NodeValue::Count(count) => Dynamic::from(*count),
+        NodeValue::ConstrainedMagnitude(bits) => {
+            Dynamic::from(NodeMagnitude::from_bits(*bits).to_f64())
+        }
+        NodeValue::UnconstrainedMagnitude(magnitude) => Dynamic::from(*magnitude),
+        NodeValue::Text(text) => Dynamic::from(text.to_string()),
+        NodeValue::Color(color) => {
+            let mut map = Map::new();
+            map.insert("r".into(), Dynamic::from(color.r as i64));
+            map.insert("g".into(), Dynamic::from(color.g as i64));
+            map.insert("b".into(), Dynamic::from(color.b as i64));
+            map.insert("a".into(), Dynamic::from(color.a as i64));
+            Dynamic::from(map)
+        }
+        NodeValue::Vector2D(vector) => {
+            let mut map = Map::new();
+            map.insert("x".into(), Dynamic::from(vector.x));
+            map.insert("y".into(), Dynamic::from(vector.y));
+            Dynamic::from(map)
+        }
+        _ => Dynamic::UNIT,
+    }
+}
+
+/// Converts a script value to a NodeValue of the given type. Integers are accepted
+/// where floats are expected.
+fn from_dynamic(value: &Dynamic, value_type: NodeValueType) -> Result<NodeValue, ScriptError> {
+    let float = |value: &Dynamic| {
+        value
+            .as_float()
+            .ok()
+            .or_else(|| value.as_int().ok().map(|int| int as f64))
+    };
+    let field = |map: &Map, name: &str| map.get(name).cloned();
+    let converted = match value_type {
+        NodeValueType::Trigger => value.as_bool().ok().map(NodeValue::Trigger),
+        NodeValueType::Toggle => value.as_bool().ok().map(NodeValue::Toggle),
+        NodeValueType::Count => value.as_int().ok().map(NodeValue::Count),
+        NodeValueType::ConstrainedMagnitude => float(value).map(|magnitude| {
+            NodeValue::ConstrainedMagnitude(NodeMagnitude::from_f64(magnitude).to_bits())
+        }),
+        NodeValueType::UnconstrainedMagnitude => {
+            float(value).map(NodeValue::UnconstrainedMagnitude)
+        }
+        NodeValueType::Text => value
+            .clone()
+            .into_string()
+            .ok()
+            .map(|text| NodeValue::Text(Arc::new(text))),
+        NodeValueType::Color => value.read_lock::<Map>().and_then(|map| {
+            let channel = |name| {
+                field(&map, name)
+                    .and_then(|channel| channel.as_int().ok())
+                    .map(|channel| channel.clamp(0, u16::MAX as i64) as u16)
+            };
+            Some(NodeValue::Color(NodeColor::new(
+                channel("r")?,
+                channel("g")?,
+                channel("b")?,
+                channel("a")?,
+            )))
+        }),
+        NodeValueType::Vector2D => value.read_lock::<Map>().and_then(|map| {
+            Some(NodeValue::Vector2D(NodeVector2 {
+                x: float(&field(&map, "x")?)?,
+                y: float(&field(&map, "y")?)?,
+            }))
+        }),
+        _ => None,
+    };
+    converted.ok_or_else(|| ScriptError::WrongValue {
+        value: value.to_string(),
+        expected: value_type,
+    })
+}
+
+impl CompiledScript {
+    fn compile(engine: &Engine, path: &Path) -> Result<CompiledScript, ScriptError> {
+        let ast = engine.compile(fs::read_to_string(path)?)?;
+        let description: Dynamic = engine.call_fn(&mut Scope::new(), &ast, "describe", ())?;
+        let description: ScriptDescription = rhai::serde::from_dynamic(&description)
+            .map_err(|err| ScriptError::InvalidDescription(err.to_string()))?;
+        let describe = |port: &ScriptPort| NodeDefBasicDescription {
+            name: port.name.clone(),
+            description: port.description.clone(),
+        };
+
+        let mut inputs = vec![];
+        for port in &description.inputs {
+            let value_type = check_type(port.value_type)?;
+            let default = match &port.default_value {
+                Some(default) => Some(from_dynamic(default, value_type)?),
+                None => None,
+            };
+            inputs.push(NodeInputDef {
+                desc: describe(port),
+                allowed_types: vec![value_type],
+                required: default.is_none(),
+                enum_def: None,
+                composite_type: None,
+                default,
+            });
+        }
+        let mut outputs = vec![];
+        for port in &description.outputs {
+            outputs.push(NodeOutputDef {
+                desc: describe(port),
+                output_type: check_type(port.value_type)?,
+                enum_def: None,
+                composite_type: None,
+            });
+        }
+        let has_init = ast
+            .iter_functions()
+            .any(|function| function.name == "init" && function.params.is_empty());
+        Ok(CompiledScript {
+            ast,
+            id: description.id,
+            version: description.version,
+            desc: NodeDefBasicDescription {
+                name: description.name,
+                description: description.description,
+            },
+            inputs,
+            outputs,
+            has_init,
+        })
+    }
+}
+
+impl CompiledScript {
+    /// True if this version of the script can replace the other one without changing
+    /// the def that they were registered as.
+    fn matches_def_of(&self, other: &CompiledScript) -> bool {
+        self.id == other.id
+            && self.version == other.version
+            && self.desc == other.desc
+            && self.inputs == other.inputs
+            && self.outputs == other.outputs
+    }
+}
+
+impl ScriptSource {
+    /// The latest compiled version of the script.
+    fn current(&self) -> Arc<CompiledScript> {
+        self.script.read().clone()
+    }
+
+    /// Compiles the script again, without holding up Nodes that are running the
+    /// current version in the meantime.
+    fn reload(&self) {
+        let compiled = CompiledScript::compile(&self.engine, &self.path);
+        let mut script = self.script.write();
+        match compiled {
+            Ok(reloaded) if reloaded.matches_def_of(&script) => {
+                *script = Arc::new(reloaded);
+            }
+            Ok(_) => eprintln!(
+                "Ignoring changes to script {}: its id, version, name, description, inputs \
+                 and outputs can't change while the server is running",
+                self.path.display()
+            ),
+            Err(err) => eprintln!(
+                "Ignoring changes to script {}: {}",
+                self.path.display(),
+                err
+            ),
+        }
+    }
+}
+
+/// Compiles a script and calls its `describe`, returning the def it implements along
+/// with the id and version to register it under.
+pub fn load_script_node(path: &Path) -> Result<(String, NodeDefVersion, NodeDef), ScriptError> {
+    let engine = script_engine();
+    let modified = modified_time(path);
+    let script = CompiledScript::compile(&engine, path)?;
+    let (id, version) = (script.id.clone(), script.version);
+    let source = Arc::new(ScriptSource {
+        path: path.to_path_buf(),
+        engine,
+        script: RwLock::new(Arc::new(script)),
+    });
+    watch(Arc::downgrade(&source), path, modified);
+    let script = source.current();
+    let def = NodeDef {
+        desc: script.desc.clone(),
+        inputs: script.inputs.clone(),
+        outputs: script.outputs.clone(),
+        runner: NodeDefRunner::ExecutorFactory(Arc::new(ScriptNodeFactory { source })),
+    };
+    Ok((id, version, def))
+}
+
+/// Starts a thread that reloads a script whenever its file changes, until the def it
+/// belongs to and every executor created from it have been dropped.
+fn watch(source: Weak<ScriptSource>, path: &Path, mut modified: Option<SystemTime>) {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    thread::Builder::new()
+        .name(format!("script-{}", name))
+        .spawn(move || loop {
+            thread::sleep(RELOAD_CHECK_INTERVAL);
+            let source = match source.upgrade() {
+                Some(source) => source,
+                None => return,
+            };
+            let latest = modified_time(&source.path);
+            if latest != modified {
+                modified = latest;
+                source.reload();
+            }
+        })
+        .unwrap();
+}
+
+impl NodeExecutorFactory for ScriptNodeFactory {
+    fn create_executor(&self) -> Box<dyn NodeExecutor> {
+        let defaults = self
+            .source
+            .script
+            .read()
+            .outputs
+            .iter()
+            .map(|output| NodeValue::default_for(output.output_type).unwrap())
+            .collect();
+        Box::new(ScriptNodeExecutor {
+            source: self.source.clone(),
+            state: Mutex::new(None),
+            last_outputs: Mutex::new(defaults),
+            failing: AtomicBool::new(false),
+        })
+    }
+}
+
+impl ScriptNodeExecutor {
+    fn try_run(
+        &self,
+        script: &CompiledScript,
+        inputs: &[&NodeValue],
+        context: &ExecutionContext,
+    ) -> Result<Vec<NodeValue>, ScriptError> {
+        let engine = &self.source.engine;
+        let mut state = self.state.lock();
+        if state.is_none() {
+            *state = Some(if script.has_init {
+                engine.call_fn(&mut Scope::new(), &script.ast, "init", ())?
+            } else {
+                Dynamic::from(Map::new())
+            });
+        }
+
+        let inputs: rhai::Array = inputs.iter().map(|input| to_dynamic(input)).collect();
+        let mut frame = Map::new();
+        let elapsed = context.elapsed;
+        frame.insert("elapsed".into(), Dynamic::from(elapsed.as_secs_f64()));
+        frame.insert("beat".into(), Dynamic::from(context.tempo.beat_at(elapsed)));
+        frame.insert(
+            "beat_phase".into(),
+            Dynamic::from(context.tempo.beat_phase_at(elapsed)),
+        );
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(state.as_mut().unwrap());
+        let outputs: rhai::Array = engine.call_fn_with_options(
+            options,
+            &mut Scope::new(),
+            &script.ast,
+            "run",
+            (inputs, frame),
+        )?;
+
+        if outputs.len() != script.outputs.len() {
+            return Err(ScriptError::WrongOutputCount {
+                expected: script.outputs.len(),
+                found: outputs.len(),
+            });
+        }
+        outputs
+            .iter()
+            .zip(&script.outputs)
+            .map(|(value, output)| from_dynamic(value, output.output_type))
+            .collect()
+    }
+}
+
+impl NodeExecutor for ScriptNodeExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, context: &ExecutionContext) -> Vec<NodeValue> {
+        let script = self.source.current();
+        let mut last_outputs = self.last_outputs.lock();
+        match self.try_run(&script, &inputs, context) {
+            Ok(outputs) => {
+                *last_outputs = outputs;
+                self.failing.store(false, Ordering::Relaxed);
+            }
+            Err(err) => {
+                if !self.failing.swap(true, Ordering::Relaxed) {
+                    eprintln!(
+                        "Script node {} failed, and will repeat its last good outputs until \
+                         it recovers: {}",
+                        script.id, err
+                    );
+                }
+            }
+        }
+        last_outputs.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    const COUNTER: &str = r#"
+        fn describe() {
+            #{
+                id: "test.counter",
+                version: "0.2.0",
+                name: "Counter",
+                inputs: [#{ name: "step", type: "Count", default_value: 1 }],
+                outputs: [
+                    #{ name: "count", type: "Count" },
+                    #{ name: "half", type: "ConstrainedMagnitude" },
+                ],
+            }
+        }
+
+        fn init() { #{ count: 0 } }
+
+        fn run(inputs, frame) {
+            this.count += inputs[0];
+            [this.count, 0.5]
+        }
+    "#;
+
+    fn script_file(name: &str, script: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.rhai", name, std::process::id()));
+        fs::write(&path, script).unwrap();
+        path
+    }
+
+    fn executor(def: NodeDef) -> Box<dyn NodeExecutor> {
+        match def.runner {
+            NodeDefRunner::ExecutorFactory(factory) => factory.create_executor(),
+            _ => panic!("Script nodes should have executor factories"),
+        }
+    }
+
+    fn load_script_node_from(script: &str) -> NodeDef {
+        let path = script_file("script", script);
+        let (_, _, def) = load_script_node(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        def
+    }
+
+    fn run(executor: &dyn NodeExecutor, step: i64) -> Vec<NodeValue> {
+        executor.execute(vec![&NodeValue::Count(step)], &ExecutionContext::default())
+    }
+
+    #[test]
+    fn describes_and_runs_scripts() {
+        let path = script_file("counter", COUNTER);
+        let (id, version, def) = load_script_node(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(id, "test.counter");
+        assert_eq!(version, NodeDefVersion::new(0, 2, 0));
+        assert_eq!(def.inputs[0].default, Some(NodeValue::Count(1)));
+        assert!(!def.inputs[0].required);
+        assert_eq!(
+            def.outputs[1].output_type,
+            NodeValueType::ConstrainedMagnitude
+        );
+
+        // Each Node keeps its own state.
+        let half = NodeValue::ConstrainedMagnitude(NodeMagnitude::from_f64(0.5).to_bits());
+        let first = executor(load_script_node_from(COUNTER));
+        let second = executor(load_script_node_from(COUNTER));
+        assert_eq!(run(&*first, 2), vec![NodeValue::Count(2), half.clone()]);
+        assert_eq!(run(&*first, 3), vec![NodeValue::Count(5), half.clone()]);
+        assert_eq!(run(&*second, 1), vec![NodeValue::Count(1), half]);
+    }
+
+    #[test]
+    fn reloads_changed_scripts() {
+        let path = script_file("reloaded", COUNTER);
+        let (_, _, def) = load_script_node(&path).unwrap();
+        let counter = executor(def);
+        assert_eq!(run(&*counter, 1)[0], NodeValue::Count(1));
+
+        let change = |script: String| {
+            fs::write(&path, script).unwrap();
+            let later = SystemTime::now() + Duration::from_secs(1);
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(later)
+                .unwrap();
+            // Give the watching thread time to notice the change and reload.
+            thread::sleep(RELOAD_CHECK_INTERVAL * 3);
+        };
+
+        // State is kept across the reload.
+        change(COUNTER.replace("+= inputs[0]", "+= inputs[0] * 10"));
+        assert_eq!(run(&*counter, 1)[0], NodeValue::Count(11));
+
+        // Scripts that don't compile, or that change the outputs, are ignored.
+        change(COUNTER.replace("fn run", "fn run("));
+        assert_eq!(run(&*counter, 1)[0], NodeValue::Count(21));
+        change(COUNTER.replace(r#"type: "Count" }"#, r#"type: "Toggle" }"#));
+        assert_eq!(run(&*counter, 1)[0], NodeValue::Count(31));
+        change(
+            COUNTER
+                .replace("+= inputs[0]", "+= inputs[0] * 100")
+                .replace(r#"name: "Counter""#, r#"name: "Renamed""#),
+        );
+        assert_eq!(run(&*counter, 1)[0], NodeValue::Count(41));
+        change(
+            COUNTER
+                .replace("+= inputs[0]", "+= inputs[0] * 100")
+                .replace(r#""0.2.0""#, r#""0.2.1""#),
+        );
+        assert_eq!(run(&*counter, 1)[0], NodeValue::Count(51));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn contains_misbehaving_scripts() {
+        let hang = executor(load_script_node_from(
+            &COUNTER.replace("this.count += inputs[0];", "loop {}"),
+        ));
+        assert_eq!(run(&*hang, 1)[0], NodeValue::Count(0));
+
+        let wrong = executor(load_script_node_from(
+            &COUNTER.replace("[this.count, 0.5]", r#"["many", 0.5]"#),
+        ));
+        assert_eq!(run(&*wrong, 1)[0], NodeValue::Count(0));
+
+        let path = script_file(
+            "unsupported",
+            &COUNTER.replace("\"Count\" }", "\"Shader2D\" }"),
+        );
+        match load_script_node(&path) {
+            Err(ScriptError::UnsupportedType(NodeValueType::Shader2D)) => {}
+            result => panic!("Expected a type error, got {:?}", result.err()),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
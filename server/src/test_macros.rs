@@ -11,122 +11,78 @@ macro_rules! map(
         };
 );
 
-/// Converts a rust variable type to the NodeValueType that could hold it
-macro_rules! node_value_of {
-    ($val:ident: TriggerSignal) => {NodeValue::Trigger($val)};
-    ($val:literal: TriggerSignal) => {NodeValue::Trigger($val)};
-    ($val:ident: bool) => {NodeValue::Toggle($val)};
-    ($val:literal: bool) => {NodeValue::Toggle($val)};
-    ($val:ident: i64) => {NodeValue::Count($val)};
-    ($val:literal: i64) => {NodeValue::Count($val)};
-    ($val:ident: u32) => {NodeValue::ConstrainedMagnitude($val)};
-    ($val:literal: u32) => {NodeValue::ConstrainedMagnitude($val)};
-    ($val:ident: f64) => {NodeValue::UnconstrainedMagnitude($val)};
-    ($val:literal: f64) => {NodeValue::UnconstrainedMagnitude($val)};
-    ($val:ident: NodeColor) => {NodeValue::Color($val)};
-    ($val:literal: NodeColor) => {NodeValue::Color($val)};
-    ($val:ident: Box<String>) => {NodeValue::Text($val)};
-    ($val:literal: Box<String>) => {NodeValue::Text($val)};
-}
-
-/// Converts a rust variable type to the NodeValueType that could hold it
-macro_rules! node_value_type_of {
-    (TriggerSignal) => {NodeValueType::Trigger};
-    (bool) => {NodeValueType::Toggle};
-    (i64) => {NodeValueType::Count};
-    (u32) => {NodeValueType::ConstrainedMagnitude};
-    (f64) => {NodeValueType::UnconstrainedMagnitude};
-    (NodeColor) => {NodeValueType::Color};
-    (Box<String>) => {NodeValueType::Text};
-}
-
 /// Converts an arg to a NodeValueInput
 macro_rules! node_input_def_from_arg {
-    ($name:ident: $type:ident) => {
+    ($name:ident: $type:ty) => {
         NodeInputDef {
             desc: NodeDefBasicDescription {
                 name: stringify!($name).to_string(),
                 description: concat!("Automatic description of input ", stringify!($name)).to_string(),
             },
-            allowed_types: vec![node_value_type_of!($type)],
+            allowed_types: vec![<$type as proton_shared::node_value::FromNodeValue>::VALUE_TYPE],
             required: true,
+            enum_def: None,
+            composite_type: None,
+            default: None,
         }
     };
 }
 
 /// Makes a list of NodeValueInputs based on function args.
 macro_rules! node_input_def_from_args {
-    ($($name:ident: $type:ident),*) => {vec![
+    ($($name:ident: $type:ty),*) => {vec![
         $(node_input_def_from_arg!($name: $type)),*
     ]};
 }
 
 /// Converts an arg to a NodeValueInput
 macro_rules! node_output_def_from_type {
-    ($type:ident) => {
+    ($type:ty) => {
         NodeOutputDef {
             desc: NodeDefBasicDescription {
                 name: "Generic output".to_string(),
                 description: "Generic output description".to_string()
             },
-            output_type: node_value_type_of!($type),
+            output_type: <$type as proton_shared::node_value::IntoNodeValue>::VALUE_TYPE,
+            enum_def: None,
+            composite_type: None,
         }
     };
 }
 
 /// Makes a list of NodeValueInputs based on function args.
 macro_rules! node_output_def_from_tuple {
-    ($($type:ident),+) => {vec![
+    ($($type:ty),+) => {vec![
         $(node_output_def_from_type!($type)),+
     ]};
 }
 
-/// Wraps a given function body with unwrapping code for NodeValue inputs
+/// Wraps a given function body with conversion of its NodeValue inputs to typed args,
+/// panicking with a description of the first input that doesn't convert.
 macro_rules! wrap_node_function {
-    (@body {$body:block} $ivar:ident $name_1:ident: $type_1:ident $idx:expr) => {
-        if let node_value_of!($name_1: $type_1) = $ivar[$idx] {
-            $body
-        } else {
-            panic!(concat!("Invalid type for NodeValue input ", stringify!($name_1)));
-        }
-    };
-
-    (@body {$body:block} $ivar:ident $name_1:ident: $type_1:ident, $($name:ident: $type:ident),+ $idx:expr) => {
-        if let node_value_of!($name_1: $type_1) = $ivar[$idx] {
-            wrap_node_function!(@body {$body} $ivar $($name: $type),+ $idx + 1usize)
-        } else {
-            panic!(concat!("Invalid type for NodeValue input ", stringify!($name_1)));
-        }
-    };
-
-    (fn $fname:ident($($name:ident: $type:ident),+) -> $o:ty $body:block) => {
+    (fn $fname:ident($($name:ident: $type:ty),*) -> $o:ty $body:block) => {
         fn $fname(inputs: Vec<&NodeValue>) -> $o {
-            wrap_node_function!(@body {$body} inputs $($name: $type),+ 0)
+            wrap_node_function!(@body {$body} inputs $($name: $type),*)
         }
     };
 
-    (|$($name:ident: $type:ident),+| $body:block) => {
+    (|$($name:ident: $type:ty),*| $body:block) => {
         |inputs: Vec<&NodeValue>| {
-            wrap_node_function!(@body {$body} inputs $($name: $type),+ 0)
-        }
-    };
-
-    (fn $fname:ident( ) -> $o:ty $body:block) => {
-        fn $fname(_inputs: Vec<&NodeValue>) -> $o {
-            $body
+            wrap_node_function!(@body {$body} inputs $($name: $type),*)
         }
     };
 
-    (| | $body:block) => {
-        |_inputs: Vec<&NodeValue>| {
-            $body
-        }
-    };
+    (@body {$body:block} $ivar:ident $($name:ident: $type:ty),*) => {{
+        let ($($name,)*): ($($type,)*) =
+            proton_shared::node_value::from_node_inputs(&$ivar)
+                .unwrap_or_else(|err| panic!("Invalid NodeValue input: {}", err));
+        $body
+    }};
 }
 
 /// Builds a NodeDef with a function runner from a lambda function.
 macro_rules! node_def_from_fn {
-    (|$($name:ident: $type:ident),*| -> ($($o:ident),+) $body:block) => {
+    (|$($name:ident: $type:ty),*| -> ($($o:ty),+) $body:block) => {
         NodeDef {
             desc: NodeDefBasicDescription {
                 name: "Test Node".to_string(),
@@ -138,11 +94,11 @@ macro_rules! node_def_from_fn {
         }
     };
 
-    (|| -> ($($o:ident),+) $body:block) => {
-        node_def_from_fn!(| | -> ($($o),+) $body);
+    (|| -> ($($o:ty),+) $body:block) => {
+        node_def_from_fn!(| | -> ($($o),+) $body)
     };
 
-    (fn $fname:ident($($name:ident: $type:ident),*) -> ($($o:ident),+) $body:block) => {
+    (fn $fname:ident($($name:ident: $type:ty),*) -> ($($o:ty),+) $body:block) => {
         NodeDef {
             desc: NodeDefBasicDescription {
                 name: stringify!($fname).to_string(),
@@ -155,7 +111,7 @@ macro_rules! node_def_from_fn {
     };
 }
 
-/// Instantiates a node with an Id, a def, and inputs.
+/// Instantiates a node with an Id, a namespaced def name, and inputs.
 macro_rules! make_node {
     (@input Wire{$nodeid:literal, $output:literal}) => {
         NodeInput::Wire(NodeOutputRef {
@@ -164,12 +120,15 @@ macro_rules! make_node {
         })
     };
     (@input $type:ident{$val:literal}) => {
-        NodeInput::Const(node_value_of!($val: $type))
+        NodeInput::Const({
+            let value: $type = $val;
+            proton_shared::node_value::IntoNodeValue::into_node_value(value)
+        })
     };
-    ($id:literal: $def:ident[$($type:ident{$($arg:literal),+}),*]) => {
+    ($id:literal: $($def:ident).+[$($type:ident{$($arg:literal),+}),*]) => {
         Node {
             id: $id,
-            def_name: stringify!($def).to_string(),
+            def_name: vec![$(stringify!($def)),+].join("."),
             inputs: vec![
                 $(make_node!(@input $type{$($arg),+})),*
             ]
@@ -177,11 +136,11 @@ macro_rules! make_node {
     };
 }
 
-/// Instantiates one or more nodes with Ids, defs, and inputs.
+/// Instantiates one or more nodes with Ids, namespaced def names, and inputs.
 macro_rules! make_nodes {
-    ($($id:literal: $def:ident[$($type:ident{$($arg:literal),+}),*]),+) => {
+    ($($id:literal: $($def:ident).+[$($type:ident{$($arg:literal),+}),*]),+) => {
         vec![
-            $(make_node!($id: $def[$($type{$($arg),+}),*])),+
+            $(make_node!($id: $($def).+[$($type{$($arg),+}),*])),+
         ]
     };
-}
\ No newline at end of file
+}
@@ -0,0 +1,603 @@
+//! NodeDefs implemented as WebAssembly modules, which run sandboxed so that untrusted
+//! nodes can't crash or hang the server.
+//!
+//! Each module implements a single def. Modules may not import anything, and must
+//! export:
+//!
+//! - `memory`: the module's linear memory.
+//! - `proton_abi_version() -> i32`: must return `WASM_ABI_VERSION`.
+//! - `proton_alloc(len: i32) -> i32`: returns a pointer to `len` bytes that the server
+//!   can write inputs to.
+//! - `proton_describe() -> i64`: returns where the module's manifest is in memory, as
+//!   the pointer in the high 32 bits and the length in the low 32 bits. The manifest is
+//!   JSON with the def's `id`, `version`, `desc`, `inputs` and `outputs`, in the same
+//!   form as an entry of the NodeDef catalog. The id must be in the module's own
+//!   namespace, which the plugin host takes from its file name, so that a module can't
+//!   claim core nodes or shadow the defs of another module.
+//! - `proton_run(ptr: i32, len: i32, elapsed_micros: i64) -> i64`: runs the def against
+//!   a JSON array of input values written by the server at `ptr`, and returns where a
+//!   JSON array of output values is in memory, packed like the manifest's location.
+//!   Values are serialized as NodeValues are, such as `{"Count": 3}`.
+//!
+//! Every Node gets its own instance of the module, so the module's globals and memory
+//! hold state from one execution to the next. A run that traps, runs out of fuel, goes
+//! over the memory limit, takes too long or returns outputs that don't match the def's
+//! outputs counts as failed: the Node repeats its last good outputs (or the defaults of its output types)
+//! and the instance is replaced by a fresh one before the next run. Only the first of a
+//! run of failures is reported, so that a broken Node doesn't flood the log every frame.
+
+use parking_lot::Mutex;
+use proton_shared::node_def::{
+    ExecutionContext, NodeDef, NodeDefBasicDescription, NodeDefRunner, NodeExecutor,
+    NodeExecutorFactory, NodeInputDef, NodeOutputDef,
+};
+use proton_shared::node_def_registry::NodeDefVersion;
+use proton_shared::node_value::{NodeValue, NodeValueType};
+use serde::Deserialize;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use wasmi::{
+    Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+};
+
+/// Version of the exports described above. Bump this whenever they change.
+pub const WASM_ABI_VERSION: i32 = 1;
+
+/// Namespace of the nodes that ship with the server, which no module may use.
+const CORE_NAMESPACE: &str = "core";
+
+/// How much a module is allowed to do in a single call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WasmLimits {
+    /// Fuel for each call, roughly one unit per instruction. Running out stops the
+    /// call, which is what keeps a module from hanging the server.
+    pub fuel_per_call: u64,
+
+    /// Largest the module's memory can grow to.
+    pub max_memory_bytes: usize,
+
+    /// Longest a `proton_run` call may take, not counting the server's own work
+    /// around it. This is only checked once the call returns, so it can't stop a call
+    /// that hangs; fuel is what bounds how long a call runs. It fails calls that do
+    /// finish but take longer than this, such as when each unit of fuel is slow.
+    pub max_call_duration: Duration,
+}
+
+impl Default for WasmLimits {
+    fn default() -> Self {
+        WasmLimits {
+            fuel_per_call: 10_000_000,
+            max_memory_bytes: 16 * 1024 * 1024,
+            max_call_duration: Duration::from_millis(10),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WasmNodeError {
+    /// The module could not be compiled, instantiated or called, including traps.
+    Runtime(wasmi::Error),
+    MissingExport(&'static str),
+    IncompatibleAbi(i32),
+
+    /// The module read or wrote outside of its memory.
+    OutOfBounds,
+    InvalidJson(serde_json::Error),
+
+    /// A def's outputs must all have default values that match their enum and
+    /// composite types, for the Node to output when a run fails before it has ever
+    /// succeeded.
+    OutputWithoutDefault(NodeValueType),
+
+    /// The outputs of a run don't match the def's outputs, including their enum and
+    /// composite types.
+    WrongOutputs(Vec<NodeValueType>),
+
+    /// The manifest claims an id outside of the module's namespace.
+    OutsideNamespace {
+        id: String,
+        namespace: String,
+    },
+    TooSlow(Duration),
+}
+
+impl fmt::Display for WasmNodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WasmNodeError::Runtime(err) => write!(f, "WebAssembly error: {}", err),
+            WasmNodeError::MissingExport(name) => write!(f, "Module does not export {}", name),
+            WasmNodeError::IncompatibleAbi(version) => write!(
+                f,
+                "Module uses ABI version {}, but the server uses {}",
+                version, WASM_ABI_VERSION
+            ),
+            WasmNodeError::OutOfBounds => f.write_str("Module pointed outside of its memory"),
+            WasmNodeError::InvalidJson(err) => write!(f, "Invalid JSON from module: {}", err),
+            WasmNodeError::OutputWithoutDefault(value_type) => write!(
+                f,
+                "Output type {:?} has no default value to fall back on",
+                value_type
+            ),
+            WasmNodeError::WrongOutputs(found) => write!(
+                f,
+                "Module returned outputs of types {:?}, which don't match its def",
+                found
+            ),
+            WasmNodeError::OutsideNamespace { id, namespace } => write!(
+                f,
+                "Module claims id {}, which is outside of its namespace {}",
+                id, namespace
+            ),
+            WasmNodeError::TooSlow(duration) => write!(f, "Run took {:?}", duration),
+        }
+    }
+}
+
+impl std::error::Error for WasmNodeError {}
+
+impl From<wasmi::Error> for WasmNodeError {
+    fn from(err: wasmi::Error) -> WasmNodeError {
+        WasmNodeError::Runtime(err)
+    }
+}
+
+impl From<serde_json::Error> for WasmNodeError {
+    fn from(err: serde_json::Error) -> WasmNodeError {
+        WasmNodeError::InvalidJson(err)
+    }
+}
+
+/// What a module says about the def it implements.
+#[derive(Deserialize)]
+struct WasmNodeManifest {
+    id: String,
+    version: NodeDefVersion,
+    desc: NodeDefBasicDescription,
+    inputs: Vec<NodeInputDef>,
+    outputs: Vec<NodeOutputDef>,
+}
+
+/// A def's compiled module, from which each Node gets its own instance.
+struct WasmNodeModule {
+    engine: Engine,
+    module: Module,
+    limits: WasmLimits,
+    id: String,
+    outputs: Vec<NodeOutputDef>,
+}
+
+struct WasmNodeFactory {
+    module: Arc<WasmNodeModule>,
+}
+
+/// A running instance of a module, along with the store that holds its state.
+struct WasmInstance {
+    store: Store<StoreLimits>,
+    instance: Instance,
+    memory: Memory,
+}
+
+struct WasmNodeExecutor {
+    module: Arc<WasmNodeModule>,
+
+    /// None after a failed run, until the next run creates a fresh instance.
+    instance: Mutex<Option<WasmInstance>>,
+    last_outputs: Mutex<Vec<NodeValue>>,
+
+    /// Whether the last run failed, in which case further failures aren't reported.
+    failing: AtomicBool,
+}
+
+/// Splits a pointer and length packed into an i64 by the module.
+fn unpack(packed: i64) -> (usize, usize) {
+    let packed = packed as u64;
+    ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize)
+}
+
+impl WasmInstance {
+    fn new(
+        engine: &Engine,
+        module: &Module,
+        limits: &WasmLimits,
+    ) -> Result<WasmInstance, WasmNodeError> {
+        let store_limits = StoreLimitsBuilder::new()
+            .memory_size(limits.max_memory_bytes)
+            .instances(1)
+            .build();
+        let mut store = Store::new(engine, store_limits);
+        store.limiter(|limits| limits);
+        store
+            .set_fuel(limits.fuel_per_call)
+            .map_err(wasmi::Error::from)?;
+        // No host functions are linked, so modules that import anything fail here.
+        let instance = Linker::new(engine)
+            .instantiate(&mut store, module)?
+            .start(&mut store)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or(WasmNodeError::MissingExport("memory"))?;
+        let mut wasm_instance = WasmInstance {
+            store,
+            instance,
+            memory,
+        };
+        let abi_version = wasm_instance.call::<(), i32>("proton_abi_version", (), limits)?;
+        if abi_version != WASM_ABI_VERSION {
+            return Err(WasmNodeError::IncompatibleAbi(abi_version));
+        }
+        Ok(wasm_instance)
+    }
+
+    /// Calls an export with a fresh tank of fuel.
+    fn call<Params, Results>(
+        &mut self,
+        name: &'static str,
+        params: Params,
+        limits: &WasmLimits,
+    ) -> Result<Results, WasmNodeError>
+    where
+        Params: wasmi::WasmParams,
+        Results: wasmi::WasmResults,
+    {
+        let func = self
+            .instance
+            .get_typed_func::<Params, Results>(&self.store, name)
+            .map_err(|_| WasmNodeError::MissingExport(name))?;
+        self.store
+            .set_fuel(limits.fuel_per_call)
+            .map_err(wasmi::Error::from)?;
+        Ok(func.call(&mut self.store, params)?)
+    }
+
+    /// Copies bytes out of the module's memory, checking that they are all in bounds
+    /// before allocating anything for them.
+    fn read(&self, (ptr, len): (usize, usize)) -> Result<Vec<u8>, WasmNodeError> {
+        let data = self.memory.data(&self.store);
+        let end = ptr
+            .checked_add(len)
+            .filter(|end| *end <= data.len())
+            .ok_or(WasmNodeError::OutOfBounds)?;
+        Ok(data[ptr..end].to_vec())
+    }
+
+    fn describe(&mut self, limits: &WasmLimits) -> Result<WasmNodeManifest, WasmNodeError> {
+        let location = unpack(self.call::<(), i64>("proton_describe", (), limits)?);
+        Ok(serde_json::from_slice(&self.read(location)?)?)
+    }
+
+    fn run(
+        &mut self,
+        inputs: &[&NodeValue],
+        elapsed_micros: i64,
+        limits: &WasmLimits,
+    ) -> Result<Vec<NodeValue>, WasmNodeError> {
+        let json = serde_json::to_vec(inputs)?;
+        let ptr = self.call::<i32, i32>("proton_alloc", json.len() as i32, limits)?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, &json)
+            .map_err(|_| WasmNodeError::OutOfBounds)?;
+        let started = Instant::now();
+        let location = self.call::<(i32, i32, i64), i64>(
+            "proton_run",
+            (ptr, json.len() as i32, elapsed_micros),
+            limits,
+        )?;
+        let took = started.elapsed();
+        if took > limits.max_call_duration {
+            return Err(WasmNodeError::TooSlow(took));
+        }
+        Ok(serde_json::from_slice(&self.read(unpack(location))?)?)
+    }
+}
+
+/// Compiles a module and reads its manifest, returning the def it implements along
+/// with the id and version to register it under. The id must be in the given
+/// namespace, which can't be the one the server's own nodes use.
+pub fn load_wasm_node(
+    bytes: &[u8],
+    namespace: &str,
+    limits: WasmLimits,
+) -> Result<(String, NodeDefVersion, NodeDef), WasmNodeError> {
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config);
+    let module = Module::new(&engine, bytes)?;
+    let manifest = WasmInstance::new(&engine, &module, &limits)?.describe(&limits)?;
+
+    let in_namespace = manifest.id.split('.').next() == Some(namespace);
+    if namespace == CORE_NAMESPACE || !in_namespace {
+        return Err(WasmNodeError::OutsideNamespace {
+            id: manifest.id,
+            namespace: namespace.to_string(),
+        });
+    }
+    if let Some(output) = manifest
+        .outputs
+        .iter()
+        .find(|output| default_output(output).is_none())
+    {
+        return Err(WasmNodeError::OutputWithoutDefault(output.output_type));
+    }
+    let factory = WasmNodeFactory {
+        module: Arc::new(WasmNodeModule {
+            engine,
+            module,
+            limits,
+            id: manifest.id.clone(),
+            outputs: manifest.outputs.clone(),
+        }),
+    };
+    let def = NodeDef {
+        desc: manifest.desc,
+        inputs: manifest.inputs,
+        outputs: manifest.outputs,
+        runner: NodeDefRunner::ExecutorFactory(Arc::new(factory)),
+    };
+    Ok((manifest.id, manifest.version, def))
+}
+
+/// Value for a Node to output before it has ever run successfully, which has to match
+/// the output's enum and composite types as well as its type.
+fn default_output(output: &NodeOutputDef) -> Option<NodeValue> {
+    NodeValue::default_for(output.output_type).filter(|value| output.produces(value))
+}
+
+impl NodeExecutorFactory for WasmNodeFactory {
+    fn create_executor(&self) -> Box<dyn NodeExecutor> {
+        let defaults = self
+            .module
+            .outputs
+            .iter()
+            .map(|output| default_output(output).unwrap())
+            .collect();
+        Box::new(WasmNodeExecutor {
+            module: self.module.clone(),
+            instance: Mutex::new(None),
+            last_outputs: Mutex::new(defaults),
+            failing: AtomicBool::new(false),
+        })
+    }
+}
+
+impl WasmNodeExecutor {
+    fn try_run(
+        &self,
+        instance: &mut Option<WasmInstance>,
+        inputs: &[&NodeValue],
+        context: &ExecutionContext,
+    ) -> Result<Vec<NodeValue>, WasmNodeError> {
+        let module = &self.module;
+        let limits = &module.limits;
+        if instance.is_none() {
+            *instance = Some(WasmInstance::new(&module.engine, &module.module, limits)?);
+        }
+        let elapsed_micros = context.elapsed.as_micros();
+        let outputs = instance
+            .as_mut()
+            .unwrap()
+            .run(inputs, elapsed_micros, limits)?;
+
+        let matches = outputs.len() == module.outputs.len()
+            && module
+                .outputs
+                .iter()
+                .zip(&outputs)
+                .all(|(output, value)| output.produces(value));
+        if !matches {
+            let output_types = outputs.iter().map(NodeValueType::from).collect();
+            return Err(WasmNodeError::WrongOutputs(output_types));
+        }
+        Ok(outputs)
+    }
+}
+
+impl NodeExecutor for WasmNodeExecutor {
+    fn prepare(&self, _enabled_outputs: &[bool]) {}
+
+    fn execute(&self, inputs: Vec<&NodeValue>, context: &ExecutionContext) -> Vec<NodeValue> {
+        let mut instance = self.instance.lock();
+        let mut last_outputs = self.last_outputs.lock();
+        match self.try_run(&mut instance, &inputs, context) {
+            Ok(outputs) => {
+                *last_outputs = outputs;
+                self.failing.store(false, Ordering::Relaxed);
+            }
+            Err(err) => {
+                if !self.failing.swap(true, Ordering::Relaxed) {
+                    eprintln!(
+                        "WebAssembly node {} failed, and will repeat its last good outputs \
+                         until it recovers: {}",
+                        self.module.id, err
+                    );
+                }
+                *instance = None;
+            }
+        }
+        last_outputs.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module with a single Count input and output, where `proton_run` has the given
+    /// body. An output of `[{"Count":0}]` is at offset 4096, and the input is written
+    /// to offset 8192.
+    fn guest(run_body: &str) -> Vec<u8> {
+        let outputs = r#"[{"desc":{"name":"count","description":""},"output_type":"Count"}]"#;
+        guest_with("test.wasm", outputs, r#"[{"Count":0}]"#, run_body)
+    }
+
+    /// Like guest, but with the given id and output defs in the manifest and the given
+    /// outputs at offset 4096.
+    fn guest_with(id: &str, outputs: &str, output: &str, run_body: &str) -> Vec<u8> {
+        let manifest = format!(
+            r#"{{"id":"{}","version":"1.2.0","desc":{{"name":"Test","description":""}},"inputs":[{{"desc":{{"name":"count","description":""}},"allowed_types":["Count"],"required":true}}],"outputs":{}}}"#,
+            id, outputs
+        );
+        let wat = format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (global $runs (mut i32) (i32.const 0))
+                (data (i32.const 0) "{}")
+                (data (i32.const 4096) "{}")
+                (func (export "proton_abi_version") (result i32) i32.const 1)
+                (func (export "proton_alloc") (param i32) (result i32) i32.const 8192)
+                (func (export "proton_describe") (result i64) i64.const {})
+                (func (export "proton_run") (param i32 i32 i64) (result i64) {}))"#,
+            manifest.replace('"', "\\\""),
+            output.replace('"', "\\\""),
+            manifest.len(),
+            run_body
+        );
+        wat::parse_str(wat).unwrap()
+    }
+
+    /// Bumps a counter kept in a global, and outputs it.
+    const COUNTER: &str = r#"
+        global.get $runs i32.const 1 i32.add global.set $runs
+        i32.const 4106 global.get $runs i32.const 48 i32.add i32.store8
+        i64.const 17592186044429"#;
+
+    /// Outputs its input.
+    const ECHO: &str =
+        "local.get 0 i64.extend_i32_u i64.const 32 i64.shl local.get 1 i64.extend_i32_u i64.or";
+
+    fn run(executor: &dyn NodeExecutor, input: i64) -> Vec<NodeValue> {
+        executor.execute(vec![&NodeValue::Count(input)], &ExecutionContext::default())
+    }
+
+    fn executor(run_body: &str, limits: WasmLimits) -> Box<dyn NodeExecutor> {
+        let (_, _, def) = load_wasm_node(&guest(run_body), "test", limits).unwrap();
+        match def.runner {
+            NodeDefRunner::ExecutorFactory(factory) => factory.create_executor(),
+            _ => panic!("WebAssembly nodes should have executor factories"),
+        }
+    }
+
+    #[test]
+    fn describes_and_runs_modules() {
+        let (id, version, def) =
+            load_wasm_node(&guest(ECHO), "test", WasmLimits::default()).unwrap();
+        assert_eq!(id, "test.wasm");
+        assert_eq!(version, NodeDefVersion::new(1, 2, 0));
+        assert_eq!(def.inputs[0].allowed_types, vec![NodeValueType::Count]);
+        assert_eq!(def.outputs[0].output_type, NodeValueType::Count);
+
+        let echo = executor(ECHO, WasmLimits::default());
+        assert_eq!(run(&*echo, 42), vec![NodeValue::Count(42)]);
+
+        // Each Node has its own instance, which keeps its state between runs.
+        let first = executor(COUNTER, WasmLimits::default());
+        let second = executor(COUNTER, WasmLimits::default());
+        assert_eq!(run(&*first, 0), vec![NodeValue::Count(1)]);
+        assert_eq!(run(&*first, 0), vec![NodeValue::Count(2)]);
+        assert_eq!(run(&*second, 0), vec![NodeValue::Count(1)]);
+    }
+
+    #[test]
+    fn contains_misbehaving_modules() {
+        let hang = executor(
+            "(loop $forever br $forever) i64.const 0",
+            WasmLimits::default(),
+        );
+        assert_eq!(run(&*hang, 1), vec![NodeValue::Count(0)]);
+
+        let limits = WasmLimits {
+            max_memory_bytes: 1024 * 1024,
+            ..WasmLimits::default()
+        };
+        let grow = "i32.const 100 memory.grow i32.const -1 i32.eq if unreachable end";
+        let hog = executor(&format!("{} {}", grow, ECHO), limits);
+        assert_eq!(run(&*hog, 1), vec![NodeValue::Count(0)]);
+
+        // After a good run, failed runs repeat its outputs, and the instance restarts.
+        let flaky = executor(
+            &format!(
+                "global.get $runs i32.const 2 i32.eq if unreachable end {}",
+                COUNTER
+            ),
+            WasmLimits::default(),
+        );
+        assert_eq!(run(&*flaky, 0), vec![NodeValue::Count(1)]);
+        assert_eq!(run(&*flaky, 0), vec![NodeValue::Count(2)]);
+        assert_eq!(run(&*flaky, 0), vec![NodeValue::Count(2)]);
+        assert_eq!(run(&*flaky, 0), vec![NodeValue::Count(1)]);
+
+        let no_outputs = executor("i64.const 0", WasmLimits::default());
+        assert_eq!(run(&*no_outputs, 1), vec![NodeValue::Count(0)]);
+
+        // Outputs that run past the end of memory are caught before they are copied.
+        let overrun = executor("i64.const 4294967295", WasmLimits::default());
+        assert_eq!(run(&*overrun, 1), vec![NodeValue::Count(0)]);
+
+        let limits = WasmLimits {
+            max_call_duration: Duration::ZERO,
+            ..WasmLimits::default()
+        };
+        let slow = executor(ECHO, limits);
+        assert_eq!(run(&*slow, 1), vec![NodeValue::Count(0)]);
+    }
+
+    #[test]
+    fn checks_outputs_against_their_composite_types() {
+        // An empty record can stand in until the first good run, but a record with a
+        // field that the def doesn't have is not one of its outputs.
+        let outputs = r#"[{"desc":{"name":"empty","description":""},"output_type":"Record","composite_type":{"Record":[]}}]"#;
+        let output = r#"[{"Record":{"fields":[["size",{"Count":3}]]}}]"#;
+        let run_body = format!("i64.const {}", (4096i64 << 32) | output.len() as i64);
+        let (_, _, def) = load_wasm_node(
+            &guest_with("test.wasm", outputs, output, &run_body),
+            "test",
+            WasmLimits::default(),
+        )
+        .unwrap();
+        let executor = match def.runner {
+            NodeDefRunner::ExecutorFactory(factory) => factory.create_executor(),
+            _ => panic!("WebAssembly nodes should have executor factories"),
+        };
+        assert_eq!(
+            run(&*executor, 1),
+            vec![NodeValue::default_for(NodeValueType::Record).unwrap()]
+        );
+
+        // Records with fields have no default to fall back on.
+        let outputs = r#"[{"desc":{"name":"sized","description":""},"output_type":"Record","composite_type":{"Record":[["size",{"Simple":"Count"}]]}}]"#;
+        match load_wasm_node(
+            &guest_with("test.wasm", outputs, output, &run_body),
+            "test",
+            WasmLimits::default(),
+        ) {
+            Err(WasmNodeError::OutputWithoutDefault(NodeValueType::Record)) => {}
+            result => panic!("Expected a missing default, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn confines_ids_to_the_module_namespace() {
+        let outputs = r#"[{"desc":{"name":"count","description":""},"output_type":"Count"}]"#;
+        let output = r#"[{"Count":0}]"#;
+        let claims = [("acme", "test.wasm"), ("core", "core.magnitude.gamma")];
+        for (namespace, id) in &claims {
+            let module = guest_with(id, outputs, output, ECHO);
+            match load_wasm_node(&module, namespace, WasmLimits::default()) {
+                Err(WasmNodeError::OutsideNamespace { id: claimed, .. }) => {
+                    assert_eq!(claimed, *id)
+                }
+                result => panic!("Expected a namespace error, got {:?}", result.err()),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_modules_that_import() {
+        let wat = r#"(module (import "env" "exit" (func)) (memory (export "memory") 1))"#;
+        match load_wasm_node(&wat::parse_str(wat).unwrap(), "test", WasmLimits::default()) {
+            Err(WasmNodeError::Runtime(_)) => {}
+            result => panic!("Expected a runtime error, got {:?}", result.err()),
+        }
+    }
+}
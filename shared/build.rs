@@ -0,0 +1,74 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn main() {
+    // Rust has no stable ABI, so plugins must be built by the same compiler as the
+    // server. Record which one this is for the plugin handshake.
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=PROTON_RUSTC_VERSION={}", version.trim());
+    println!("cargo:rerun-if-env-changed=RUSTC");
+
+    // Plugins also share proton_shared's types with the server, so they must agree on
+    // its exact sources, features and target, and not just its version number.
+    let mut hash = Fnv1a::new();
+    let mut files = vec![PathBuf::from("Cargo.toml")];
+    list_files(Path::new("src"), &mut files);
+    files.sort();
+    for file in &files {
+        hash.write(file.to_string_lossy().as_bytes());
+        hash.write(&fs::read(file).unwrap());
+    }
+    let mut features: Vec<String> = env::vars()
+        .map(|(name, _)| name)
+        .filter(|name| name.starts_with("CARGO_FEATURE_"))
+        .collect();
+    features.sort();
+    for feature in &features {
+        hash.write(feature.as_bytes());
+    }
+    let target = env::var("TARGET").unwrap();
+    hash.write(target.as_bytes());
+    println!(
+        "cargo:rustc-env=PROTON_SHARED_BUILD_ID={}-{:016x}",
+        target, hash.0
+    );
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-changed=src");
+}
+
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            list_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+/// 64-bit FNV-1a, which unlike std's hashers is guaranteed to give the same result on
+/// every build.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // Length first, so that moving bytes from one input to the next changes the hash.
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use super::node_value::{Bitmap1D, Bitmap2D, NodeMagnitude, NodeValue, NodeValueType};
+use std::sync::Arc;
+
+/// Converts a NodeValue of one type into an equivalent NodeValue of another type.
+/// A Coercion is only ever called with values of the type it was looked up for.
+pub type Coercion = fn(&NodeValue) -> NodeValue;
+
+/// Finds the conversion from one NodeValueType to another, if there is one. This is
+/// the full table of automatic conversions applied to wires between mismatched types.
+pub fn find_coercion(from: NodeValueType, to: NodeValueType) -> Option<Coercion> {
+    use NodeValueType::*;
+    match (from, to) {
+        (Trigger, Toggle) => Some(trigger_to_toggle),
+        (Toggle, Trigger) => Some(toggle_to_trigger),
+        (Toggle, Count) => Some(toggle_to_count),
+        (Count, UnconstrainedMagnitude) => Some(count_to_unconstrained),
+        (UnconstrainedMagnitude, Count) => Some(unconstrained_to_count),
+        (ConstrainedMagnitude, UnconstrainedMagnitude) => Some(constrained_to_unconstrained),
+        (UnconstrainedMagnitude, ConstrainedMagnitude) => Some(unconstrained_to_constrained),
+        (Color, Bitmap1D) => Some(color_to_bitmap_1d),
+        (Color, Bitmap2D) => Some(color_to_bitmap_2d),
+        _ => None,
+    }
+}
+
+/// Returns true if a value of type `from` can be used where `to` is expected.
+pub fn can_coerce(from: NodeValueType, to: NodeValueType) -> bool {
+    from == to || find_coercion(from, to).is_some()
+}
+
+/// Converts a value to the given type, or returns None if there is no conversion.
+pub fn coerce(value: &NodeValue, to: NodeValueType) -> Option<NodeValue> {
+    let from = NodeValueType::from(value);
+    if from == to {
+        return Some(value.clone());
+    }
+    find_coercion(from, to).map(|coercion| coercion(value))
+}
+
+fn mismatch(value: &NodeValue) -> ! {
+    panic!("Coercion called with wrong value type: {:?}", value);
+}
+
+fn trigger_to_toggle(value: &NodeValue) -> NodeValue {
+    match value {
+        NodeValue::Trigger(signal) => NodeValue::Toggle(*signal),
+        _ => mismatch(value),
+    }
+}
+
+fn toggle_to_trigger(value: &NodeValue) -> NodeValue {
+    match value {
+        NodeValue::Toggle(on) => NodeValue::Trigger(*on),
+        _ => mismatch(value),
+    }
+}
+
+fn toggle_to_count(value: &NodeValue) -> NodeValue {
+    match value {
+        NodeValue::Toggle(on) => NodeValue::Count(*on as i64),
+        _ => mismatch(value),
+    }
+}
+
+fn count_to_unconstrained(value: &NodeValue) -> NodeValue {
+    match value {
+        NodeValue::Count(count) => NodeValue::UnconstrainedMagnitude(*count as f64),
+        _ => mismatch(value),
+    }
+}
+
+/// Rounds to the nearest whole number, saturating at the bounds of i64.
+fn unconstrained_to_count(value: &NodeValue) -> NodeValue {
+    match value {
+        NodeValue::UnconstrainedMagnitude(magnitude) => NodeValue::Count(magnitude.round() as i64),
+        _ => mismatch(value),
+    }
+}
+
+fn constrained_to_unconstrained(value: &NodeValue) -> NodeValue {
+    match value {
+        NodeValue::ConstrainedMagnitude(magnitude) => {
+            NodeValue::UnconstrainedMagnitude(NodeMagnitude::from_bits(*magnitude).to_f64())
+        }
+        _ => mismatch(value),
+    }
+}
+
+/// Clamps the value to the 0 to 1 range. NaN becomes 0.
+fn unconstrained_to_constrained(value: &NodeValue) -> NodeValue {
+    match value {
+        NodeValue::UnconstrainedMagnitude(magnitude) => {
+            NodeValue::ConstrainedMagnitude(NodeMagnitude::from_f64(*magnitude).to_bits())
+        }
+        _ => mismatch(value),
+    }
+}
+
+/// Produces a single-pixel bitmap filled with the color.
+fn color_to_bitmap_1d(value: &NodeValue) -> NodeValue {
+    match value {
+        NodeValue::Color(color) => NodeValue::Bitmap1D(Arc::new(Bitmap1D::filled(1, *color))),
+        _ => mismatch(value),
+    }
+}
+
+/// Produces a 1x1 bitmap filled with the color.
+fn color_to_bitmap_2d(value: &NodeValue) -> NodeValue {
+    match value {
+        NodeValue::Color(color) => NodeValue::Bitmap2D(Arc::new(Bitmap2D::filled(1, 1, *color))),
+        _ => mismatch(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_between_magnitudes() {
+        assert_eq!(
+            coerce(
+                &NodeValue::ConstrainedMagnitude(u32::MAX),
+                NodeValueType::UnconstrainedMagnitude
+            ),
+            Some(NodeValue::UnconstrainedMagnitude(1.0))
+        );
+        assert_eq!(
+            coerce(
+                &NodeValue::UnconstrainedMagnitude(1.5),
+                NodeValueType::ConstrainedMagnitude
+            ),
+            Some(NodeValue::ConstrainedMagnitude(u32::MAX))
+        );
+        assert_eq!(
+            coerce(
+                &NodeValue::UnconstrainedMagnitude(-0.5),
+                NodeValueType::ConstrainedMagnitude
+            ),
+            Some(NodeValue::ConstrainedMagnitude(0))
+        );
+        assert_eq!(
+            coerce(&NodeValue::Count(3), NodeValueType::UnconstrainedMagnitude),
+            Some(NodeValue::UnconstrainedMagnitude(3.0))
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_conversions() {
+        assert!(!can_coerce(NodeValueType::Text, NodeValueType::Count));
+        assert!(!can_coerce(NodeValueType::Trigger, NodeValueType::Color));
+        assert_eq!(coerce(&NodeValue::Count(1), NodeValueType::Color), None);
+    }
+}
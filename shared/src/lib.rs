@@ -2,6 +2,14 @@ extern crate strum;
 #[macro_use]
 extern crate strum_macros;
 
+pub mod coercion;
 pub mod node_def;
+pub mod node_def_catalog;
 pub mod node_def_registry;
-pub mod node_value;
\ No newline at end of file
+pub mod node_value;
+pub mod pixel_map;
+pub mod plugin;
+pub mod serialization;
+pub mod shader_expression;
+pub mod shader_registry;
+pub mod tempo;
\ No newline at end of file
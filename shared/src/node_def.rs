@@ -1,5 +1,12 @@
-use super::node_value::{NodeValue, NodeValueType};
+use super::node_def_registry::{NodeDefRegistry, NodeDefRegistryError};
+use super::node_value::{NodeCompositeType, NodeDuration, NodeEnumDef, NodeValue, NodeValueType};
+use super::pixel_map::PixelMap;
+use super::shader_registry::ShaderRegistry;
+use super::tempo::{TempoChange, TempoClock};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::sync::Arc;
 
 /// A NodeDef represents a type of function that can be called in an evaluation graph.
 /// These functions, like Rust's own functions, have a name and defined input and output
@@ -15,23 +22,54 @@ pub struct NodeDef {
     pub runner: NodeDefRunner,
 }
 
+/// A NodeDef written in Rust, with the id it is registered under. Usually implemented
+/// by the `#[proton_node]` attribute from proton_node_macros.
+pub trait ProtonNode {
+    const ID: &'static str;
+
+    fn node_def() -> NodeDef;
+
+    fn register(registry: &NodeDefRegistry) -> Result<(), NodeDefRegistryError> {
+        registry.register(Self::ID.to_string(), Self::node_def())
+    }
+}
+
 /// Represents a single input to a NodeDef function.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct NodeInputDef {
     pub desc: NodeDefBasicDescription,
     pub allowed_types: Vec<NodeValueType>,
     pub required: bool,
+
+    /// If set, Enumeration values passed to this input must be options of this enum.
+    pub enum_def: Option<Arc<NodeEnumDef>>,
+
+    /// If set, List and Record values passed to this input must have exactly this
+    /// type. Otherwise any List or Record is accepted.
+    pub composite_type: Option<NodeCompositeType>,
+
+    /// Value given to the NodeDef when a Node leaves this input out. Inputs can only
+    /// be left out after the last one the Node does set.
+    #[serde(default)]
+    pub default: Option<NodeValue>,
 }
 
 /// Represents a single output of a NodeDef function.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct NodeOutputDef {
     pub desc: NodeDefBasicDescription,
     pub output_type: NodeValueType,
+
+    /// If set, Enumeration values from this output are always options of this enum.
+    pub enum_def: Option<Arc<NodeEnumDef>>,
+
+    /// If set, List and Record values from this output always have exactly this type.
+    /// Otherwise the contents of the List or Record are only known at runtime.
+    pub composite_type: Option<NodeCompositeType>,
 }
 
 /// Human-readable information about a node or its inputs or outputs.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct NodeDefBasicDescription {
     pub name: String,
     pub description: String,
@@ -40,12 +78,24 @@ pub struct NodeDefBasicDescription {
 /// Options for executing a Node, as specified in a NodeDef.
 pub enum NodeDefRunner {
     Function(fn(Vec<&NodeValue>) -> Vec<NodeValue>),
+
+    /// Like Function, but with one function per output (in the same order as the
+    /// NodeDef's outputs). Each output is computed independently, and only if some
+    /// other Node is actually wired to it.
+    PerOutputFunction(Vec<fn(&[&NodeValue]) -> NodeValue>),
     Executor(fn() -> Box<dyn NodeExecutor>),
+
+    /// Like Executor, for defs whose executors need data that a plain fn can't hold,
+    /// such as defs loaded at runtime.
+    ExecutorFactory(Arc<dyn NodeExecutorFactory>),
     OutputDevice(NodeDefOutputRunner),
 }
 
+/// Sends a frame of input values to an output device. Called on the device's own thread.
+pub type OutputDeviceFn = Arc<dyn Fn(Vec<&NodeValue>) + Send + Sync>;
+
 pub struct NodeDefOutputRunner {
-    pub run: fn(Vec<&NodeValue>),
+    pub run: OutputDeviceFn,
     pub device: OutputDevice,
 }
 
@@ -55,6 +105,65 @@ pub struct OutputDevice {
     pub name: String,
 }
 
+impl NodeInputDef {
+    /// True if the value can be passed to this input without any conversion.
+    pub fn accepts(&self, value: &NodeValue) -> bool {
+        if !self.allowed_types.contains(&NodeValueType::from(value)) {
+            return false;
+        }
+        if let Some(composite_type) = &self.composite_type {
+            if NodeCompositeType::of(value) != *composite_type {
+                return false;
+            }
+        }
+        match (value, &self.enum_def) {
+            (NodeValue::Enumeration(enum_value), Some(enum_def)) => enum_value.is_of(enum_def),
+            _ => true,
+        }
+    }
+
+    /// True if the output can be wired to this input without any conversion. Inputs
+    /// with a composite type only accept outputs known to have the same one, since an
+    /// output without one could produce a List or Record of anything.
+    pub fn accepts_output(&self, output: &NodeOutputDef) -> bool {
+        if !self.allowed_types.contains(&output.output_type) {
+            return false;
+        }
+        let composite = matches!(
+            output.output_type,
+            NodeValueType::List | NodeValueType::Record
+        );
+        if let Some(input_type) = &self.composite_type {
+            if composite && output.composite_type.as_ref() != Some(input_type) {
+                return false;
+            }
+        }
+        match (&output.enum_def, &self.enum_def) {
+            (Some(output_enum), Some(input_enum)) => output_enum == input_enum,
+            _ => true,
+        }
+    }
+}
+
+impl NodeOutputDef {
+    /// True if the value is one this output says it produces, such as for checking
+    /// the outputs of nodes that can't be trusted to keep to their defs.
+    pub fn produces(&self, value: &NodeValue) -> bool {
+        if NodeValueType::from(value) != self.output_type {
+            return false;
+        }
+        if let Some(composite_type) = &self.composite_type {
+            if NodeCompositeType::of(value) != *composite_type {
+                return false;
+            }
+        }
+        match (value, &self.enum_def) {
+            (NodeValue::Enumeration(enum_value), Some(enum_def)) => enum_value.is_of(enum_def),
+            _ => true,
+        }
+    }
+}
+
 impl fmt::Debug for NodeDefRunner {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str("[Node Runner]")
@@ -68,7 +177,61 @@ impl std::cmp::PartialEq for NodeDefRunner {
     }
 }
 
+/// Information about the current execution of a graph, shared by every Node in it.
+#[derive(Debug, Default)]
+pub struct ExecutionContext {
+    /// Time since the graph started executing. Nodes that change over time should
+    /// use this rather than counting executions, so that they behave the same at any
+    /// frame rate and can be tested deterministically.
+    pub elapsed: NodeDuration,
+
+    /// The graph's musical clock as it was at the start of this execution.
+    pub tempo: TempoClock,
+
+    /// Programs backing the graph's Shader values. Nodes that create shaders
+    /// register them here, and Nodes that draw shaders look them up here.
+    pub shaders: ShaderRegistry,
+
+    /// Where the installation's LEDs are, for Nodes that sample effects per fixture.
+    pub pixel_map: Arc<PixelMap>,
+
+    /// Changes to the clock requested by Nodes during this execution. They are
+    /// applied once every Node has run, so all Nodes see the same clock.
+    tempo_changes: Mutex<Vec<TempoChange>>,
+}
+
+impl ExecutionContext {
+    pub fn new(
+        elapsed: NodeDuration,
+        tempo: TempoClock,
+        shaders: ShaderRegistry,
+        pixel_map: Arc<PixelMap>,
+    ) -> ExecutionContext {
+        ExecutionContext {
+            elapsed,
+            tempo,
+            shaders,
+            pixel_map,
+            tempo_changes: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn request_tempo_change(&self, change: TempoChange) {
+        self.tempo_changes.lock().push(change);
+    }
+
+    /// Changes requested during the execution, in the order they were requested.
+    pub fn into_tempo_changes(self) -> Vec<TempoChange> {
+        self.tempo_changes.into_inner()
+    }
+}
+
 pub trait NodeExecutor: Send + Sync {
-    fn prepare(&self, enabled_outputs: &Vec<bool>);
-    fn execute(&self, inputs: Vec<&NodeValue>) -> Vec<NodeValue>;
+    fn prepare(&self, enabled_outputs: &[bool]);
+    fn execute(&self, inputs: Vec<&NodeValue>, context: &ExecutionContext) -> Vec<NodeValue>;
+}
+
+/// Creates a new NodeExecutor for each Node of a def. See NodeDefRunner::ExecutorFactory.
+pub trait NodeExecutorFactory: Send + Sync {
+    fn create_executor(&self) -> Box<dyn NodeExecutor>;
 }
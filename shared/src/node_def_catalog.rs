@@ -0,0 +1,444 @@
+//! Everything about the registered NodeDefs that a client needs to work with them
+//! without running them, such as a node editor building its palette and checking
+//! connections offline.
+//!
+//! A catalog is exported with `serialization::to_json`, and looks like:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "data": {
+//!     "defs": [
+//!       {
+//!         "id": "core.magnitude.gamma",
+//!         "version": "1.0.0",
+//!         "desc": { "name": "Gamma", "description": "Raises a magnitude to a power..." },
+//!         "inputs": [
+//!           {
+//!             "desc": { "name": "gamma", "description": "Power to raise the magnitude to" },
+//!             "allowed_types": ["UnconstrainedMagnitude"],
+//!             "required": false,
+//!             "enum_def": null,
+//!             "composite_type": null,
+//!             "default": { "UnconstrainedMagnitude": 2.2 }
+//!           }
+//!         ],
+//!         "outputs": [
+//!           {
+//!             "desc": { "name": "result", "description": "Resulting magnitude" },
+//!             "output_type": "ConstrainedMagnitude",
+//!             "enum_def": null,
+//!             "composite_type": null
+//!           }
+//!         ],
+//!         "output_device": null
+//!       }
+//!     ]
+//!   }
+//! }
+//! ```
+//!
+//! Defs are sorted by id, then from oldest to newest version. Every field is described
+//! in the JSON Schema returned by `schema`, which lists value types straight from
+//! NodeValueType. Numbers that can be infinite or NaN, such as UnconstrainedMagnitudes
+//! and vector components, are written as the strings `"inf"`, `"-inf"` and `"NaN"` when
+//! they are.
+
+use super::node_def::{
+    NodeDef, NodeDefBasicDescription, NodeDefRunner, NodeInputDef, NodeOutputDef,
+};
+use super::node_def_registry::NodeDefVersion;
+use super::node_value::NodeValueType;
+use super::serialization::FORMAT_VERSION;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use strum::IntoEnumIterator;
+
+/// Metadata of every NodeDef in a registry, minus their runners.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct NodeDefCatalog {
+    pub defs: Vec<NodeDefCatalogEntry>,
+}
+
+/// Metadata of one version of a NodeDef.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct NodeDefCatalogEntry {
+    /// Namespaced name the def is registered under, such as `core.magnitude.gamma`.
+    pub id: String,
+    pub version: NodeDefVersion,
+    pub desc: NodeDefBasicDescription,
+    pub inputs: Vec<NodeInputDef>,
+    pub outputs: Vec<NodeOutputDef>,
+
+    /// Name of the device that Nodes of this def send their inputs to, if they are
+    /// output devices. Output devices have no outputs of their own.
+    pub output_device: Option<String>,
+}
+
+impl NodeDefCatalogEntry {
+    pub fn new(id: &str, version: NodeDefVersion, def: &NodeDef) -> NodeDefCatalogEntry {
+        let output_device = match &def.runner {
+            NodeDefRunner::OutputDevice(runner) => Some(runner.device.name.clone()),
+            _ => None,
+        };
+        NodeDefCatalogEntry {
+            id: id.to_string(),
+            version,
+            desc: def.desc.clone(),
+            inputs: def.inputs.clone(),
+            outputs: def.outputs.clone(),
+            output_device,
+        }
+    }
+}
+
+impl NodeDefCatalog {
+    /// The latest version of a def.
+    pub fn get(&self, id: &str) -> Option<&NodeDefCatalogEntry> {
+        self.defs.iter().rev().find(|entry| entry.id == id)
+    }
+}
+
+/// JSON Schema (draft 2020-12) of a catalog exported with `serialization::to_json`.
+pub fn schema() -> Value {
+    let value_types: Vec<String> = NodeValueType::iter()
+        .map(|value_type| format!("{:?}", value_type))
+        .collect();
+    let nullable = |schema: Value| json!({ "oneOf": [{ "type": "null" }, schema] });
+    let vector = |components: &[&str]| {
+        let properties: serde_json::Map<String, Value> = components
+            .iter()
+            .map(|component| {
+                (
+                    component.to_string(),
+                    json!({ "$ref": "#/$defs/any_number" }),
+                )
+            })
+            .collect();
+        json!({
+            "type": "object",
+            "required": components,
+            "additionalProperties": false,
+            "properties": properties
+        })
+    };
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Proton NodeDef catalog",
+        "description": "Every NodeDef available on a Proton server, minus the code that runs them.",
+        "type": "object",
+        "required": ["version", "data"],
+        "additionalProperties": false,
+        "properties": {
+            "version": {
+                "description": "Version of the serialization format.",
+                "const": FORMAT_VERSION
+            },
+            "data": { "$ref": "#/$defs/catalog" }
+        },
+        "$defs": {
+            "catalog": {
+                "type": "object",
+                "required": ["defs"],
+                "additionalProperties": false,
+                "properties": {
+                    "defs": {
+                        "description": "Every registered version of every def, sorted by id and then from oldest to newest version.",
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/def" }
+                    }
+                }
+            },
+            "def": {
+                "type": "object",
+                "required": ["id", "version", "desc", "inputs", "outputs", "output_device"],
+                "additionalProperties": false,
+                "properties": {
+                    "id": {
+                        "description": "Namespaced name of the def, which Nodes refer to as either `id` for its latest version or `id@version` for the latest compatible one.",
+                        "type": "string",
+                        "pattern": "^[A-Za-z0-9_]+(\\.[A-Za-z0-9_]+)+$"
+                    },
+                    "version": {
+                        "description": "Semantic version. Versions with the same major version (or, before 1.0.0, the same minor version) are compatible.",
+                        "type": "string",
+                        "pattern": "^[0-9]+\\.[0-9]+\\.[0-9]+$"
+                    },
+                    "desc": { "$ref": "#/$defs/description" },
+                    "inputs": {
+                        "description": "Inputs, in the order Nodes pass them.",
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/input" }
+                    },
+                    "outputs": {
+                        "description": "Outputs, in the order Nodes produce them.",
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/output" }
+                    },
+                    "output_device": nullable(json!({
+                        "description": "Name of the device that Nodes of this def send their inputs to.",
+                        "type": "string"
+                    }))
+                }
+            },
+            "description": {
+                "description": "Human-readable information about a def or one of its inputs or outputs.",
+                "type": "object",
+                "required": ["name", "description"],
+                "additionalProperties": false,
+                "properties": {
+                    "name": { "type": "string" },
+                    "description": { "type": "string" }
+                }
+            },
+            "input": {
+                "type": "object",
+                "required": ["desc", "allowed_types", "required", "enum_def", "composite_type"],
+                "additionalProperties": false,
+                "properties": {
+                    "desc": { "$ref": "#/$defs/description" },
+                    "allowed_types": {
+                        "description": "Types that can be wired to the input without any conversion.",
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/value_type" }
+                    },
+                    "required": { "type": "boolean" },
+                    "enum_def": nullable(json!({
+                        "description": "If set, Enumeration values passed to the input must be options of this enum.",
+                        "$ref": "#/$defs/enum_def"
+                    })),
+                    "composite_type": nullable(json!({
+                        "description": "If set, List and Record values passed to the input must have exactly this type.",
+                        "$ref": "#/$defs/composite_type"
+                    })),
+                    "default": nullable(json!({
+                        "description": "Value given to the def when a Node leaves the input out. Inputs can only be left out after the last one the Node sets.",
+                        "$ref": "#/$defs/value"
+                    }))
+                }
+            },
+            "output": {
+                "type": "object",
+                "required": ["desc", "output_type", "enum_def", "composite_type"],
+                "additionalProperties": false,
+                "properties": {
+                    "desc": { "$ref": "#/$defs/description" },
+                    "output_type": { "$ref": "#/$defs/value_type" },
+                    "enum_def": nullable(json!({
+                        "description": "If set, Enumeration values from the output are always options of this enum.",
+                        "$ref": "#/$defs/enum_def"
+                    })),
+                    "composite_type": nullable(json!({
+                        "description": "If set, List and Record values from the output always have exactly this type.",
+                        "$ref": "#/$defs/composite_type"
+                    }))
+                }
+            },
+            "value_type": { "enum": value_types },
+            "enum_def": {
+                "type": "object",
+                "required": ["name", "options"],
+                "additionalProperties": false,
+                "properties": {
+                    "name": { "type": "string" },
+                    "options": { "type": "array", "items": { "type": "string" } }
+                }
+            },
+            "composite_type": {
+                "oneOf": [
+                    {
+                        "description": "Any type other than List or Record.",
+                        "type": "object",
+                        "required": ["Simple"],
+                        "additionalProperties": false,
+                        "properties": { "Simple": { "$ref": "#/$defs/value_type" } }
+                    },
+                    {
+                        "description": "List where every item has the given type.",
+                        "type": "object",
+                        "required": ["List"],
+                        "additionalProperties": false,
+                        "properties": { "List": { "$ref": "#/$defs/composite_type" } }
+                    },
+                    {
+                        "description": "Record with the given fields, in order.",
+                        "type": "object",
+                        "required": ["Record"],
+                        "additionalProperties": false,
+                        "properties": {
+                            "Record": {
+                                "type": "array",
+                                "items": {
+                                    "type": "array",
+                                    "prefixItems": [
+                                        { "type": "string" },
+                                        { "$ref": "#/$defs/composite_type" }
+                                    ],
+                                    "minItems": 2,
+                                    "maxItems": 2
+                                }
+                            }
+                        }
+                    }
+                ]
+            },
+            "value": {
+                "description": "A NodeValue, as an object with the value's type as its only key.",
+                "type": "object",
+                "minProperties": 1,
+                "maxProperties": 1,
+                "propertyNames": { "$ref": "#/$defs/value_type" },
+                "properties": {
+                    "UnconstrainedMagnitude": { "$ref": "#/$defs/any_number" },
+                    "Vector2D": vector(&["x", "y"]),
+                    "Vector3D": vector(&["x", "y", "z"])
+                }
+            },
+            "any_number": {
+                "description": "A number, or one of the strings \"NaN\", \"inf\" and \"-inf\" for numbers that JSON can't represent.",
+                "oneOf": [
+                    { "type": "number" },
+                    { "enum": ["NaN", "inf", "-inf"] }
+                ]
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node_def::{NodeDefOutputRunner, OutputDevice};
+    use crate::node_def_registry::NodeDefRegistry;
+    use crate::node_value::{NodeValue, NodeVector2, NodeVector3};
+    use crate::serialization::{from_json, to_json};
+    use std::sync::Arc;
+
+    fn describe(name: &str) -> NodeDefBasicDescription {
+        NodeDefBasicDescription {
+            name: name.to_string(),
+            description: String::new(),
+        }
+    }
+
+    fn make_registry() -> NodeDefRegistry {
+        let registry = NodeDefRegistry::new();
+        let scale = || NodeDef {
+            desc: describe("Scale"),
+            inputs: vec![NodeInputDef {
+                desc: describe("amount"),
+                allowed_types: vec![NodeValueType::UnconstrainedMagnitude],
+                required: false,
+                enum_def: None,
+                composite_type: None,
+                default: Some(NodeValue::UnconstrainedMagnitude(1.0)),
+            }],
+            outputs: vec![NodeOutputDef {
+                desc: describe("result"),
+                output_type: NodeValueType::UnconstrainedMagnitude,
+                enum_def: None,
+                composite_type: None,
+            }],
+            runner: NodeDefRunner::Function(|_| vec![]),
+        };
+        registry
+            .register_version(
+                "test.scale".to_string(),
+                NodeDefVersion::new(2, 0, 0),
+                scale(),
+            )
+            .unwrap();
+        registry
+            .register("test.scale".to_string(), scale())
+            .unwrap();
+        registry
+            .register(
+                "test.lamp".to_string(),
+                NodeDef {
+                    desc: describe("Lamp"),
+                    inputs: vec![],
+                    outputs: vec![],
+                    runner: NodeDefRunner::OutputDevice(NodeDefOutputRunner {
+                        run: Arc::new(|_: Vec<&NodeValue>| {}),
+                        device: OutputDevice {
+                            name: "lamp".to_string(),
+                        },
+                    }),
+                },
+            )
+            .unwrap();
+        registry
+    }
+
+    #[test]
+    fn lists_every_version_in_order() {
+        let catalog = make_registry().catalog();
+        let listed: Vec<(&str, String)> = catalog
+            .defs
+            .iter()
+            .map(|entry| (entry.id.as_str(), entry.version.to_string()))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                ("test.lamp", "1.0.0".to_string()),
+                ("test.scale", "1.0.0".to_string()),
+                ("test.scale", "2.0.0".to_string()),
+            ]
+        );
+        assert_eq!(catalog.defs[0].output_device, Some("lamp".to_string()));
+        assert_eq!(
+            catalog.get("test.scale").unwrap().version,
+            NodeDefVersion::new(2, 0, 0)
+        );
+
+        let json = to_json(&catalog).unwrap();
+        assert!(json.contains("\"version\": \"2.0.0\""));
+        assert_eq!(from_json::<NodeDefCatalog>(&json).unwrap(), catalog);
+    }
+
+    #[test]
+    fn exports_catalogs_that_match_the_schema() {
+        let validator = jsonschema::validator_for(&schema()).unwrap();
+        let validate = |catalog: &NodeDefCatalog| {
+            let json: Value = serde_json::from_str(&to_json(catalog).unwrap()).unwrap();
+            validator.validate(&json).map_err(|error| error.to_string())
+        };
+        let mut catalog = make_registry().catalog();
+        assert_eq!(validate(&catalog), Ok(()));
+
+        let mut set_default = |value: NodeValue| {
+            catalog.defs[1].inputs[0].default = Some(value);
+            validate(&catalog)
+        };
+        for value in &[
+            NodeValue::UnconstrainedMagnitude(f64::NEG_INFINITY),
+            NodeValue::UnconstrainedMagnitude(f64::NAN),
+            NodeValue::Vector2D(NodeVector2::new(f64::INFINITY, 1.0)),
+            NodeValue::Vector3D(NodeVector3::new(0.0, f64::NAN, 1.0)),
+        ] {
+            assert_eq!(set_default(value.clone()), Ok(()), "{:?}", value);
+        }
+
+        let mut json: Value = serde_json::from_str(&to_json(&catalog).unwrap()).unwrap();
+        json["data"]["defs"][1]["inputs"][0]["default"] = json!({ "Vector3D": { "x": 1 } });
+        assert!(!validator.is_valid(&json));
+        json["data"]["defs"][1]["inputs"][0]["default"] =
+            json!({ "UnconstrainedMagnitude": "infinity" });
+        assert!(!validator.is_valid(&json));
+        json["data"]["defs"][1]["inputs"][0]["default"] = Value::Null;
+        json["data"]["defs"][1]["color"] = json!("red");
+        assert!(!validator.is_valid(&json));
+    }
+
+    #[test]
+    fn schema_lists_every_value_type() {
+        let schema = schema();
+        let value_types = schema["$defs"]["value_type"]["enum"].as_array().unwrap();
+        assert_eq!(value_types.len(), NodeValueType::iter().count());
+        for value_type in NodeValueType::iter() {
+            assert!(value_types.contains(&json!(format!("{:?}", value_type))));
+        }
+    }
+}
@@ -1,43 +1,486 @@
-use super::node_def::NodeDef;
-use parking_lot::{MappedRwLockReadGuard, RwLock, RwLockReadGuard};
-use std::collections::HashMap;
+use super::node_def::{NodeDef, NodeDefRunner};
+use super::node_def_catalog::{NodeDefCatalog, NodeDefCatalogEntry};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
 
 /// Clonable map of implementations for NodeDefs.
+///
+/// NodeDefs are registered under a namespaced name, made of dot-separated parts such as
+/// `core.math.add` or `acme.lasers.scan`, where the first part is the namespace of
+/// whoever provides the def. Each name can have several versions registered at once,
+/// so that shows saved against an older version of a def keep working after a newer one
+/// is added. Nodes refer to a def as either `name`, for its latest version, or
+/// `name@version`, for the latest version that is compatible with the given one.
+///
+/// Defs are handed out behind an Arc, so whoever resolved one can keep running it after
+/// it is replaced or unregistered.
 #[derive(Clone)]
 pub struct NodeDefRegistry {
     internal: Arc<NodeDefRegistryInternal>,
 }
 
 struct NodeDefRegistryInternal {
-    map: RwLock<HashMap<String, NodeDef>>,
+    map: RwLock<HashMap<String, BTreeMap<NodeDefVersion, Arc<NodeDef>>>>,
+}
+
+/// Semantic version of a NodeDef. Versions with the same major version (or, before
+/// 1.0.0, the same minor version) are compatible, so a newer one can stand in for an
+/// older one. Serialized as a string such as `"1.2.3"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct NodeDefVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeDefRegistryError {
+    /// Names need a namespace and at least one more part, separated by dots. Each part
+    /// is made of ASCII letters, digits and underscores.
+    InvalidName(String),
+    InvalidVersion(String),
+    AlreadyRegistered {
+        name: String,
+        version: NodeDefVersion,
+    },
+
+    /// No registered def matches the name, or the version it asks for.
+    NotFound(String),
+
+    /// The def has a PerOutputFunction runner without exactly one function per output.
+    WrongOutputFunctionCount {
+        name: String,
+        outputs: usize,
+        functions: usize,
+    },
+
+    /// The def gives an input a default after an optional input without one. Nodes
+    /// fill left-out inputs by position, so they could not leave out the optional
+    /// input while still getting the later default.
+    DefaultAfterOptionalInput {
+        name: String,
+        input_index: usize,
+    },
+}
+
+impl fmt::Display for NodeDefRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeDefRegistryError::InvalidName(name) => write!(
+                f,
+                "Invalid node def name {:?}, which should look like namespace.name",
+                name
+            ),
+            NodeDefRegistryError::InvalidVersion(version) => write!(
+                f,
+                "Invalid node def version {:?}, which should look like 1.2.3",
+                version
+            ),
+            NodeDefRegistryError::AlreadyRegistered { name, version } => {
+                write!(f, "{}@{} already registered as a node def", name, version)
+            }
+            NodeDefRegistryError::NotFound(name) => write!(f, "No such node type: {}", name),
+            NodeDefRegistryError::WrongOutputFunctionCount {
+                name,
+                outputs,
+                functions,
+            } => write!(
+                f,
+                "Node def {} has {} outputs but {} output functions",
+                name, outputs, functions
+            ),
+            NodeDefRegistryError::DefaultAfterOptionalInput { name, input_index } => write!(
+                f,
+                "Node def {} gives input {} a default after an optional input without one",
+                name, input_index
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NodeDefRegistryError {}
+
+impl NodeDefVersion {
+    /// Version of defs registered without one.
+    pub const INITIAL: NodeDefVersion = NodeDefVersion::new(1, 0, 0);
+
+    pub const fn new(major: u32, minor: u32, patch: u32) -> NodeDefVersion {
+        NodeDefVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// True if this version can be used in place of the requested one.
+    pub fn is_compatible_with(&self, requested: &NodeDefVersion) -> bool {
+        let same_series = if requested.major == 0 {
+            self.major == 0 && self.minor == requested.minor
+        } else {
+            self.major == requested.major
+        };
+        same_series && self >= requested
+    }
+}
+
+impl fmt::Display for NodeDefVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for NodeDefVersion {
+    type Err = NodeDefRegistryError;
+
+    fn from_str(version: &str) -> Result<NodeDefVersion, NodeDefRegistryError> {
+        let invalid = || NodeDefRegistryError::InvalidVersion(version.to_string());
+        let parts = version
+            .split('.')
+            .map(|part| {
+                // Plain digits only, without the signs that u32's own parsing allows.
+                if part.bytes().all(|byte| byte.is_ascii_digit()) {
+                    part.parse::<u32>().map_err(|_| invalid())
+                } else {
+                    Err(invalid())
+                }
+            })
+            .collect::<Result<Vec<u32>, _>>()?;
+        match parts.as_slice() {
+            [major, minor, patch] => Ok(NodeDefVersion::new(*major, *minor, *patch)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl From<NodeDefVersion> for String {
+    fn from(version: NodeDefVersion) -> String {
+        version.to_string()
+    }
+}
+
+impl TryFrom<String> for NodeDefVersion {
+    type Error = NodeDefRegistryError;
+
+    fn try_from(version: String) -> Result<NodeDefVersion, NodeDefRegistryError> {
+        version.parse()
+    }
+}
+
+/// Checks that a name is namespaced, such as `core.math.add`.
+pub fn validate_name(name: &str) -> Result<(), NodeDefRegistryError> {
+    let valid_part = |part: &str| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if name.contains('.') && name.split('.').all(valid_part) {
+        Ok(())
+    } else {
+        Err(NodeDefRegistryError::InvalidName(name.to_string()))
+    }
+}
+
+/// Splits a reference to a def into its name and the version it asks for, if any.
+pub fn parse_ref(
+    node_def_ref: &str,
+) -> Result<(&str, Option<NodeDefVersion>), NodeDefRegistryError> {
+    let (name, version) = match node_def_ref.split_once('@') {
+        Some((name, version)) => (name, Some(version.parse()?)),
+        None => (node_def_ref, None),
+    };
+    validate_name(name)?;
+    Ok((name, version))
+}
+
+impl Default for NodeDefRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NodeDefRegistry {
     pub fn new() -> NodeDefRegistry {
-        NodeDefRegistry{
+        NodeDefRegistry {
             internal: Arc::new(NodeDefRegistryInternal {
                 map: RwLock::new(HashMap::new()),
-            })
+            }),
+        }
+    }
+
+    /// Registers the initial version of a def.
+    pub fn register(
+        &self,
+        node_def_name: String,
+        node_def: NodeDef,
+    ) -> Result<(), NodeDefRegistryError> {
+        self.register_version(node_def_name, NodeDefVersion::INITIAL, node_def)
+    }
+
+    /// Registers a version of a def, alongside any other versions already registered.
+    pub fn register_version(
+        &self,
+        node_def_name: String,
+        version: NodeDefVersion,
+        node_def: NodeDef,
+    ) -> Result<(), NodeDefRegistryError> {
+        validate_name(&node_def_name)?;
+        if let NodeDefRunner::PerOutputFunction(funcs) = &node_def.runner {
+            if funcs.len() != node_def.outputs.len() {
+                return Err(NodeDefRegistryError::WrongOutputFunctionCount {
+                    name: node_def_name,
+                    outputs: node_def.outputs.len(),
+                    functions: funcs.len(),
+                });
+            }
+        }
+        let first_without_default = node_def
+            .inputs
+            .iter()
+            .position(|input| !input.required && input.default.is_none());
+        if let Some(first) = first_without_default {
+            let later_default = node_def.inputs[first..]
+                .iter()
+                .position(|input| input.default.is_some());
+            if let Some(offset) = later_default {
+                return Err(NodeDefRegistryError::DefaultAfterOptionalInput {
+                    name: node_def_name,
+                    input_index: first + offset,
+                });
+            }
+        }
+        let mut map = self.internal.map.write();
+        let versions = map.get(&node_def_name);
+        if versions.is_some_and(|versions| versions.contains_key(&version)) {
+            return Err(NodeDefRegistryError::AlreadyRegistered {
+                name: node_def_name,
+                version,
+            });
         }
+        map.entry(node_def_name)
+            .or_default()
+            .insert(version, Arc::new(node_def));
+        Ok(())
     }
 
-    pub fn register(&self, node_def_name: String, node_def: NodeDef) {
-        if self.internal.map.read().contains_key(&node_def_name) {
-            panic!(node_def_name + " already registered as a node def");
+    /// Removes a version of a def, returning it if it was registered.
+    pub fn unregister(&self, node_def_name: &str, version: NodeDefVersion) -> Option<Arc<NodeDef>> {
+        let mut map = self.internal.map.write();
+        let versions = map.get_mut(node_def_name)?;
+        let def = versions.remove(&version);
+        if versions.is_empty() {
+            map.remove(node_def_name);
         }
-        self.internal.map.write().insert(node_def_name, node_def);
+        def
     }
 
-    pub fn get_def(&self, node_def_name: &String) -> MappedRwLockReadGuard<NodeDef> {
-        RwLockReadGuard::map(self.internal.map.read(), |hashmap| {
-            hashmap.get(node_def_name).unwrap_or_else(|| {
-                panic!("No such node type: ".to_owned() + node_def_name);
+    /// Looks up a def by name, or by `name@version` for the latest version that is
+    /// compatible with the given one.
+    pub fn get_def(&self, node_def_ref: &str) -> Result<Arc<NodeDef>, NodeDefRegistryError> {
+        self.resolve(node_def_ref).map(|(_, def)| def)
+    }
+
+    /// Like get_def, but also returns which version the reference resolved to.
+    pub fn resolve(
+        &self,
+        node_def_ref: &str,
+    ) -> Result<(NodeDefVersion, Arc<NodeDef>), NodeDefRegistryError> {
+        let (name, version) = parse_ref(node_def_ref)?;
+        let map = self.internal.map.read();
+        map.get(name)
+            .and_then(|versions| {
+                versions.iter().rev().find(|(registered, _)| {
+                    version.is_none_or(|version| registered.is_compatible_with(&version))
+                })
+            })
+            .map(|(version, def)| (*version, def.clone()))
+            .ok_or_else(|| NodeDefRegistryError::NotFound(node_def_ref.to_string()))
+    }
+
+    /// Versions registered for a name, from oldest to newest.
+    pub fn versions(&self, node_def_name: &str) -> Vec<NodeDefVersion> {
+        match self.internal.map.read().get(node_def_name) {
+            Some(versions) => versions.keys().copied().collect(),
+            None => vec![],
+        }
+    }
+
+    /// Metadata of every registered version of every def, for clients that can't run
+    /// them. See NodeDefCatalog.
+    pub fn catalog(&self) -> NodeDefCatalog {
+        let map = self.internal.map.read();
+        let mut defs: Vec<NodeDefCatalogEntry> = map
+            .iter()
+            .flat_map(|(name, versions)| {
+                versions
+                    .iter()
+                    .map(move |(version, def)| NodeDefCatalogEntry::new(name, *version, def))
             })
-        })
+            .collect();
+        defs.sort_by(|a, b| (&a.id, a.version).cmp(&(&b.id, b.version)));
+        NodeDefCatalog { defs }
     }
 
     pub fn reset(&self) {
         self.internal.map.write().clear()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node_def::{NodeDefBasicDescription, NodeInputDef, NodeOutputDef};
+    use crate::node_value::{NodeValue, NodeValueType};
+
+    fn def(name: &str) -> NodeDef {
+        NodeDef {
+            desc: NodeDefBasicDescription {
+                name: name.to_string(),
+                description: String::new(),
+            },
+            inputs: vec![],
+            outputs: vec![],
+            runner: NodeDefRunner::Function(|_| vec![]),
+        }
+    }
+
+    #[test]
+    fn checks_names_and_duplicates() {
+        let registry = NodeDefRegistry::new();
+        assert!(registry
+            .register("acme.lasers.scan".to_string(), def("Scan"))
+            .is_ok());
+        assert_eq!(
+            registry.register("acme.lasers.scan".to_string(), def("Scan")),
+            Err(NodeDefRegistryError::AlreadyRegistered {
+                name: "acme.lasers.scan".to_string(),
+                version: NodeDefVersion::INITIAL,
+            })
+        );
+        for name in &["scan", "acme..scan", "acme.lasers scan", ".scan"] {
+            assert_eq!(
+                registry.register(name.to_string(), def("Scan")),
+                Err(NodeDefRegistryError::InvalidName(name.to_string()))
+            );
+        }
+        assert_eq!(
+            registry.get_def("acme.lasers.sacn").unwrap_err(),
+            NodeDefRegistryError::NotFound("acme.lasers.sacn".to_string())
+        );
+    }
+
+    #[test]
+    fn checks_output_function_counts() {
+        let registry = NodeDefRegistry::new();
+        let mut per_output = def("Split");
+        per_output.outputs = (0..2)
+            .map(|_| NodeOutputDef {
+                desc: NodeDefBasicDescription {
+                    name: "count".to_string(),
+                    description: String::new(),
+                },
+                output_type: NodeValueType::Count,
+                enum_def: None,
+                composite_type: None,
+            })
+            .collect();
+        per_output.runner = NodeDefRunner::PerOutputFunction(vec![|_| NodeValue::Count(0)]);
+        assert_eq!(
+            registry.register("acme.lasers.split".to_string(), per_output),
+            Err(NodeDefRegistryError::WrongOutputFunctionCount {
+                name: "acme.lasers.split".to_string(),
+                outputs: 2,
+                functions: 1,
+            })
+        );
+        assert!(registry.versions("acme.lasers.split").is_empty());
+    }
+
+    #[test]
+    fn rejects_defaults_after_optional_inputs() {
+        let registry = NodeDefRegistry::new();
+        let input = |required, default| NodeInputDef {
+            desc: NodeDefBasicDescription {
+                name: "count".to_string(),
+                description: String::new(),
+            },
+            allowed_types: vec![NodeValueType::Count],
+            required,
+            enum_def: None,
+            composite_type: None,
+            default,
+        };
+        let mut optional = def("Optional");
+        optional.inputs = vec![
+            input(true, None),
+            input(false, Some(NodeValue::Count(1))),
+            input(false, None),
+            input(false, None),
+        ];
+        registry
+            .register("acme.lasers.optional".to_string(), optional)
+            .unwrap();
+
+        let mut misordered = def("Misordered");
+        misordered.inputs = vec![
+            input(false, None),
+            input(true, None),
+            input(false, Some(NodeValue::Count(1))),
+        ];
+        assert_eq!(
+            registry.register("acme.lasers.misordered".to_string(), misordered),
+            Err(NodeDefRegistryError::DefaultAfterOptionalInput {
+                name: "acme.lasers.misordered".to_string(),
+                input_index: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn resolves_compatible_versions() {
+        let registry = NodeDefRegistry::new();
+        let name = "acme.lasers.scan";
+        for (version, label) in &[("1.0.0", "1.0"), ("1.2.0", "1.2"), ("2.0.1", "2.0")] {
+            let version = version.parse().unwrap();
+            registry
+                .register_version(name.to_string(), version, def(label))
+                .unwrap();
+        }
+        let resolve = |node_def_ref: &str| {
+            registry
+                .get_def(node_def_ref)
+                .map(|def| def.desc.name.clone())
+        };
+        assert_eq!(resolve(name), Ok("2.0".to_string()));
+        assert_eq!(resolve("acme.lasers.scan@1.0.0"), Ok("1.2".to_string()));
+        assert_eq!(
+            registry.resolve(name).unwrap().0,
+            NodeDefVersion::new(2, 0, 1)
+        );
+        assert_eq!(resolve("acme.lasers.scan@2.0.0"), Ok("2.0".to_string()));
+        assert!(resolve("acme.lasers.scan@1.3.0").is_err());
+        assert!(resolve("acme.lasers.scan@3.0.0").is_err());
+        assert_eq!(
+            resolve("acme.lasers.scan@1.0"),
+            Err(NodeDefRegistryError::InvalidVersion("1.0".to_string()))
+        );
+        assert_eq!(registry.versions(name).len(), 3);
+
+        let latest = registry.get_def(name).unwrap();
+        assert!(registry
+            .unregister(name, NodeDefVersion::new(2, 0, 1))
+            .is_some());
+        assert_eq!(resolve(name), Ok("1.2".to_string()));
+        assert_eq!(latest.desc.name, "2.0");
+        assert!(registry
+            .unregister(name, NodeDefVersion::new(2, 0, 1))
+            .is_none());
+
+        let zero = NodeDefVersion::new(0, 2, 0);
+        assert!(NodeDefVersion::new(0, 2, 5).is_compatible_with(&zero));
+        assert!(!NodeDefVersion::new(0, 3, 0).is_compatible_with(&zero));
+    }
+}
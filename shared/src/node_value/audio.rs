@@ -0,0 +1,136 @@
+use super::time::NodeDuration;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::slice::ChunksExact;
+
+/// A block of uncompressed audio. Samples are stored as interleaved frames, where
+/// each frame holds one sample per channel, normalized to the -1 to 1 range.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "AudioBlockFields")]
+pub struct AudioBlock {
+    sample_rate: u32,
+    channels: u16,
+    samples: Vec<f32>,
+}
+
+impl AudioBlock {
+    /// Wraps interleaved samples. Returns None if there are no channels, or if the
+    /// samples do not make up a whole number of frames.
+    pub fn new(sample_rate: u32, channels: u16, samples: Vec<f32>) -> Option<AudioBlock> {
+        if channels == 0 || !samples.len().is_multiple_of(channels as usize) {
+            return None;
+        }
+        Some(AudioBlock {
+            sample_rate,
+            channels,
+            samples,
+        })
+    }
+
+    /// Creates a block of silence with the given number of frames.
+    pub fn silent(sample_rate: u32, channels: u16, frame_count: usize) -> AudioBlock {
+        AudioBlock {
+            sample_rate,
+            channels: channels.max(1),
+            samples: vec![0.0; frame_count * channels.max(1) as usize],
+        }
+    }
+
+    /// Frames per second.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.samples.len() / self.channels as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Length of time the block covers when played back.
+    pub fn duration(&self) -> NodeDuration {
+        if self.sample_rate == 0 {
+            return NodeDuration::ZERO;
+        }
+        NodeDuration::from_micros(self.frame_count() as i64 * 1_000_000 / self.sample_rate as i64)
+    }
+
+    /// All samples, interleaved.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    /// One sample per channel at the given frame, or None if out of bounds.
+    pub fn frame(&self, index: usize) -> Option<&[f32]> {
+        let channels = self.channels as usize;
+        self.samples.get(index * channels..(index + 1) * channels)
+    }
+
+    /// Iterates over frames, each holding one sample per channel.
+    pub fn frames(&self) -> ChunksExact<'_, f32> {
+        self.samples.chunks_exact(self.channels as usize)
+    }
+
+    /// Iterates over the samples of a single channel.
+    pub fn channel(&self, channel: u16) -> impl Iterator<Item = f32> + '_ {
+        self.frames()
+            .filter_map(move |frame| frame.get(channel as usize).copied())
+    }
+
+    /// Averages all channels together into a single channel.
+    pub fn to_mono(&self) -> Vec<f32> {
+        let channels = self.channels as f32;
+        self.frames()
+            .map(|frame| frame.iter().sum::<f32>() / channels)
+            .collect()
+    }
+}
+
+impl Default for AudioBlock {
+    /// An empty mono block at 48kHz.
+    fn default() -> AudioBlock {
+        AudioBlock::silent(48000, 1, 0)
+    }
+}
+
+/// Fields of a deserialized AudioBlock, before checking they are consistent.
+#[derive(Deserialize)]
+struct AudioBlockFields {
+    sample_rate: u32,
+    channels: u16,
+    samples: Vec<f32>,
+}
+
+impl TryFrom<AudioBlockFields> for AudioBlock {
+    type Error = &'static str;
+
+    fn try_from(fields: AudioBlockFields) -> Result<AudioBlock, Self::Error> {
+        AudioBlock::new(fields.sample_rate, fields.channels, fields.samples)
+            .ok_or("AudioBlock samples do not make up whole frames")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_interleaved_frames() {
+        let block = AudioBlock::new(4, 2, vec![0.0, 1.0, 0.5, -0.5, 1.0, 0.0]).unwrap();
+        assert_eq!(block.frame_count(), 3);
+        assert_eq!(block.frame(1), Some(&[0.5, -0.5][..]));
+        assert_eq!(block.frame(3), None);
+        assert_eq!(block.channel(1).collect::<Vec<f32>>(), vec![1.0, -0.5, 0.0]);
+        assert_eq!(block.to_mono(), vec![0.5, 0.0, 0.5]);
+        assert_eq!(block.duration(), NodeDuration::from_millis(750));
+
+        assert!(AudioBlock::new(4, 2, vec![0.0; 3]).is_none());
+        assert!(AudioBlock::new(4, 0, vec![]).is_none());
+    }
+}
@@ -0,0 +1,248 @@
+use super::color::NodeColor;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::slice::{ChunksExact, ChunksExactMut};
+
+/// 1-dimensional image, such as the colors of each LED on a strip. Stored uncompressed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Bitmap1D {
+    pixels: Vec<NodeColor>,
+}
+
+/// 2-dimensional image. Pixels are stored uncompressed in a single contiguous buffer,
+/// one row after another, so rows can be sliced out or processed in parallel cheaply.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(try_from = "Bitmap2DFields")]
+pub struct Bitmap2D {
+    width: usize,
+    height: usize,
+    pixels: Vec<NodeColor>,
+}
+
+impl Bitmap1D {
+    /// Creates a transparent bitmap with the given number of pixels.
+    pub fn new(width: usize) -> Bitmap1D {
+        Bitmap1D::filled(width, NodeColor::TRANSPARENT)
+    }
+
+    /// Creates a bitmap where every pixel is the given color.
+    pub fn filled(width: usize, color: NodeColor) -> Bitmap1D {
+        Bitmap1D {
+            pixels: vec![color; width],
+        }
+    }
+
+    pub fn from_pixels(pixels: Vec<NodeColor>) -> Bitmap1D {
+        Bitmap1D { pixels }
+    }
+
+    pub fn width(&self) -> usize {
+        self.pixels.len()
+    }
+
+    pub fn get(&self, x: usize) -> Option<&NodeColor> {
+        self.pixels.get(x)
+    }
+
+    pub fn get_mut(&mut self, x: usize) -> Option<&mut NodeColor> {
+        self.pixels.get_mut(x)
+    }
+
+    pub fn pixels(&self) -> &[NodeColor] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [NodeColor] {
+        &mut self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<NodeColor> {
+        self.pixels
+    }
+}
+
+impl From<Vec<NodeColor>> for Bitmap1D {
+    fn from(pixels: Vec<NodeColor>) -> Bitmap1D {
+        Bitmap1D::from_pixels(pixels)
+    }
+}
+
+impl Bitmap2D {
+    /// Creates a transparent bitmap with the given dimensions. Panics if the number of
+    /// pixels would overflow a usize.
+    pub fn new(width: usize, height: usize) -> Bitmap2D {
+        Bitmap2D::filled(width, height, NodeColor::TRANSPARENT)
+    }
+
+    /// Creates a bitmap where every pixel is the given color. Panics if the number of
+    /// pixels would overflow a usize.
+    pub fn filled(width: usize, height: usize, color: NodeColor) -> Bitmap2D {
+        let len = width
+            .checked_mul(height)
+            .unwrap_or_else(|| panic!("Bitmap2D of {}x{} pixels is too large", width, height));
+        Bitmap2D {
+            width,
+            height,
+            pixels: vec![color; len],
+        }
+    }
+
+    /// Wraps a row-major pixel buffer. Returns None if the buffer does not contain
+    /// exactly `width * height` pixels, including when that would overflow.
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<NodeColor>) -> Option<Bitmap2D> {
+        if width.checked_mul(height) != Some(pixels.len()) {
+            return None;
+        }
+        Some(Bitmap2D {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Copies a list of rows into a single buffer. Returns None if the rows are not
+    /// all the same length.
+    pub fn from_rows(rows: &[Vec<NodeColor>]) -> Option<Bitmap2D> {
+        let width = rows.first().map_or(0, |row| row.len());
+        if rows.iter().any(|row| row.len() != width) {
+            return None;
+        }
+        Bitmap2D::from_pixels(width, rows.len(), rows.concat())
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&NodeColor> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.pixels.get(y * self.width + x)
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut NodeColor> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.pixels.get_mut(y * self.width + x)
+    }
+
+    /// All pixels in row-major order.
+    pub fn pixels(&self) -> &[NodeColor] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [NodeColor] {
+        &mut self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<NodeColor> {
+        self.pixels
+    }
+
+    /// Pixels of a single row, or None if the row is out of bounds.
+    pub fn row(&self, y: usize) -> Option<&[NodeColor]> {
+        if y >= self.height {
+            return None;
+        }
+        Some(&self.pixels[y * self.width..(y + 1) * self.width])
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> Option<&mut [NodeColor]> {
+        if y >= self.height {
+            return None;
+        }
+        Some(&mut self.pixels[y * self.width..(y + 1) * self.width])
+    }
+
+    /// Iterates over rows from top to bottom. A bitmap with a width of 0 has no rows.
+    pub fn rows(&self) -> ChunksExact<'_, NodeColor> {
+        self.pixels.chunks_exact(self.width.max(1))
+    }
+
+    /// Iterates mutably over rows from top to bottom. A bitmap with a width of 0 has
+    /// no rows.
+    pub fn rows_mut(&mut self) -> ChunksExactMut<'_, NodeColor> {
+        self.pixels.chunks_exact_mut(self.width.max(1))
+    }
+
+    /// Copies a single row out as a Bitmap1D.
+    pub fn row_bitmap(&self, y: usize) -> Option<Bitmap1D> {
+        self.row(y).map(|row| Bitmap1D::from_pixels(row.to_vec()))
+    }
+
+    /// Copies the pixels out into a separate Vec per row.
+    pub fn to_rows(&self) -> Vec<Vec<NodeColor>> {
+        self.rows().map(|row| row.to_vec()).collect()
+    }
+}
+
+impl From<Bitmap1D> for Bitmap2D {
+    /// Treats the strip as an image with a single row.
+    fn from(bitmap: Bitmap1D) -> Bitmap2D {
+        Bitmap2D {
+            width: bitmap.width(),
+            height: 1,
+            pixels: bitmap.into_pixels(),
+        }
+    }
+}
+
+/// Fields of a deserialized Bitmap2D, before checking they are consistent.
+#[derive(Deserialize)]
+struct Bitmap2DFields {
+    width: usize,
+    height: usize,
+    pixels: Vec<NodeColor>,
+}
+
+impl TryFrom<Bitmap2DFields> for Bitmap2D {
+    type Error = &'static str;
+
+    fn try_from(fields: Bitmap2DFields) -> Result<Bitmap2D, Self::Error> {
+        Bitmap2D::from_pixels(fields.width, fields.height, fields.pixels)
+            .ok_or("Bitmap2D pixel count does not match its dimensions")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slices_rows_from_contiguous_buffer() {
+        let red = NodeColor::opaque(u16::MAX, 0, 0);
+        let mut bitmap = Bitmap2D::new(3, 2);
+        *bitmap.get_mut(1, 1).unwrap() = red;
+
+        assert_eq!(bitmap.pixels().len(), 6);
+        assert_eq!(bitmap.row(0).unwrap(), &[NodeColor::TRANSPARENT; 3]);
+        assert_eq!(
+            bitmap.row(1).unwrap(),
+            &[NodeColor::TRANSPARENT, red, NodeColor::TRANSPARENT]
+        );
+        assert_eq!(bitmap.row(2), None);
+        assert_eq!(bitmap.get(3, 0), None);
+        assert_eq!(bitmap.rows().count(), 2);
+    }
+
+    #[test]
+    fn converts_from_rows() {
+        let red = NodeColor::opaque(u16::MAX, 0, 0);
+        let rows = vec![vec![red, NodeColor::BLACK], vec![NodeColor::WHITE, red]];
+        let bitmap = Bitmap2D::from_rows(&rows).unwrap();
+        assert_eq!(bitmap.width(), 2);
+        assert_eq!(bitmap.height(), 2);
+        assert_eq!(bitmap.get(0, 1), Some(&NodeColor::WHITE));
+        assert_eq!(bitmap.to_rows(), rows);
+
+        assert_eq!(Bitmap2D::from_rows(&[vec![red], vec![]]), None);
+        assert_eq!(Bitmap2D::from_pixels(2, 2, vec![red; 3]), None);
+        assert_eq!(Bitmap2D::from_pixels(usize::MAX, 2, vec![]), None);
+    }
+}
@@ -0,0 +1,436 @@
+use serde::{Deserialize, Serialize};
+
+/// An RGB color with an alpha channel. Supports 16-bits per channel to allow for HDR
+/// content or colors on devices like RGB LEDs that may have color accuracy beyond that
+/// of most monitors. Channels are sRGB-encoded and alpha is straight (not premultiplied),
+/// so values line up with the hex colors artists are used to. Convert to a LinearColor
+/// before doing any math on colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct NodeColor {
+    pub r: u16,
+    pub g: u16,
+    pub b: u16,
+    pub a: u16,
+}
+
+/// A color in linear light with straight alpha. Channels are not clamped, so values
+/// above 1 can represent HDR intensities.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinearColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+/// Hue (in degrees, 0 to 360), saturation and value, each other channel from 0 to 1.
+/// Defined over sRGB-encoded channels, matching common color pickers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct HsvColor {
+    pub h: f32,
+    pub s: f32,
+    pub v: f32,
+    pub a: f32,
+}
+
+/// Hue (in degrees, 0 to 360), saturation and lightness, each other channel from 0 to 1.
+/// Defined over sRGB-encoded channels, matching common color pickers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct HslColor {
+    pub h: f32,
+    pub s: f32,
+    pub l: f32,
+    pub a: f32,
+}
+
+/// Ways of combining a source color with the backdrop it is drawn over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// The source replaces the backdrop.
+    Normal,
+
+    /// The source light is added to the backdrop light.
+    Add,
+
+    /// The source darkens the backdrop by multiplying it.
+    Multiply,
+
+    /// The source brightens the backdrop, the inverse of multiplying the inverses.
+    Screen,
+}
+
+/// Green output of an amber emitter relative to its red output, in linear light.
+pub const AMBER_GREEN_RATIO: f32 = 0.5;
+
+const CHANNEL_MAX: f32 = u16::MAX as f32;
+
+fn channel_to_f32(channel: u16) -> f32 {
+    channel as f32 / CHANNEL_MAX
+}
+
+fn channel_from_f32(channel: f32) -> u16 {
+    (channel.clamp(0.0, 1.0) * CHANNEL_MAX).round() as u16
+}
+
+/// Converts an sRGB-encoded channel value to linear light.
+pub fn srgb_to_linear(channel: f32) -> f32 {
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear light channel value to sRGB encoding.
+pub fn linear_to_srgb(channel: f32) -> f32 {
+    if channel <= 0.003_130_8 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl NodeColor {
+    pub const BLACK: NodeColor = NodeColor::new(0, 0, 0, u16::MAX);
+    pub const WHITE: NodeColor = NodeColor::new(u16::MAX, u16::MAX, u16::MAX, u16::MAX);
+    pub const TRANSPARENT: NodeColor = NodeColor::new(0, 0, 0, 0);
+
+    pub const fn new(r: u16, g: u16, b: u16, a: u16) -> NodeColor {
+        NodeColor { r, g, b, a }
+    }
+
+    /// Creates a fully opaque color.
+    pub const fn opaque(r: u16, g: u16, b: u16) -> NodeColor {
+        NodeColor::new(r, g, b, u16::MAX)
+    }
+
+    /// Creates a color from sRGB-encoded channels in the 0 to 1 range. Out of range
+    /// values are clamped.
+    pub fn from_srgb_f32(r: f32, g: f32, b: f32, a: f32) -> NodeColor {
+        NodeColor::new(
+            channel_from_f32(r),
+            channel_from_f32(g),
+            channel_from_f32(b),
+            channel_from_f32(a),
+        )
+    }
+
+    /// Returns the sRGB-encoded channels in the 0 to 1 range, as (r, g, b, a).
+    pub fn to_srgb_f32(&self) -> (f32, f32, f32, f32) {
+        (
+            channel_to_f32(self.r),
+            channel_to_f32(self.g),
+            channel_to_f32(self.b),
+            channel_to_f32(self.a),
+        )
+    }
+
+    pub fn to_linear(&self) -> LinearColor {
+        let (r, g, b, a) = self.to_srgb_f32();
+        LinearColor {
+            r: srgb_to_linear(r),
+            g: srgb_to_linear(g),
+            b: srgb_to_linear(b),
+            a,
+        }
+    }
+
+    /// Converts from linear light. Intensities above 1 are clipped.
+    pub fn from_linear(color: &LinearColor) -> NodeColor {
+        NodeColor::from_srgb_f32(
+            linear_to_srgb(color.r.max(0.0)),
+            linear_to_srgb(color.g.max(0.0)),
+            linear_to_srgb(color.b.max(0.0)),
+            color.a,
+        )
+    }
+
+    pub fn to_hsv(&self) -> HsvColor {
+        let (r, g, b, a) = self.to_srgb_f32();
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+        HsvColor {
+            h: hue_of(r, g, b, max, delta),
+            s: if max == 0.0 { 0.0 } else { delta / max },
+            v: max,
+            a,
+        }
+    }
+
+    pub fn from_hsv(color: &HsvColor) -> NodeColor {
+        let chroma = color.v * color.s;
+        let (r, g, b) = rgb_from_hue(color.h, chroma);
+        let m = color.v - chroma;
+        NodeColor::from_srgb_f32(r + m, g + m, b + m, color.a)
+    }
+
+    pub fn to_hsl(&self) -> HslColor {
+        let (r, g, b, a) = self.to_srgb_f32();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+        let l = (max + min) / 2.0;
+        HslColor {
+            h: hue_of(r, g, b, max, delta),
+            s: if delta == 0.0 {
+                0.0
+            } else {
+                delta / (1.0 - (2.0 * l - 1.0).abs())
+            },
+            l,
+            a,
+        }
+    }
+
+    pub fn from_hsl(color: &HslColor) -> NodeColor {
+        let chroma = (1.0 - (2.0 * color.l - 1.0).abs()) * color.s;
+        let (r, g, b) = rgb_from_hue(color.h, chroma);
+        let m = color.l - chroma / 2.0;
+        NodeColor::from_srgb_f32(r + m, g + m, b + m, color.a)
+    }
+
+    /// Approximates the color of a black body radiator at the given temperature, such
+    /// as 2700K for warm incandescent light or 6500K for daylight. Accurate from
+    /// 1000K to 40000K; temperatures outside that range are clamped.
+    pub fn from_kelvin(kelvin: f64) -> NodeColor {
+        let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+        let r = if t <= 66.0 {
+            255.0
+        } else {
+            329.698_727_446 * (t - 60.0).powf(-0.133_204_759_2)
+        };
+        let g = if t <= 66.0 {
+            99.470_802_586_1 * t.ln() - 161.119_568_166_1
+        } else {
+            288.122_169_528_3 * (t - 60.0).powf(-0.075_514_849_2)
+        };
+        let b = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.517_731_223_1 * (t - 10.0).ln() - 305.044_792_730_7
+        };
+        NodeColor::from_srgb_f32(
+            (r / 255.0) as f32,
+            (g / 255.0) as f32,
+            (b / 255.0) as f32,
+            1.0,
+        )
+    }
+
+    /// Splits the color into linear intensities for red, green, blue and white
+    /// emitters, as [r, g, b, w]. As much light as possible is moved to the white
+    /// channel. Alpha is applied as if the color were drawn over black.
+    pub fn to_rgbw(&self) -> [u16; 4] {
+        let linear = self.to_linear().premultiplied();
+        let w = linear.r.min(linear.g).min(linear.b).max(0.0);
+        [
+            channel_from_f32(linear.r - w),
+            channel_from_f32(linear.g - w),
+            channel_from_f32(linear.b - w),
+            channel_from_f32(w),
+        ]
+    }
+
+    /// Splits the color into linear intensities for red, green, blue and amber
+    /// emitters, as [r, g, b, amber]. As much light as possible is moved to the amber
+    /// channel, assuming the emitter's green output is AMBER_GREEN_RATIO of its red.
+    /// Alpha is applied as if the color were drawn over black.
+    pub fn to_rgb_amber(&self) -> [u16; 4] {
+        let linear = self.to_linear().premultiplied();
+        let amber = linear.r.min(linear.g / AMBER_GREEN_RATIO).max(0.0);
+        [
+            channel_from_f32(linear.r - amber),
+            channel_from_f32(linear.g - amber * AMBER_GREEN_RATIO),
+            channel_from_f32(linear.b),
+            channel_from_f32(amber),
+        ]
+    }
+
+    /// Draws this color over a backdrop color using the given blend mode. Blending
+    /// is done in linear light.
+    pub fn blend_over(&self, backdrop: &NodeColor, mode: BlendMode) -> NodeColor {
+        NodeColor::from_linear(&self.to_linear().blend_over(&backdrop.to_linear(), mode))
+    }
+}
+
+impl From<(u16, u16, u16, u16)> for NodeColor {
+    fn from((r, g, b, a): (u16, u16, u16, u16)) -> NodeColor {
+        NodeColor::new(r, g, b, a)
+    }
+}
+
+impl LinearColor {
+    pub const TRANSPARENT: LinearColor = LinearColor {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 0.0,
+    };
+
+    /// Returns the color with each channel multiplied by alpha, as used when the
+    /// color is drawn over black.
+    pub fn premultiplied(&self) -> LinearColor {
+        LinearColor {
+            r: self.r * self.a,
+            g: self.g * self.a,
+            b: self.b * self.a,
+            a: self.a,
+        }
+    }
+
+    /// Draws this color over a backdrop color using the given blend mode, following
+    /// the W3C compositing model: the blend mode mixes the colors where they overlap,
+    /// and the result is composited with source-over alpha.
+    pub fn blend_over(&self, backdrop: &LinearColor, mode: BlendMode) -> LinearColor {
+        let alpha = self.a + backdrop.a * (1.0 - self.a);
+        if alpha <= 0.0 {
+            return LinearColor::TRANSPARENT;
+        }
+        let channel = |source: f32, back: f32| {
+            let mixed = (1.0 - backdrop.a) * source + backdrop.a * mode.blend(back, source);
+            (self.a * mixed + backdrop.a * back * (1.0 - self.a)) / alpha
+        };
+        LinearColor {
+            r: channel(self.r, backdrop.r),
+            g: channel(self.g, backdrop.g),
+            b: channel(self.b, backdrop.b),
+            a: alpha,
+        }
+    }
+}
+
+impl BlendMode {
+    /// Combines a single channel of a backdrop and a source color, ignoring alpha.
+    pub fn blend(self, backdrop: f32, source: f32) -> f32 {
+        match self {
+            BlendMode::Normal => source,
+            BlendMode::Add => backdrop + source,
+            BlendMode::Multiply => backdrop * source,
+            BlendMode::Screen => backdrop + source - backdrop * source,
+        }
+    }
+}
+
+/// Hue in degrees of an RGB color, given its largest channel and its chroma.
+fn hue_of(r: f32, g: f32, b: f32, max: f32, delta: f32) -> f32 {
+    if delta == 0.0 {
+        return 0.0;
+    }
+    let hue = if max == r {
+        60.0 * ((g - b) / delta)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    hue.rem_euclid(360.0)
+}
+
+/// The RGB color with the given hue and chroma, before its lightness is adjusted.
+fn rgb_from_hue(hue: f32, chroma: f32) -> (f32, f32, f32) {
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    match sector as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_linear() {
+        let color = NodeColor::new(0, 1000, 30000, 65535);
+        assert_eq!(NodeColor::from_linear(&color.to_linear()), color);
+    }
+
+    #[test]
+    fn converts_hsv_and_hsl() {
+        let orange = NodeColor::opaque(u16::MAX, 32768, 0);
+        let hsv = orange.to_hsv();
+        assert!((hsv.h - 30.0).abs() < 0.01);
+        assert_eq!(hsv.s, 1.0);
+        assert_eq!(NodeColor::from_hsv(&hsv), orange);
+
+        let hsl = orange.to_hsl();
+        assert!((hsl.l - 0.5).abs() < 0.001);
+        assert_eq!(NodeColor::from_hsl(&hsl), orange);
+    }
+
+    #[test]
+    fn approximates_daylight_as_white() {
+        let daylight = NodeColor::from_kelvin(6600.0);
+        assert_eq!(daylight.r, u16::MAX);
+        assert!(daylight.g > 62000);
+        assert_eq!(daylight.b, u16::MAX);
+
+        let candle = NodeColor::from_kelvin(1900.0);
+        assert!(candle.r > candle.g && candle.g > candle.b);
+    }
+
+    #[test]
+    fn decomposes_into_white_and_amber() {
+        assert_eq!(NodeColor::WHITE.to_rgbw(), [0, 0, 0, u16::MAX]);
+        assert_eq!(
+            NodeColor::opaque(u16::MAX, 0, 0).to_rgbw(),
+            [u16::MAX, 0, 0, 0]
+        );
+
+        let amber = NodeColor::from_linear(&LinearColor {
+            r: 1.0,
+            g: AMBER_GREEN_RATIO,
+            b: 0.0,
+            a: 1.0,
+        });
+        let [r, g, b, a] = amber.to_rgb_amber();
+        assert!(r < 10 && g < 10 && b == 0);
+        assert!(a > 65525);
+    }
+
+    #[test]
+    fn blends_colors() {
+        let red = NodeColor::opaque(u16::MAX, 0, 0);
+        let blue = NodeColor::opaque(0, 0, u16::MAX);
+        assert_eq!(red.blend_over(&blue, BlendMode::Normal), red);
+        assert_eq!(
+            red.blend_over(&blue, BlendMode::Add),
+            NodeColor::opaque(u16::MAX, 0, u16::MAX)
+        );
+        assert_eq!(red.blend_over(&blue, BlendMode::Multiply), NodeColor::BLACK);
+        assert_eq!(
+            red.blend_over(&blue, BlendMode::Screen),
+            NodeColor::opaque(u16::MAX, 0, u16::MAX)
+        );
+        assert_eq!(
+            NodeColor::TRANSPARENT.blend_over(&blue, BlendMode::Normal),
+            blue
+        );
+    }
+
+    #[test]
+    fn composites_partial_alpha() {
+        let half_white = LinearColor {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            a: 0.5,
+        };
+        let black = LinearColor {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+            a: 1.0,
+        };
+        let result = half_white.blend_over(&black, BlendMode::Normal);
+        assert_eq!(result.a, 1.0);
+        assert!((result.r - 0.5).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,222 @@
+use super::{NodeValue, NodeValueType};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// Full type of a value, including the types of the values inside Lists and Records.
+/// NodeValueType only says that a value is a List; this also says what it is a list of.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NodeCompositeType {
+    /// Any type other than List or Record.
+    Simple(NodeValueType),
+
+    /// List where every item has the given type.
+    List(Box<NodeCompositeType>),
+
+    /// Record with the given fields, in order.
+    Record(Vec<(String, NodeCompositeType)>),
+}
+
+/// Homogeneous list of values, such as the colors of every fixture in a room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "NodeListFields")]
+pub struct NodeList {
+    element_type: NodeCompositeType,
+    items: Vec<NodeValue>,
+}
+
+/// Set of named values, such as the position, color and size of a single particle.
+/// Field order is part of a record's type.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct NodeRecord {
+    fields: Vec<(String, NodeValue)>,
+}
+
+impl NodeCompositeType {
+    pub fn of(value: &NodeValue) -> NodeCompositeType {
+        match value {
+            NodeValue::List(list) => NodeCompositeType::list_of(list.element_type().clone()),
+            NodeValue::Record(record) => NodeCompositeType::Record(
+                record
+                    .fields()
+                    .iter()
+                    .map(|(name, value)| (name.clone(), NodeCompositeType::of(value)))
+                    .collect(),
+            ),
+            _ => NodeCompositeType::Simple(NodeValueType::from(value)),
+        }
+    }
+
+    pub fn list_of(element_type: NodeCompositeType) -> NodeCompositeType {
+        NodeCompositeType::List(Box::new(element_type))
+    }
+
+    /// The outermost type, without any information about what is inside it.
+    pub fn base_type(&self) -> NodeValueType {
+        match self {
+            NodeCompositeType::Simple(value_type) => *value_type,
+            NodeCompositeType::List(_) => NodeValueType::List,
+            NodeCompositeType::Record(_) => NodeValueType::Record,
+        }
+    }
+}
+
+impl From<NodeValueType> for NodeCompositeType {
+    fn from(value_type: NodeValueType) -> NodeCompositeType {
+        NodeCompositeType::Simple(value_type)
+    }
+}
+
+impl NodeList {
+    /// Creates a list from items. Returns None if any item is not of the element type.
+    pub fn new(element_type: NodeCompositeType, items: Vec<NodeValue>) -> Option<NodeList> {
+        if items
+            .iter()
+            .any(|item| NodeCompositeType::of(item) != element_type)
+        {
+            return None;
+        }
+        Some(NodeList {
+            element_type,
+            items,
+        })
+    }
+
+    pub fn empty(element_type: NodeCompositeType) -> NodeList {
+        NodeList {
+            element_type,
+            items: vec![],
+        }
+    }
+
+    pub fn element_type(&self) -> &NodeCompositeType {
+        &self.element_type
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&NodeValue> {
+        self.items.get(index)
+    }
+
+    pub fn items(&self) -> &[NodeValue] {
+        &self.items
+    }
+
+    pub fn into_items(self) -> Vec<NodeValue> {
+        self.items
+    }
+
+    /// Adds an item to the end of the list. Gives the item back if it is not of the
+    /// element type.
+    pub fn push(&mut self, item: NodeValue) -> Result<(), NodeValue> {
+        if NodeCompositeType::of(&item) != self.element_type {
+            return Err(item);
+        }
+        self.items.push(item);
+        Ok(())
+    }
+}
+
+impl NodeRecord {
+    pub fn new() -> NodeRecord {
+        NodeRecord::default()
+    }
+
+    /// Creates a record from a list of fields. Later fields replace earlier fields
+    /// with the same name.
+    pub fn from_fields(fields: Vec<(String, NodeValue)>) -> NodeRecord {
+        let mut record = NodeRecord::new();
+        for (name, value) in fields {
+            record.set(&name, value);
+        }
+        record
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NodeValue> {
+        self.fields
+            .iter()
+            .find(|(field_name, _)| field_name == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Replaces the value of a field, or adds the field to the end if it is new.
+    pub fn set(&mut self, name: &str, value: NodeValue) {
+        match self
+            .fields
+            .iter_mut()
+            .find(|(field_name, _)| field_name == name)
+        {
+            Some(field) => field.1 = value,
+            None => self.fields.push((name.to_string(), value)),
+        }
+    }
+
+    pub fn fields(&self) -> &[(String, NodeValue)] {
+        &self.fields
+    }
+}
+
+/// Fields of a deserialized NodeList, before checking they are consistent.
+#[derive(Deserialize)]
+struct NodeListFields {
+    element_type: NodeCompositeType,
+    items: Vec<NodeValue>,
+}
+
+impl TryFrom<NodeListFields> for NodeList {
+    type Error = &'static str;
+
+    fn try_from(fields: NodeListFields) -> Result<NodeList, Self::Error> {
+        NodeList::new(fields.element_type, fields.items)
+            .ok_or("NodeList items do not match its element type")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node_value::NodeColor;
+    use std::sync::Arc;
+
+    #[test]
+    fn keeps_lists_homogeneous() {
+        let color_type = NodeCompositeType::from(NodeValueType::Color);
+        let mut list = NodeList::new(
+            color_type.clone(),
+            vec![NodeValue::Color(NodeColor::WHITE); 2],
+        )
+        .unwrap();
+        assert_eq!(list.push(NodeValue::Count(1)), Err(NodeValue::Count(1)));
+        assert_eq!(list.push(NodeValue::Color(NodeColor::BLACK)), Ok(()));
+        assert_eq!(list.len(), 3);
+        assert!(NodeList::new(color_type.clone(), vec![NodeValue::Count(1)]).is_none());
+
+        assert_eq!(
+            NodeCompositeType::of(&NodeValue::List(Arc::new(list))),
+            NodeCompositeType::list_of(color_type)
+        );
+    }
+
+    #[test]
+    fn describes_record_types() {
+        let mut record = NodeRecord::new();
+        record.set("size", NodeValue::Count(1));
+        record.set("color", NodeValue::Color(NodeColor::WHITE));
+        record.set("size", NodeValue::Count(2));
+        assert_eq!(record.get("size"), Some(&NodeValue::Count(2)));
+        assert_eq!(record.get("missing"), None);
+        assert_eq!(
+            NodeCompositeType::of(&NodeValue::Record(Arc::new(record))),
+            NodeCompositeType::Record(vec![
+                ("size".to_string(), NodeValueType::Count.into()),
+                ("color".to_string(), NodeValueType::Color.into()),
+            ])
+        );
+    }
+}
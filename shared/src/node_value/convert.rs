@@ -0,0 +1,283 @@
+use super::*;
+use std::fmt;
+use std::sync::Arc;
+
+/// Rust-side type of Trigger values, which would otherwise look the same as Toggle
+/// values when converted from a bool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct NodeTrigger(pub TriggerSignal);
+
+/// Rust-side type of Shader1D values, holding the program id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeShader1D(pub Shader1DProgramId);
+
+/// Rust-side type of Shader2D values, holding the program id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeShader2D(pub Shader2DProgramId);
+
+/// Rust-side type of Shader3D values, holding the program id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeShader3D(pub Shader3DProgramId);
+
+/// A Rust type that can be taken out of a NodeValue of a single type. Large
+/// payloads are taken as their Arc, so extracting them never copies the data.
+pub trait FromNodeValue: Sized {
+    const VALUE_TYPE: NodeValueType;
+
+    /// Returns None if the value is not of VALUE_TYPE.
+    fn from_node_value(value: &NodeValue) -> Option<Self>;
+}
+
+/// A Rust type that can be wrapped up as a NodeValue.
+pub trait IntoNodeValue {
+    const VALUE_TYPE: NodeValueType;
+
+    fn into_node_value(self) -> NodeValue;
+}
+
+macro_rules! impl_node_value_conversions {
+    ($($rust_type:ty => $variant:ident ($value:ident) { $from:expr, $into:expr }),+ $(,)?) => {
+        $(
+            impl FromNodeValue for $rust_type {
+                const VALUE_TYPE: NodeValueType = NodeValueType::$variant;
+
+                fn from_node_value(value: &NodeValue) -> Option<Self> {
+                    match value {
+                        NodeValue::$variant($value) => Some($from),
+                        _ => None,
+                    }
+                }
+            }
+
+            impl IntoNodeValue for $rust_type {
+                const VALUE_TYPE: NodeValueType = NodeValueType::$variant;
+
+                fn into_node_value(self) -> NodeValue {
+                    let $value = self;
+                    NodeValue::$variant($into)
+                }
+            }
+        )+
+    };
+}
+
+impl_node_value_conversions! {
+    NodeTrigger => Trigger(fired) { NodeTrigger(*fired), fired.0 },
+    bool => Toggle(on) { *on, on },
+    i64 => Count(count) { *count, count },
+    u32 => ConstrainedMagnitude(bits) { *bits, bits },
+    NodeMagnitude => ConstrainedMagnitude(magnitude) {
+        NodeMagnitude::from_bits(*magnitude),
+        magnitude.to_bits()
+    },
+    f64 => UnconstrainedMagnitude(magnitude) { *magnitude, magnitude },
+    NodeColor => Color(color) { *color, color },
+    Arc<String> => Text(text) { text.clone(), text },
+    String => Text(text) { text.as_ref().clone(), Arc::new(text) },
+    Arc<Bitmap1D> => Bitmap1D(bitmap) { bitmap.clone(), bitmap },
+    Arc<Bitmap2D> => Bitmap2D(bitmap) { bitmap.clone(), bitmap },
+    NodeShader1D => Shader1D(id) { NodeShader1D(*id), id.0 },
+    NodeShader2D => Shader2D(id) { NodeShader2D(*id), id.0 },
+    NodeShader3D => Shader3D(id) { NodeShader3D(*id), id.0 },
+    NodeVector2 => Vector2D(vector) { *vector, vector },
+    NodeVector3 => Vector3D(vector) { *vector, vector },
+    NodeDuration => Duration(duration) { *duration, duration },
+    NodeTimestamp => Timestamp(timestamp) { *timestamp, timestamp },
+    NodeEnumValue => Enumeration(value) { value.clone(), value },
+    Arc<NodeList> => List(list) { list.clone(), list },
+    Arc<NodeRecord> => Record(record) { record.clone(), record },
+    Arc<AudioBlock> => Audio(audio) { audio.clone(), audio },
+}
+
+/// Payloads that are stored behind an Arc can also be converted from their bare
+/// type. Going the other way would copy the data, so take the Arc instead.
+macro_rules! impl_into_node_value_for_bare_payloads {
+    ($($rust_type:ty => $variant:ident),+ $(,)?) => {
+        $(
+            impl IntoNodeValue for $rust_type {
+                const VALUE_TYPE: NodeValueType = NodeValueType::$variant;
+
+                fn into_node_value(self) -> NodeValue {
+                    NodeValue::$variant(Arc::new(self))
+                }
+            }
+        )+
+    };
+}
+
+impl_into_node_value_for_bare_payloads! {
+    Bitmap1D => Bitmap1D,
+    Bitmap2D => Bitmap2D,
+    NodeList => List,
+    NodeRecord => Record,
+    AudioBlock => Audio,
+}
+
+/// Why the inputs given to a Node could not be converted to the Rust types it takes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeInputError {
+    /// Fewer inputs were given than the Node takes.
+    Missing {
+        index: usize,
+        expected: NodeValueType,
+    },
+    WrongType {
+        index: usize,
+        expected: NodeValueType,
+        found: NodeValueType,
+    },
+}
+
+impl fmt::Display for NodeInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeInputError::Missing { index, expected } => {
+                write!(f, "Input {} ({:?}) is missing", index, expected)
+            }
+            NodeInputError::WrongType {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Input {} should be of type {:?} but is {:?}",
+                index, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NodeInputError {}
+
+/// Converts the input at an index, describing what went wrong if it can't be.
+pub fn node_input<T: FromNodeValue>(
+    inputs: &[&NodeValue],
+    index: usize,
+) -> Result<T, NodeInputError> {
+    let value = inputs.get(index).ok_or(NodeInputError::Missing {
+        index,
+        expected: T::VALUE_TYPE,
+    })?;
+    T::from_node_value(value).ok_or_else(|| NodeInputError::WrongType {
+        index,
+        expected: T::VALUE_TYPE,
+        found: NodeValueType::from(*value),
+    })
+}
+
+/// Like node_input, but for inputs that a Node may leave out, which convert to None.
+pub fn optional_node_input<T: FromNodeValue>(
+    inputs: &[&NodeValue],
+    index: usize,
+) -> Result<Option<T>, NodeInputError> {
+    match node_input(inputs, index) {
+        Ok(value) => Ok(Some(value)),
+        Err(NodeInputError::Missing { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// A tuple of Rust types that a Node's inputs can be converted to all at once, in
+/// order, such as `let (count, color): (i64, NodeColor) = from_node_inputs(&inputs)?`.
+pub trait FromNodeInputs: Sized {
+    /// Types of the inputs, in order.
+    fn value_types() -> Vec<NodeValueType>;
+
+    fn from_node_inputs(inputs: &[&NodeValue]) -> Result<Self, NodeInputError>;
+}
+
+macro_rules! impl_from_node_inputs {
+    ($($rust_type:ident $index:tt),*) => {
+        impl<$($rust_type: FromNodeValue),*> FromNodeInputs for ($($rust_type,)*) {
+            fn value_types() -> Vec<NodeValueType> {
+                vec![$($rust_type::VALUE_TYPE),*]
+            }
+
+            #[allow(unused_variables)]
+            fn from_node_inputs(inputs: &[&NodeValue]) -> Result<Self, NodeInputError> {
+                Ok(($(node_input::<$rust_type>(inputs, $index)?,)*))
+            }
+        }
+    };
+}
+
+impl_from_node_inputs!();
+impl_from_node_inputs!(A 0);
+impl_from_node_inputs!(A 0, B 1);
+impl_from_node_inputs!(A 0, B 1, C 2);
+impl_from_node_inputs!(A 0, B 1, C 2, D 3);
+impl_from_node_inputs!(A 0, B 1, C 2, D 3, E 4);
+impl_from_node_inputs!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_from_node_inputs!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_from_node_inputs!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+/// Converts every input of a Node at once. See FromNodeInputs.
+pub fn from_node_inputs<T: FromNodeInputs>(inputs: &[&NodeValue]) -> Result<T, NodeInputError> {
+    T::from_node_inputs(inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::every_value;
+    use super::*;
+
+    fn round_trip<T: FromNodeValue + IntoNodeValue>(value: &NodeValue) {
+        let converted = T::from_node_value(value).unwrap();
+        assert_eq!(&converted.into_node_value(), value);
+        assert_eq!(<T as FromNodeValue>::VALUE_TYPE, NodeValueType::from(value));
+    }
+
+    #[test]
+    fn converts_every_payload_type() {
+        let values = every_value();
+        round_trip::<NodeTrigger>(&values[0]);
+        round_trip::<bool>(&values[1]);
+        round_trip::<i64>(&values[2]);
+        round_trip::<NodeMagnitude>(&values[3]);
+        round_trip::<f64>(&values[4]);
+        round_trip::<NodeColor>(&values[5]);
+        round_trip::<String>(&values[6]);
+        round_trip::<Arc<Bitmap1D>>(&values[7]);
+        round_trip::<Arc<Bitmap2D>>(&values[8]);
+        round_trip::<NodeShader1D>(&values[9]);
+        round_trip::<NodeShader2D>(&values[10]);
+        round_trip::<NodeShader3D>(&values[11]);
+        round_trip::<NodeVector2>(&values[12]);
+        round_trip::<NodeVector3>(&values[13]);
+        round_trip::<NodeDuration>(&values[14]);
+        round_trip::<NodeTimestamp>(&values[15]);
+        round_trip::<NodeEnumValue>(&values[16]);
+        round_trip::<Arc<NodeList>>(&values[17]);
+        round_trip::<Arc<NodeRecord>>(&values[18]);
+        round_trip::<Arc<AudioBlock>>(&values[19]);
+
+        // Triggers and Toggles are both bools, but only convert to their own type.
+        assert_eq!(bool::from_node_value(&NodeValue::Trigger(true)), None);
+        assert_eq!(NodeTrigger::from_node_value(&NodeValue::Toggle(true)), None);
+    }
+
+    #[test]
+    fn extracts_typed_inputs() {
+        let count = NodeValue::Count(2);
+        let color = NodeValue::Color(NodeColor::WHITE);
+        let (a, b): (i64, NodeColor) = from_node_inputs(&[&count, &color]).unwrap();
+        assert_eq!((a, b), (2, NodeColor::WHITE));
+        assert_eq!(
+            <(i64, NodeColor)>::value_types(),
+            vec![NodeValueType::Count, NodeValueType::Color]
+        );
+
+        let error = from_node_inputs::<(i64, f64)>(&[&count, &color]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Input 1 should be of type UnconstrainedMagnitude but is Color"
+        );
+        assert_eq!(
+            from_node_inputs::<(i64, NodeColor, bool)>(&[&count, &color]),
+            Err(NodeInputError::Missing {
+                index: 2,
+                expected: NodeValueType::Toggle
+            })
+        );
+    }
+}
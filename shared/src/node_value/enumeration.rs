@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+/// A named set of options, such as a "direction" with "up", "down", "left" and "right".
+/// NodeDefs use these to declare which options an Enumeration input accepts.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NodeEnumDef {
+    pub name: String,
+    pub options: Vec<String>,
+}
+
+/// One option out of a NodeEnumDef. Holds on to its definition so that the value can
+/// always be displayed and checked against the input it is passed to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "NodeEnumValueFields")]
+pub struct NodeEnumValue {
+    def: Arc<NodeEnumDef>,
+    index: usize,
+}
+
+impl NodeEnumDef {
+    pub fn new(name: &str, options: &[&str]) -> NodeEnumDef {
+        NodeEnumDef {
+            name: name.to_string(),
+            options: options.iter().map(|option| option.to_string()).collect(),
+        }
+    }
+
+    pub fn index_of(&self, option: &str) -> Option<usize> {
+        self.options.iter().position(|o| o == option)
+    }
+}
+
+impl NodeEnumValue {
+    /// Selects an option by index. Returns None if the index is out of range.
+    pub fn new(def: Arc<NodeEnumDef>, index: usize) -> Option<NodeEnumValue> {
+        if index >= def.options.len() {
+            return None;
+        }
+        Some(NodeEnumValue { def, index })
+    }
+
+    /// Selects an option by name. Returns None if there is no such option.
+    pub fn from_option(def: Arc<NodeEnumDef>, option: &str) -> Option<NodeEnumValue> {
+        let index = def.index_of(option)?;
+        Some(NodeEnumValue { def, index })
+    }
+
+    pub fn def(&self) -> &Arc<NodeEnumDef> {
+        &self.def
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn option(&self) -> &str {
+        &self.def.options[self.index]
+    }
+
+    /// True if this value is an option of the given enum.
+    pub fn is_of(&self, def: &NodeEnumDef) -> bool {
+        *self.def == *def
+    }
+}
+
+impl PartialEq for NodeEnumValue {
+    /// Values are equal if they select the same option of the same named enum.
+    fn eq(&self, other: &NodeEnumValue) -> bool {
+        self.def.name == other.def.name && self.option() == other.option()
+    }
+}
+
+/// Fields of a deserialized NodeEnumValue, before checking they are consistent.
+#[derive(Deserialize)]
+struct NodeEnumValueFields {
+    def: Arc<NodeEnumDef>,
+    index: usize,
+}
+
+impl TryFrom<NodeEnumValueFields> for NodeEnumValue {
+    type Error = &'static str;
+
+    fn try_from(fields: NodeEnumValueFields) -> Result<NodeEnumValue, Self::Error> {
+        NodeEnumValue::new(fields.def, fields.index)
+            .ok_or("NodeEnumValue index is not one of its options")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_options_by_name() {
+        let direction = Arc::new(NodeEnumDef::new("direction", &["up", "down"]));
+        let down = NodeEnumValue::from_option(direction.clone(), "down").unwrap();
+        assert_eq!(down.index(), 1);
+        assert_eq!(down.option(), "down");
+        assert!(down.is_of(&direction));
+        assert_eq!(down, NodeEnumValue::new(direction.clone(), 1).unwrap());
+        assert!(NodeEnumValue::from_option(direction.clone(), "left").is_none());
+        assert!(NodeEnumValue::new(direction, 2).is_none());
+
+        let blend = Arc::new(NodeEnumDef::new("blend mode", &["normal", "down"]));
+        assert_ne!(down, NodeEnumValue::new(blend.clone(), 1).unwrap());
+        assert!(!down.is_of(&blend));
+    }
+}
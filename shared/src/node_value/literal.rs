@@ -0,0 +1,773 @@
+//! Text form of NodeValues, for setting values from config files, command lines and
+//! other places where there is no Rust code to build them.
+//!
+//! Every literal starts with the type of value, a colon and then the value, such as
+//! `count:5`, `toggle:true`, `mag:0.25` (a ConstrainedMagnitude), `umag:1.5` (an
+//! UnconstrainedMagnitude), `color:#ff8800ff`, `text:"hi"`, `vec2:(1, 2)`,
+//! `duration:500ms`, `timestamp:1600000000000000` (microseconds since the epoch) or
+//! `shader2d:3`. Colors have 8 or 16 bits per channel, with or without alpha.
+//!
+//! Larger values are written as:
+//! - `bitmap1d:[#ff0000ff, #00ff00ff]`
+//! - `bitmap2d:2x1[#ff0000ff, #00ff00ff]`, with pixels listed row by row
+//! - `enum:"direction"["up", "down"]:"down"`
+//! - `list:<count>[count:1, count:2]`, where the item type can also be something like
+//!   `list<mag>` or `record{size: count}`
+//! - `record:{size: count:2, "full name": text:"Ada"}`
+//! - `audio:48000hz/2ch[0.5, -0.5]`, with samples interleaved by channel
+//!
+//! Formatting a value with Display gives the canonical literal, which parses back to
+//! an equal value.
+
+use super::*;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+use strum::IntoEnumIterator;
+
+/// Characters that end a word, such as a number or a bare record field name.
+const DELIMITERS: &str = ",:()[]{}<>\"";
+
+/// Deepest that lists, records and their types can nest inside one literal.
+const MAX_NESTING: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeValueParseError {
+    pub message: String,
+
+    /// Byte offset in the source where the problem was found.
+    pub offset: usize,
+}
+
+impl NodeValueParseError {
+    fn new(message: &str, offset: usize) -> NodeValueParseError {
+        NodeValueParseError {
+            message: message.to_string(),
+            offset,
+        }
+    }
+}
+
+impl fmt::Display for NodeValueParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.offset + 1)
+    }
+}
+
+impl std::error::Error for NodeValueParseError {}
+
+/// Name of a type in literals.
+fn tag(value_type: NodeValueType) -> &'static str {
+    match value_type {
+        NodeValueType::Trigger => "trigger",
+        NodeValueType::Toggle => "toggle",
+        NodeValueType::Count => "count",
+        NodeValueType::ConstrainedMagnitude => "mag",
+        NodeValueType::UnconstrainedMagnitude => "umag",
+        NodeValueType::Color => "color",
+        NodeValueType::Text => "text",
+        NodeValueType::Bitmap1D => "bitmap1d",
+        NodeValueType::Bitmap2D => "bitmap2d",
+        NodeValueType::Shader1D => "shader1d",
+        NodeValueType::Shader2D => "shader2d",
+        NodeValueType::Shader3D => "shader3d",
+        NodeValueType::Vector2D => "vec2",
+        NodeValueType::Vector3D => "vec3",
+        NodeValueType::Duration => "duration",
+        NodeValueType::Timestamp => "timestamp",
+        NodeValueType::Enumeration => "enum",
+        NodeValueType::List => "list",
+        NodeValueType::Record => "record",
+        NodeValueType::Audio => "audio",
+    }
+}
+
+fn type_of_tag(name: &str) -> Option<NodeValueType> {
+    NodeValueType::iter().find(|value_type| tag(*value_type) == name)
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Writes a record field name, quoting it unless it is a plain identifier.
+fn write_name(f: &mut fmt::Formatter<'_>, name: &str) -> fmt::Result {
+    let is_identifier = name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if is_identifier {
+        f.write_str(name)
+    } else {
+        write_string(f, name)
+    }
+}
+
+fn write_sequence<T>(
+    f: &mut fmt::Formatter<'_>,
+    (open, close): (char, char),
+    items: &[T],
+    mut write_item: impl FnMut(&mut fmt::Formatter<'_>, &T) -> fmt::Result,
+) -> fmt::Result {
+    f.write_char(open)?;
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            f.write_str(", ")?;
+        }
+        write_item(f, item)?;
+    }
+    f.write_char(close)
+}
+
+/// Writes a color with 8 bits per channel if that loses nothing, or 16 otherwise.
+fn write_color(f: &mut fmt::Formatter<'_>, color: &NodeColor) -> fmt::Result {
+    let channels = [color.r, color.g, color.b, color.a];
+    f.write_char('#')?;
+    if channels.iter().all(|channel| channel % 257 == 0) {
+        for channel in &channels {
+            write!(f, "{:02x}", channel / 257)?;
+        }
+    } else {
+        for channel in &channels {
+            write!(f, "{:04x}", channel)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the shortest decimal that parses back to the same magnitude.
+fn write_magnitude(f: &mut fmt::Formatter<'_>, magnitude: NodeMagnitude) -> fmt::Result {
+    let value = magnitude.to_f64();
+    for precision in 0..17 {
+        let text = format!("{:.*}", precision, value);
+        if NodeMagnitude::from_f64(text.parse().unwrap()) == magnitude {
+            let text = if text.contains('.') {
+                text.trim_end_matches('0').trim_end_matches('.')
+            } else {
+                &text
+            };
+            return f.write_str(text);
+        }
+    }
+    write!(f, "{}", value)
+}
+
+/// Writes a duration in the largest unit that represents it exactly.
+fn write_duration(f: &mut fmt::Formatter<'_>, duration: &NodeDuration) -> fmt::Result {
+    let micros = duration.as_micros();
+    if micros % 1_000_000 == 0 {
+        write!(f, "{}s", micros / 1_000_000)
+    } else if micros % 1000 == 0 {
+        write!(f, "{}ms", micros / 1000)
+    } else {
+        write!(f, "{}us", micros)
+    }
+}
+
+impl fmt::Display for NodeCompositeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeCompositeType::Simple(value_type) => f.write_str(tag(*value_type)),
+            NodeCompositeType::List(element_type) => write!(f, "list<{}>", element_type),
+            NodeCompositeType::Record(fields) => {
+                f.write_str("record")?;
+                write_sequence(f, ('{', '}'), fields, |f, (name, field_type)| {
+                    write_name(f, name)?;
+                    write!(f, ": {}", field_type)
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for NodeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", tag(NodeValueType::from(self)))?;
+        match self {
+            NodeValue::Trigger(value) | NodeValue::Toggle(value) => write!(f, "{}", value),
+            NodeValue::Count(count) => write!(f, "{}", count),
+            NodeValue::ConstrainedMagnitude(bits) => {
+                write_magnitude(f, NodeMagnitude::from_bits(*bits))
+            }
+            NodeValue::UnconstrainedMagnitude(value) => write!(f, "{}", value),
+            NodeValue::Color(color) => write_color(f, color),
+            NodeValue::Text(text) => write_string(f, text),
+            NodeValue::Bitmap1D(bitmap) => {
+                write_sequence(f, ('[', ']'), bitmap.pixels(), write_color)
+            }
+            NodeValue::Bitmap2D(bitmap) => {
+                write!(f, "{}x{}", bitmap.width(), bitmap.height())?;
+                write_sequence(f, ('[', ']'), bitmap.pixels(), write_color)
+            }
+            NodeValue::Shader1D(id) | NodeValue::Shader2D(id) | NodeValue::Shader3D(id) => {
+                write!(f, "{}", id)
+            }
+            NodeValue::Vector2D(vector) => write!(f, "({}, {})", vector.x, vector.y),
+            NodeValue::Vector3D(vector) => {
+                write!(f, "({}, {}, {})", vector.x, vector.y, vector.z)
+            }
+            NodeValue::Duration(duration) => write_duration(f, duration),
+            NodeValue::Timestamp(timestamp) => write!(f, "{}", timestamp.as_micros_since_epoch()),
+            NodeValue::Enumeration(value) => {
+                write_string(f, &value.def().name)?;
+                write_sequence(f, ('[', ']'), &value.def().options, |f, option| {
+                    write_string(f, option)
+                })?;
+                f.write_char(':')?;
+                write_string(f, value.option())
+            }
+            NodeValue::List(list) => {
+                write!(f, "<{}>", list.element_type())?;
+                write_sequence(f, ('[', ']'), list.items(), |f, item| write!(f, "{}", item))
+            }
+            NodeValue::Record(record) => {
+                write_sequence(f, ('{', '}'), record.fields(), |f, (name, value)| {
+                    write_name(f, name)?;
+                    write!(f, ": {}", value)
+                })
+            }
+            NodeValue::Audio(audio) => {
+                write!(f, "{}hz/{}ch", audio.sample_rate(), audio.channels())?;
+                write_sequence(f, ('[', ']'), audio.samples(), |f, sample| {
+                    write!(f, "{}", sample)
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for NodeValue {
+    type Err = NodeValueParseError;
+
+    fn from_str(source: &str) -> Result<NodeValue, NodeValueParseError> {
+        let mut parser = Parser::new(source);
+        let value = parser.value()?;
+        parser.end()?;
+        Ok(value)
+    }
+}
+
+impl FromStr for NodeCompositeType {
+    type Err = NodeValueParseError;
+
+    fn from_str(source: &str) -> Result<NodeCompositeType, NodeValueParseError> {
+        let mut parser = Parser::new(source);
+        let composite_type = parser.composite_type()?;
+        parser.end()?;
+        Ok(composite_type)
+    }
+}
+
+type ParseResult<T> = Result<T, NodeValueParseError>;
+
+/// Recursive descent parser over the characters of a literal.
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Parser<'a> {
+        Parser {
+            source,
+            position: 0,
+            depth: 0,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    /// The next character that is not whitespace, without consuming it.
+    fn peek(&mut self) -> Option<char> {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += expected.len_utf8();
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, expected: char) -> ParseResult<()> {
+        if !self.eat(expected) {
+            return Err(NodeValueParseError::new(
+                &format!("Expected '{}'", expected),
+                self.position,
+            ));
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> ParseResult<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(NodeValueParseError::new(
+                "Unexpected text after the value",
+                self.position,
+            )),
+        }
+    }
+
+    /// Reads characters up to the next delimiter or whitespace, returning them and
+    /// the offset they start at.
+    fn word(&mut self, description: &str) -> ParseResult<(&'a str, usize)> {
+        self.peek();
+        let start = self.position;
+        let length = self
+            .rest()
+            .find(|c: char| c.is_whitespace() || DELIMITERS.contains(c))
+            .unwrap_or_else(|| self.rest().len());
+        if length == 0 {
+            return Err(NodeValueParseError::new(
+                &format!("Expected {}", description),
+                start,
+            ));
+        }
+        self.position += length;
+        Ok((&self.source[start..start + length], start))
+    }
+
+    /// Reads a word and parses it with FromStr.
+    fn parsed<T: FromStr>(&mut self, description: &str) -> ParseResult<T> {
+        let (word, offset) = self.word(description)?;
+        word.parse()
+            .map_err(|_| NodeValueParseError::new(&format!("Expected {}", description), offset))
+    }
+
+    fn string(&mut self) -> ParseResult<String> {
+        self.expect('"')?;
+        let start = self.position;
+        let mut text = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((index, c)) = chars.next() {
+            let offset = start + index;
+            match c {
+                '"' => {
+                    self.position = offset + 1;
+                    return Ok(text);
+                }
+                '\\' => {
+                    let escaped = match chars.next() {
+                        Some((_, '"')) => '"',
+                        Some((_, '\\')) => '\\',
+                        Some((_, 'n')) => '\n',
+                        Some((_, 'r')) => '\r',
+                        Some((_, 't')) => '\t',
+                        Some((_, 'u')) => {
+                            let rest = &self.source[offset + 2..];
+                            let code = rest
+                                .strip_prefix('{')
+                                .and_then(|rest| rest.split_once('}'))
+                                .and_then(|(hex, _)| u32::from_str_radix(hex, 16).ok())
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| {
+                                    NodeValueParseError::new("Invalid unicode escape", offset)
+                                })?;
+                            // Skip past the braces and hex digits.
+                            let length = rest.find('}').unwrap() + 1;
+                            for _ in 0..length {
+                                chars.next();
+                            }
+                            code
+                        }
+                        _ => return Err(NodeValueParseError::new("Invalid escape", offset)),
+                    };
+                    text.push(escaped);
+                }
+                c => text.push(c),
+            }
+        }
+        Err(NodeValueParseError::new(
+            "Text is missing its closing quote",
+            start - 1,
+        ))
+    }
+
+    /// Reads a record field name, which is either quoted or a bare word.
+    fn name(&mut self) -> ParseResult<String> {
+        if self.peek() == Some('"') {
+            return self.string();
+        }
+        Ok(self.word("a field name")?.0.to_string())
+    }
+
+    /// Reads a comma separated list of items between brackets.
+    fn sequence<T>(
+        &mut self,
+        (open, close): (char, char),
+        mut item: impl FnMut(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<Vec<T>> {
+        self.expect(open)?;
+        let mut items = vec![];
+        if self.eat(close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.eat(close) {
+                return Ok(items);
+            }
+            if !self.eat(',') {
+                return Err(NodeValueParseError::new(
+                    &format!("Expected ',' or '{}'", close),
+                    self.position,
+                ));
+            }
+        }
+    }
+
+    fn color(&mut self) -> ParseResult<NodeColor> {
+        let (word, offset) = self.word("a color")?;
+        let error = || NodeValueParseError::new("Expected a color such as #ff8800ff", offset);
+        let hex = word.strip_prefix('#').ok_or_else(error)?;
+        let digits = match hex.len() {
+            6 | 8 => 2,
+            12 | 16 => 4,
+            _ => return Err(error()),
+        };
+        let mut channels = [u16::MAX; 4];
+        for (index, channel) in channels.iter_mut().enumerate().take(hex.len() / digits) {
+            let digits = hex
+                .get(index * digits..(index + 1) * digits)
+                .ok_or_else(error)?;
+            let value = u16::from_str_radix(digits, 16).map_err(|_| error())?;
+            *channel = if digits.len() == 2 {
+                value * 257
+            } else {
+                value
+            };
+        }
+        Ok(NodeColor::new(
+            channels[0],
+            channels[1],
+            channels[2],
+            channels[3],
+        ))
+    }
+
+    /// Reads a list of numbers in parentheses, which must have the given length.
+    fn components(&mut self, count: usize) -> ParseResult<Vec<f64>> {
+        let offset = self.position;
+        let components = self.sequence(('(', ')'), |parser| parser.parsed("a number"))?;
+        if components.len() != count {
+            return Err(NodeValueParseError::new(
+                &format!("Expected {} components", count),
+                offset,
+            ));
+        }
+        Ok(components)
+    }
+
+    fn duration(&mut self) -> ParseResult<NodeDuration> {
+        let (word, offset) = self.word("a duration")?;
+        let error = || NodeValueParseError::new("Expected a duration such as 500ms", offset);
+        let unit_start = word.find(|c: char| c.is_alphabetic()).ok_or_else(error)?;
+        let (amount, unit) = word.split_at(unit_start);
+        let amount: i64 = amount.parse().map_err(|_| error())?;
+        let scale = match unit {
+            "us" => 1,
+            "ms" => 1000,
+            "s" => 1_000_000,
+            _ => return Err(error()),
+        };
+        let micros = amount
+            .checked_mul(scale)
+            .ok_or_else(|| NodeValueParseError::new("Duration is too long", offset))?;
+        Ok(NodeDuration::from_micros(micros))
+    }
+
+    /// Parses something inside a list or record, failing once that goes deeper than
+    /// MAX_NESTING instead of overflowing the stack.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        if self.depth == MAX_NESTING {
+            return Err(NodeValueParseError::new(
+                "Value is too deeply nested",
+                self.position,
+            ));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn composite_type(&mut self) -> ParseResult<NodeCompositeType> {
+        let (word, offset) = self.word("a type")?;
+        let value_type = type_of_tag(word)
+            .ok_or_else(|| NodeValueParseError::new(&format!("Unknown type '{}'", word), offset))?;
+        Ok(match value_type {
+            NodeValueType::List if self.eat('<') => {
+                let element_type = self.nested(Parser::composite_type)?;
+                self.expect('>')?;
+                NodeCompositeType::list_of(element_type)
+            }
+            NodeValueType::Record if self.peek() == Some('{') => {
+                NodeCompositeType::Record(self.sequence(('{', '}'), |parser| {
+                    let name = parser.name()?;
+                    parser.expect(':')?;
+                    Ok((name, parser.nested(Parser::composite_type)?))
+                })?)
+            }
+            _ => NodeCompositeType::Simple(value_type),
+        })
+    }
+
+    fn value(&mut self) -> ParseResult<NodeValue> {
+        let (word, offset) = self.word("a value")?;
+        let value_type = type_of_tag(word)
+            .ok_or_else(|| NodeValueParseError::new(&format!("Unknown type '{}'", word), offset))?;
+        self.expect(':')?;
+        let offset = self.position;
+        Ok(match value_type {
+            NodeValueType::Trigger => NodeValue::Trigger(self.parsed("true or false")?),
+            NodeValueType::Toggle => NodeValue::Toggle(self.parsed("true or false")?),
+            NodeValueType::Count => NodeValue::Count(self.parsed("a whole number")?),
+            NodeValueType::ConstrainedMagnitude => {
+                let value: f64 = self.parsed("a number")?;
+                if !(0.0..=1.0).contains(&value) {
+                    return Err(NodeValueParseError::new(
+                        "Magnitude must be from 0 to 1",
+                        offset,
+                    ));
+                }
+                NodeValue::ConstrainedMagnitude(NodeMagnitude::from_f64(value).to_bits())
+            }
+            NodeValueType::UnconstrainedMagnitude => {
+                NodeValue::UnconstrainedMagnitude(self.parsed("a number")?)
+            }
+            NodeValueType::Color => NodeValue::Color(self.color()?),
+            NodeValueType::Text => NodeValue::Text(Arc::new(self.string()?)),
+            NodeValueType::Bitmap1D => {
+                let pixels = self.sequence(('[', ']'), Parser::color)?;
+                NodeValue::Bitmap1D(Arc::new(Bitmap1D::from_pixels(pixels)))
+            }
+            NodeValueType::Bitmap2D => {
+                let (size, size_offset) = self.word("a size such as 2x1")?;
+                let (width, height) = size
+                    .split_once('x')
+                    .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                    .ok_or_else(|| {
+                        NodeValueParseError::new("Expected a size such as 2x1", size_offset)
+                    })?;
+                let pixels = self.sequence(('[', ']'), Parser::color)?;
+                let bitmap = Bitmap2D::from_pixels(width, height, pixels).ok_or_else(|| {
+                    NodeValueParseError::new("Pixel count does not match the size", offset)
+                })?;
+                NodeValue::Bitmap2D(Arc::new(bitmap))
+            }
+            NodeValueType::Shader1D => NodeValue::Shader1D(self.parsed("a program id")?),
+            NodeValueType::Shader2D => NodeValue::Shader2D(self.parsed("a program id")?),
+            NodeValueType::Shader3D => NodeValue::Shader3D(self.parsed("a program id")?),
+            NodeValueType::Vector2D => {
+                let c = self.components(2)?;
+                NodeValue::Vector2D(NodeVector2::new(c[0], c[1]))
+            }
+            NodeValueType::Vector3D => {
+                let c = self.components(3)?;
+                NodeValue::Vector3D(NodeVector3::new(c[0], c[1], c[2]))
+            }
+            NodeValueType::Duration => NodeValue::Duration(self.duration()?),
+            NodeValueType::Timestamp => NodeValue::Timestamp(
+                NodeTimestamp::from_micros_since_epoch(self.parsed("a whole number")?),
+            ),
+            NodeValueType::Enumeration => {
+                let name = self.string()?;
+                let options = self.sequence(('[', ']'), Parser::string)?;
+                self.expect(':')?;
+                let option_offset = self.position;
+                let option = self.string()?;
+                let def = Arc::new(NodeEnumDef { name, options });
+                let value = NodeEnumValue::from_option(def, &option).ok_or_else(|| {
+                    NodeValueParseError::new(
+                        &format!("'{}' is not one of the options", option),
+                        option_offset,
+                    )
+                })?;
+                NodeValue::Enumeration(value)
+            }
+            NodeValueType::List => {
+                self.expect('<')?;
+                let element_type = self.nested(Parser::composite_type)?;
+                self.expect('>')?;
+                let items = self.sequence(('[', ']'), |parser| parser.nested(Parser::value))?;
+                let list = NodeList::new(element_type, items).ok_or_else(|| {
+                    NodeValueParseError::new("List items do not match the item type", offset)
+                })?;
+                NodeValue::List(Arc::new(list))
+            }
+            NodeValueType::Record => {
+                let mut record = NodeRecord::new();
+                self.expect('{')?;
+                if !self.eat('}') {
+                    loop {
+                        let name_offset = self.position;
+                        let name = self.name()?;
+                        self.expect(':')?;
+                        let value = self.nested(Parser::value)?;
+                        if record.get(&name).is_some() {
+                            return Err(NodeValueParseError::new(
+                                &format!("Field '{}' is set twice", name),
+                                name_offset,
+                            ));
+                        }
+                        record.set(&name, value);
+                        if self.eat('}') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                NodeValue::Record(Arc::new(record))
+            }
+            NodeValueType::Audio => {
+                let (format, format_offset) = self.word("an audio format such as 48000hz/2ch")?;
+                let (sample_rate, channels) = format
+                    .strip_suffix("ch")
+                    .and_then(|format| format.split_once("hz/"))
+                    .and_then(|(rate, channels)| Some((rate.parse().ok()?, channels.parse().ok()?)))
+                    .ok_or_else(|| {
+                        NodeValueParseError::new(
+                            "Expected an audio format such as 48000hz/2ch",
+                            format_offset,
+                        )
+                    })?;
+                let samples = self.sequence(('[', ']'), |parser| parser.parsed("a sample"))?;
+                let audio = AudioBlock::new(sample_rate, channels, samples).ok_or_else(|| {
+                    NodeValueParseError::new(
+                        "Sample count is not a multiple of the channel count",
+                        offset,
+                    )
+                })?;
+                NodeValue::Audio(Arc::new(audio))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::every_value;
+    use super::*;
+
+    fn parse(source: &str) -> NodeValue {
+        source.parse().unwrap()
+    }
+
+    #[test]
+    fn round_trips_every_value() {
+        for value in every_value() {
+            let literal = value.to_string();
+            assert_eq!(parse(&literal), value, "{} did not round trip", literal);
+        }
+    }
+
+    #[test]
+    fn formats_canonical_literals() {
+        assert_eq!(NodeValue::Count(5).to_string(), "count:5");
+        assert_eq!(
+            NodeValue::Color(NodeColor::opaque(u16::MAX, 0x8888, 0)).to_string(),
+            "color:#ff8800ff"
+        );
+        assert_eq!(
+            NodeValue::Color(NodeColor::new(1, 2, 3, 4)).to_string(),
+            "color:#0001000200030004"
+        );
+        assert_eq!(parse("mag:0.25").to_string(), "mag:0.25");
+        assert_eq!(
+            NodeValue::Text(Arc::new("say \"hi\"\n".to_string())).to_string(),
+            r#"text:"say \"hi\"\n""#
+        );
+        assert_eq!(
+            NodeValue::Duration(NodeDuration::from_millis(1500)).to_string(),
+            "duration:1500ms"
+        );
+
+        let nested =
+            parse(r#"list:<record{"full name": text}>[record:{"full name": text:"\u{e9}"}]"#);
+        assert_eq!(
+            nested.to_string(),
+            r#"list:<record{"full name": text}>[record:{"full name": text:"é"}]"#
+        );
+        assert_eq!(
+            parse(" color : #ff0000 "),
+            NodeValue::Color(NodeColor::opaque(u16::MAX, 0, 0))
+        );
+        assert_eq!(
+            "list<list<vec2>>".parse::<NodeCompositeType>().unwrap(),
+            NodeCompositeType::list_of(NodeCompositeType::list_of(NodeValueType::Vector2D.into()))
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        let error = |source: &str| source.parse::<NodeValue>().unwrap_err().to_string();
+        assert_eq!(error("size:5"), "Unknown type 'size' at column 1");
+        assert_eq!(error("count:five"), "Expected a whole number at column 7");
+        assert_eq!(
+            error("mag:1.5"),
+            "Magnitude must be from 0 to 1 at column 5"
+        );
+        assert_eq!(
+            error("text:\"hi"),
+            "Text is missing its closing quote at column 6"
+        );
+        assert_eq!(
+            error("list:<count>[count:1, mag:1]"),
+            "List items do not match the item type at column 6"
+        );
+        assert_eq!(error("vec2:(1, 2"), "Expected ',' or ')' at column 11");
+        assert_eq!(
+            error("count:1 2"),
+            "Unexpected text after the value at column 9"
+        );
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let lists = |depth: usize| format!("{}count{}", "list<".repeat(depth), ">".repeat(depth));
+        assert!(lists(MAX_NESTING).parse::<NodeCompositeType>().is_ok());
+        assert_eq!(
+            lists(100_000)
+                .parse::<NodeCompositeType>()
+                .unwrap_err()
+                .message,
+            "Value is too deeply nested"
+        );
+
+        let records = format!(
+            "{}count:1{}",
+            "record:{a: ".repeat(100_000),
+            "}".repeat(100_000)
+        );
+        assert_eq!(
+            records.parse::<NodeValue>().unwrap_err().message,
+            "Value is too deeply nested"
+        );
+        let lists = format!(
+            "{}[]{}",
+            "list:<count>[".repeat(100_000),
+            "]".repeat(100_000)
+        );
+        assert_eq!(
+            lists.parse::<NodeValue>().unwrap_err().message,
+            "Value is too deeply nested"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Mul;
+
+const MAX_BITS: u64 = u32::MAX as u64;
+
+/// A fixed-point value from 0 to 1, stored as a u32 where u32::MAX is exactly 1. This
+/// is the payload of ConstrainedMagnitude values. Math on it rounds to the nearest
+/// step and saturates at 0 and 1 rather than overflowing.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct NodeMagnitude {
+    bits: u32,
+}
+
+impl NodeMagnitude {
+    pub const ZERO: NodeMagnitude = NodeMagnitude::from_bits(0);
+    pub const ONE: NodeMagnitude = NodeMagnitude::from_bits(u32::MAX);
+
+    pub const fn from_bits(bits: u32) -> NodeMagnitude {
+        NodeMagnitude { bits }
+    }
+
+    pub const fn to_bits(self) -> u32 {
+        self.bits
+    }
+
+    /// Rounds to the nearest step, clamping to the 0 to 1 range. NaN becomes 0.
+    pub fn from_f64(value: f64) -> NodeMagnitude {
+        if value.is_nan() {
+            return NodeMagnitude::ZERO;
+        }
+        NodeMagnitude::from_bits((value.clamp(0.0, 1.0) * MAX_BITS as f64).round() as u32)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.bits as f64 / MAX_BITS as f64
+    }
+
+    /// Interpolates from self towards another value, where an amount of 0 is self and
+    /// 1 is other.
+    pub fn lerp(self, other: NodeMagnitude, amount: NodeMagnitude) -> NodeMagnitude {
+        let difference = other.bits as i128 - self.bits as i128;
+        let scaled = difference * amount.bits as i128;
+        // Round half away from zero, so that lerping back and forth is symmetric.
+        let half = MAX_BITS as i128 / 2;
+        let offset = if scaled < 0 {
+            (scaled - half) / MAX_BITS as i128
+        } else {
+            (scaled + half) / MAX_BITS as i128
+        };
+        NodeMagnitude::from_bits((self.bits as i128 + offset) as u32)
+    }
+
+    pub fn saturating_add(self, other: NodeMagnitude) -> NodeMagnitude {
+        NodeMagnitude::from_bits(self.bits.saturating_add(other.bits))
+    }
+
+    pub fn saturating_sub(self, other: NodeMagnitude) -> NodeMagnitude {
+        NodeMagnitude::from_bits(self.bits.saturating_sub(other.bits))
+    }
+
+    /// One minus the value, so 0 becomes 1 and 1 becomes 0.
+    pub const fn invert(self) -> NodeMagnitude {
+        NodeMagnitude::from_bits(u32::MAX - self.bits)
+    }
+
+    /// Raises the value to the given power. Gammas above 1 darken the middle of the
+    /// range and gammas below 1 brighten it, while 0 and 1 stay put.
+    pub fn gamma(self, gamma: f64) -> NodeMagnitude {
+        NodeMagnitude::from_f64(self.to_f64().powf(gamma))
+    }
+
+    /// Eases in and out of the ends of the range, with the S-shaped curve
+    /// `3t^2 - 2t^3`.
+    pub fn smoothstep(self) -> NodeMagnitude {
+        let t = self.to_f64();
+        NodeMagnitude::from_f64(t * t * (3.0 - 2.0 * t))
+    }
+}
+
+impl Mul for NodeMagnitude {
+    type Output = NodeMagnitude;
+
+    /// Multiplies exactly, rounding to the nearest step. Multiplying by 1 returns the
+    /// value unchanged.
+    fn mul(self, other: NodeMagnitude) -> NodeMagnitude {
+        let product = self.bits as u64 * other.bits as u64;
+        NodeMagnitude::from_bits(((product + MAX_BITS / 2) / MAX_BITS) as u32)
+    }
+}
+
+impl From<u32> for NodeMagnitude {
+    fn from(bits: u32) -> NodeMagnitude {
+        NodeMagnitude::from_bits(bits)
+    }
+}
+
+impl From<NodeMagnitude> for u32 {
+    fn from(magnitude: NodeMagnitude) -> u32 {
+        magnitude.to_bits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HALF: NodeMagnitude = NodeMagnitude::from_bits(u32::MAX / 2 + 1);
+
+    #[test]
+    fn converts_with_rounding() {
+        assert_eq!(NodeMagnitude::from_f64(0.5), HALF);
+        assert_eq!(NodeMagnitude::from_f64(2.0), NodeMagnitude::ONE);
+        assert_eq!(NodeMagnitude::from_f64(-1.0), NodeMagnitude::ZERO);
+        assert_eq!(NodeMagnitude::from_f64(f64::NAN), NodeMagnitude::ZERO);
+        assert_eq!(NodeMagnitude::ONE.to_f64(), 1.0);
+        let value = NodeMagnitude::from_bits(123_456_789);
+        assert_eq!(NodeMagnitude::from_f64(value.to_f64()), value);
+    }
+
+    #[test]
+    fn multiplies_and_interpolates_without_overflow() {
+        let value = NodeMagnitude::from_bits(987_654_321);
+        assert_eq!(value * NodeMagnitude::ONE, value);
+        assert_eq!(NodeMagnitude::ONE * NodeMagnitude::ONE, NodeMagnitude::ONE);
+        assert_eq!(value * NodeMagnitude::ZERO, NodeMagnitude::ZERO);
+        assert_eq!(HALF * HALF, NodeMagnitude::from_f64(0.25));
+
+        let (zero, one) = (NodeMagnitude::ZERO, NodeMagnitude::ONE);
+        assert_eq!(zero.lerp(one, value), value);
+        assert_eq!(one.lerp(zero, value), value.invert());
+        assert_eq!(value.lerp(one, NodeMagnitude::ONE), one);
+        assert_eq!(value.lerp(zero, NodeMagnitude::ZERO), value);
+    }
+
+    #[test]
+    fn saturates_and_applies_curves() {
+        assert_eq!(HALF.saturating_add(HALF), NodeMagnitude::ONE);
+        assert_eq!(
+            NodeMagnitude::ZERO.saturating_sub(HALF),
+            NodeMagnitude::ZERO
+        );
+        assert_eq!(NodeMagnitude::ZERO.invert(), NodeMagnitude::ONE);
+
+        assert_eq!(HALF.gamma(2.0), HALF * HALF);
+        assert_eq!(NodeMagnitude::ONE.gamma(2.2), NodeMagnitude::ONE);
+        assert_eq!(HALF.smoothstep(), HALF);
+        assert!(NodeMagnitude::from_f64(0.25).smoothstep() < NodeMagnitude::from_f64(0.25));
+    }
+}
@@ -0,0 +1,203 @@
+mod audio;
+mod bitmap;
+mod color;
+mod composite;
+mod convert;
+mod enumeration;
+mod literal;
+mod magnitude;
+mod time;
+mod vector;
+
+pub use self::audio::*;
+pub use self::bitmap::*;
+pub use self::color::*;
+pub use self::composite::*;
+pub use self::convert::*;
+pub use self::enumeration::*;
+pub use self::literal::*;
+pub use self::magnitude::*;
+pub use self::time::*;
+pub use self::vector::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Type alias for Trigger booleans
+pub type TriggerSignal = bool;
+
+/// Type alias for 1D shader program indices.
+pub type Shader1DProgramId = u16;
+
+/// Type alias for 2D shader program indices.
+pub type Shader2DProgramId = u16;
+
+/// Type alias for 3D shader program indices.
+pub type Shader3DProgramId = u16;
+
+/// Proton-specific data type representation. Large payloads are reference counted, so
+/// cloning a value to pass it to another Node never copies pixel or text data. Use
+/// `Arc::make_mut` to get a mutable payload, which copies only if it is still shared.
+#[derive(Debug, EnumDiscriminants, PartialEq, Clone, Serialize, Deserialize)]
+#[strum_discriminants(name(NodeValueType), derive(Hash, EnumIter, Serialize, Deserialize))]
+pub enum NodeValue {
+    /// Stateless value, acts as a way of kicking off an action.
+    Trigger(TriggerSignal),
+
+    /// Boolean value, used to switch something on or off
+    Toggle(bool),
+
+    /// Signed integer value. Acts just like i64 in Rust.
+    Count(i64),
+
+    /// Represents a value from 0 to 1 with a precision of 1/(2^32). Use NodeMagnitude
+    /// to convert it or do math on it.
+    ConstrainedMagnitude(u32),
+
+    /// Like ConstrainedMagnitude, meant to represent a value of 0 to 1. Unlike
+    /// ConstrainedMagnitude it is actually able to go outside of those bounds, allowing
+    /// the value to be inverted (passed a value < 0) or 'over-driven' (passed a value > 1).
+    UnconstrainedMagnitude(#[serde(with = "crate::serialization::any_f64")] f64),
+
+    /// An RGB color with an alpha channel. Supports 16-bits per channel to allow for HDR
+    /// content or colors on devices like RGB LEDs that may have color accuracy beyond that
+    /// of most monitors.
+    Color(NodeColor),
+
+    /// UTF-8 string data.
+    Text(Arc<String>),
+
+    /// 1-dimensional bitmap image. Stored uncompressed.
+    Bitmap1D(Arc<Bitmap1D>),
+
+    /// 2-dimensional bitmap image. Stored uncompressed in a contiguous buffer.
+    Bitmap2D(Arc<Bitmap2D>),
+
+    /// Shader program with a 1-dimensional positional input. Stores the index of the program,
+    /// not the program itself, so that this value can be comparable and clonable.
+    Shader1D(Shader1DProgramId),
+
+    /// Shader program with a 2-dimensional positional input. Stores the index of the program,
+    /// not the program itself, so that this value can be comparable and clonable.
+    Shader2D(Shader2DProgramId),
+
+    /// Shader program with a 3-dimensional positional input. Stores the index of the program,
+    /// not the program itself, so that this value can be comparable and clonable.
+    Shader3D(Shader3DProgramId),
+
+    /// 2-dimensional vector, such as a position or direction on a plane.
+    Vector2D(NodeVector2),
+
+    /// 3-dimensional vector, such as a position or direction in space.
+    Vector3D(NodeVector3),
+
+    /// Signed span of time with microsecond precision.
+    Duration(NodeDuration),
+
+    /// Point in time with microsecond precision.
+    Timestamp(NodeTimestamp),
+
+    /// One option out of a named set of options, such as a blend mode or a direction.
+    Enumeration(NodeEnumValue),
+
+    /// Homogeneous list of values.
+    List(Arc<NodeList>),
+
+    /// Set of named values.
+    Record(Arc<NodeRecord>),
+
+    /// Block of PCM audio samples, such as the audio played since the last frame.
+    Audio(Arc<AudioBlock>),
+}
+
+impl NodeValue {
+    /// A neutral value of the given type, such as 0, false or transparent black.
+    /// Returns None for types that have no sensible default, such as shaders and
+    /// enumerations, or whose default depends on what they contain, such as lists.
+    pub fn default_for(value_type: NodeValueType) -> Option<NodeValue> {
+        match value_type {
+            NodeValueType::Trigger => Some(NodeValue::Trigger(false)),
+            NodeValueType::Toggle => Some(NodeValue::Toggle(false)),
+            NodeValueType::Count => Some(NodeValue::Count(0)),
+            NodeValueType::ConstrainedMagnitude => Some(NodeValue::ConstrainedMagnitude(0)),
+            NodeValueType::UnconstrainedMagnitude => Some(NodeValue::UnconstrainedMagnitude(0.0)),
+            NodeValueType::Color => Some(NodeValue::Color(NodeColor::TRANSPARENT)),
+            NodeValueType::Text => Some(NodeValue::Text(Arc::new(String::new()))),
+            NodeValueType::Bitmap1D => Some(NodeValue::Bitmap1D(Arc::new(Bitmap1D::default()))),
+            NodeValueType::Bitmap2D => Some(NodeValue::Bitmap2D(Arc::new(Bitmap2D::default()))),
+            NodeValueType::Vector2D => Some(NodeValue::Vector2D(NodeVector2::ZERO)),
+            NodeValueType::Vector3D => Some(NodeValue::Vector3D(NodeVector3::ZERO)),
+            NodeValueType::Duration => Some(NodeValue::Duration(NodeDuration::ZERO)),
+            NodeValueType::Timestamp => Some(NodeValue::Timestamp(NodeTimestamp::default())),
+            NodeValueType::Record => Some(NodeValue::Record(Arc::new(NodeRecord::new()))),
+            NodeValueType::Audio => Some(NodeValue::Audio(Arc::new(AudioBlock::default()))),
+            NodeValueType::Shader1D
+            | NodeValueType::Shader2D
+            | NodeValueType::Shader3D
+            | NodeValueType::Enumeration
+            | NodeValueType::List => None,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod fixtures {
+    use super::*;
+
+    /// One value of every type.
+    pub fn every_value() -> Vec<NodeValue> {
+        let options = Arc::new(NodeEnumDef::new("shape", &["circle", "square"]));
+        let mut record = NodeRecord::new();
+        record.set("size", NodeValue::Count(3));
+        vec![
+            NodeValue::Trigger(true),
+            NodeValue::Toggle(false),
+            NodeValue::Count(-5),
+            NodeValue::ConstrainedMagnitude(u32::MAX),
+            NodeValue::UnconstrainedMagnitude(0.25),
+            NodeValue::Color(NodeColor::new(1, 2, 3, 4)),
+            NodeValue::Text(Arc::new("hello".to_string())),
+            NodeValue::Bitmap1D(Arc::new(Bitmap1D::filled(2, NodeColor::WHITE))),
+            NodeValue::Bitmap2D(Arc::new(Bitmap2D::filled(2, 3, NodeColor::BLACK))),
+            NodeValue::Shader1D(1),
+            NodeValue::Shader2D(2),
+            NodeValue::Shader3D(3),
+            NodeValue::Vector2D(NodeVector2::new(1.0, -2.0)),
+            NodeValue::Vector3D(NodeVector3::new(1.0, 2.0, 3.5)),
+            NodeValue::Duration(NodeDuration::from_millis(-20)),
+            NodeValue::Timestamp(NodeTimestamp::from_micros_since_epoch(1_600_000_000)),
+            NodeValue::Enumeration(NodeEnumValue::from_option(options, "square").unwrap()),
+            NodeValue::List(Arc::new(
+                NodeList::new(
+                    NodeValueType::Count.into(),
+                    vec![NodeValue::Count(1), NodeValue::Count(2)],
+                )
+                .unwrap(),
+            )),
+            NodeValue::Record(Arc::new(record)),
+            NodeValue::Audio(Arc::new(AudioBlock::new(100, 2, vec![0.5, -0.5]).unwrap())),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_payloads_until_mutated() {
+        let original = NodeValue::Bitmap2D(Arc::new(Bitmap2D::new(3840, 2160)));
+        let mut copy = original.clone();
+        if let (NodeValue::Bitmap2D(a), NodeValue::Bitmap2D(b)) = (&original, &copy) {
+            assert!(Arc::ptr_eq(a, b));
+        }
+
+        if let NodeValue::Bitmap2D(bitmap) = &mut copy {
+            *Arc::make_mut(bitmap).get_mut(0, 0).unwrap() = NodeColor::WHITE;
+        }
+        if let (NodeValue::Bitmap2D(a), NodeValue::Bitmap2D(b)) = (&original, &copy) {
+            assert!(!Arc::ptr_eq(a, b));
+            assert_eq!(a.get(0, 0), Some(&NodeColor::TRANSPARENT));
+            assert_eq!(b.get(0, 0), Some(&NodeColor::WHITE));
+        }
+    }
+}
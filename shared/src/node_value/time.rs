@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, Mul, Neg, Sub};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A signed span of time with microsecond precision. Unlike std::time::Duration it can
+/// be negative, so the difference between any two timestamps can be represented.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub struct NodeDuration {
+    micros: i64,
+}
+
+/// A point in time, stored as microseconds since the Unix epoch.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub struct NodeTimestamp {
+    micros_since_epoch: i64,
+}
+
+impl NodeDuration {
+    pub const ZERO: NodeDuration = NodeDuration::from_micros(0);
+
+    pub const fn from_micros(micros: i64) -> NodeDuration {
+        NodeDuration { micros }
+    }
+
+    pub const fn from_millis(millis: i64) -> NodeDuration {
+        NodeDuration::from_micros(millis.saturating_mul(1000))
+    }
+
+    pub fn from_secs_f64(secs: f64) -> NodeDuration {
+        NodeDuration::from_micros((secs * 1_000_000.0).round() as i64)
+    }
+
+    pub const fn as_micros(&self) -> i64 {
+        self.micros
+    }
+
+    pub fn as_secs_f64(&self) -> f64 {
+        self.micros as f64 / 1_000_000.0
+    }
+
+    pub const fn is_negative(&self) -> bool {
+        self.micros < 0
+    }
+
+    pub const fn abs(&self) -> NodeDuration {
+        NodeDuration::from_micros(self.micros.saturating_abs())
+    }
+}
+
+impl From<Duration> for NodeDuration {
+    /// Saturates at the largest representable duration.
+    fn from(duration: Duration) -> NodeDuration {
+        NodeDuration::from_micros(duration.as_micros().min(i64::MAX as u128) as i64)
+    }
+}
+
+impl NodeTimestamp {
+    pub const fn from_micros_since_epoch(micros: i64) -> NodeTimestamp {
+        NodeTimestamp {
+            micros_since_epoch: micros,
+        }
+    }
+
+    /// The current wall clock time.
+    pub fn now() -> NodeTimestamp {
+        NodeTimestamp::from(SystemTime::now())
+    }
+
+    pub const fn as_micros_since_epoch(&self) -> i64 {
+        self.micros_since_epoch
+    }
+}
+
+impl From<SystemTime> for NodeTimestamp {
+    fn from(time: SystemTime) -> NodeTimestamp {
+        let since_epoch = match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => NodeDuration::from(after),
+            Err(before) => -NodeDuration::from(before.duration()),
+        };
+        NodeTimestamp::from_micros_since_epoch(since_epoch.as_micros())
+    }
+}
+
+impl Add for NodeDuration {
+    type Output = NodeDuration;
+    fn add(self, other: NodeDuration) -> NodeDuration {
+        NodeDuration::from_micros(self.micros.saturating_add(other.micros))
+    }
+}
+
+impl Sub for NodeDuration {
+    type Output = NodeDuration;
+    fn sub(self, other: NodeDuration) -> NodeDuration {
+        NodeDuration::from_micros(self.micros.saturating_sub(other.micros))
+    }
+}
+
+impl Mul<f64> for NodeDuration {
+    type Output = NodeDuration;
+    fn mul(self, scale: f64) -> NodeDuration {
+        NodeDuration::from_micros((self.micros as f64 * scale).round() as i64)
+    }
+}
+
+impl Neg for NodeDuration {
+    type Output = NodeDuration;
+    fn neg(self) -> NodeDuration {
+        NodeDuration::from_micros(self.micros.saturating_neg())
+    }
+}
+
+impl Add<NodeDuration> for NodeTimestamp {
+    type Output = NodeTimestamp;
+    fn add(self, duration: NodeDuration) -> NodeTimestamp {
+        NodeTimestamp::from_micros_since_epoch(
+            self.micros_since_epoch.saturating_add(duration.as_micros()),
+        )
+    }
+}
+
+impl Sub<NodeDuration> for NodeTimestamp {
+    type Output = NodeTimestamp;
+    fn sub(self, duration: NodeDuration) -> NodeTimestamp {
+        NodeTimestamp::from_micros_since_epoch(
+            self.micros_since_epoch.saturating_sub(duration.as_micros()),
+        )
+    }
+}
+
+impl Sub for NodeTimestamp {
+    type Output = NodeDuration;
+    fn sub(self, other: NodeTimestamp) -> NodeDuration {
+        NodeDuration::from_micros(
+            self.micros_since_epoch
+                .saturating_sub(other.micros_since_epoch),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn does_time_math() {
+        let start = NodeTimestamp::from_micros_since_epoch(1_000_000);
+        let later = start + NodeDuration::from_millis(1500);
+        assert_eq!(later - start, NodeDuration::from_secs_f64(1.5));
+        assert_eq!(start - later, NodeDuration::from_micros(-1_500_000));
+        assert!((start - later).is_negative());
+        assert_eq!((later - start) * 2.0, NodeDuration::from_millis(3000));
+        assert_eq!(
+            NodeDuration::from(Duration::from_millis(5)),
+            NodeDuration::from_millis(5)
+        );
+    }
+}
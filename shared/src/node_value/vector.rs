@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// A 2-dimensional vector, used for positions and directions on a plane.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct NodeVector2 {
+    #[serde(with = "crate::serialization::any_f64")]
+    pub x: f64,
+    #[serde(with = "crate::serialization::any_f64")]
+    pub y: f64,
+}
+
+/// A 3-dimensional vector, used for positions and directions in space.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct NodeVector3 {
+    #[serde(with = "crate::serialization::any_f64")]
+    pub x: f64,
+    #[serde(with = "crate::serialization::any_f64")]
+    pub y: f64,
+    #[serde(with = "crate::serialization::any_f64")]
+    pub z: f64,
+}
+
+impl NodeVector2 {
+    pub const ZERO: NodeVector2 = NodeVector2::new(0.0, 0.0);
+
+    pub const fn new(x: f64, y: f64) -> NodeVector2 {
+        NodeVector2 { x, y }
+    }
+
+    pub fn dot(&self, other: &NodeVector2) -> f64 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn length(&self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    /// Returns a vector in the same direction with a length of 1, or the zero vector
+    /// if this vector has no length.
+    pub fn normalized(&self) -> NodeVector2 {
+        let length = self.length();
+        if length == 0.0 {
+            return NodeVector2::ZERO;
+        }
+        *self * (1.0 / length)
+    }
+
+    /// Linearly interpolates towards another vector, where 0 is self and 1 is other.
+    pub fn lerp(&self, other: &NodeVector2, amount: f64) -> NodeVector2 {
+        *self + (*other - *self) * amount
+    }
+}
+
+impl NodeVector3 {
+    pub const ZERO: NodeVector3 = NodeVector3::new(0.0, 0.0, 0.0);
+
+    pub const fn new(x: f64, y: f64, z: f64) -> NodeVector3 {
+        NodeVector3 { x, y, z }
+    }
+
+    pub fn dot(&self, other: &NodeVector3) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: &NodeVector3) -> NodeVector3 {
+        NodeVector3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(&self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    /// Returns a vector in the same direction with a length of 1, or the zero vector
+    /// if this vector has no length.
+    pub fn normalized(&self) -> NodeVector3 {
+        let length = self.length();
+        if length == 0.0 {
+            return NodeVector3::ZERO;
+        }
+        *self * (1.0 / length)
+    }
+
+    /// Linearly interpolates towards another vector, where 0 is self and 1 is other.
+    pub fn lerp(&self, other: &NodeVector3, amount: f64) -> NodeVector3 {
+        *self + (*other - *self) * amount
+    }
+}
+
+impl Add for NodeVector2 {
+    type Output = NodeVector2;
+    fn add(self, other: NodeVector2) -> NodeVector2 {
+        NodeVector2::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for NodeVector2 {
+    type Output = NodeVector2;
+    fn sub(self, other: NodeVector2) -> NodeVector2 {
+        NodeVector2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<f64> for NodeVector2 {
+    type Output = NodeVector2;
+    fn mul(self, scale: f64) -> NodeVector2 {
+        NodeVector2::new(self.x * scale, self.y * scale)
+    }
+}
+
+impl Neg for NodeVector2 {
+    type Output = NodeVector2;
+    fn neg(self) -> NodeVector2 {
+        NodeVector2::new(-self.x, -self.y)
+    }
+}
+
+impl Add for NodeVector3 {
+    type Output = NodeVector3;
+    fn add(self, other: NodeVector3) -> NodeVector3 {
+        NodeVector3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for NodeVector3 {
+    type Output = NodeVector3;
+    fn sub(self, other: NodeVector3) -> NodeVector3 {
+        NodeVector3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f64> for NodeVector3 {
+    type Output = NodeVector3;
+    fn mul(self, scale: f64) -> NodeVector3 {
+        NodeVector3::new(self.x * scale, self.y * scale, self.z * scale)
+    }
+}
+
+impl Neg for NodeVector3 {
+    type Output = NodeVector3;
+    fn neg(self) -> NodeVector3 {
+        NodeVector3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn does_vector_math() {
+        let a = NodeVector3::new(1.0, 0.0, 0.0);
+        let b = NodeVector3::new(0.0, 1.0, 0.0);
+        assert_eq!(a.cross(&b), NodeVector3::new(0.0, 0.0, 1.0));
+        assert_eq!(a.dot(&b), 0.0);
+        assert_eq!(a.lerp(&b, 0.5), NodeVector3::new(0.5, 0.5, 0.0));
+
+        assert_eq!(NodeVector2::new(3.0, 4.0).length(), 5.0);
+        assert_eq!(
+            NodeVector2::new(0.0, -2.0).normalized(),
+            NodeVector2::new(0.0, -1.0)
+        );
+        assert_eq!(NodeVector2::ZERO.normalized(), NodeVector2::ZERO);
+    }
+}
@@ -0,0 +1,206 @@
+use super::node_value::NodeVector3;
+use serde::{Deserialize, Serialize};
+
+/// A light fixture, such as an LED strip or a single spotlight, along with where each
+/// of its LEDs is in space. LEDs are listed in the order the fixture is addressed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    pub name: String,
+    pub positions: Vec<NodeVector3>,
+}
+
+impl Fixture {
+    pub fn new(name: &str, positions: Vec<NodeVector3>) -> Fixture {
+        Fixture {
+            name: name.to_string(),
+            positions,
+        }
+    }
+
+    /// A fixture with a single LED, such as a spotlight.
+    pub fn point(name: &str, position: NodeVector3) -> Fixture {
+        Fixture::new(name, vec![position])
+    }
+
+    /// A straight strip of evenly spaced LEDs, with the first at `start` and the last
+    /// at `end`.
+    pub fn line(name: &str, start: NodeVector3, end: NodeVector3, count: usize) -> Fixture {
+        let positions = (0..count)
+            .map(|index| match count {
+                1 => start,
+                _ => start.lerp(&end, index as f64 / (count - 1) as f64),
+            })
+            .collect();
+        Fixture::new(name, positions)
+    }
+}
+
+/// Where every LED of an installation is, grouped by fixture. Positions can use any
+/// unit, since Nodes sample effects at positions normalized to the bounds of the
+/// whole map. This lets one effect span every fixture regardless of the layout.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(from = "PixelMapFields")]
+pub struct PixelMap {
+    fixtures: Vec<Fixture>,
+
+    /// Smallest and largest coordinates of any LED, or None if there are no LEDs.
+    #[serde(skip)]
+    bounds: Option<(NodeVector3, NodeVector3)>,
+}
+
+impl PixelMap {
+    pub fn new(fixtures: Vec<Fixture>) -> PixelMap {
+        let mut map = PixelMap {
+            fixtures,
+            bounds: None,
+        };
+        map.update_bounds();
+        map
+    }
+
+    pub fn fixtures(&self) -> &[Fixture] {
+        &self.fixtures
+    }
+
+    pub fn fixture(&self, name: &str) -> Option<&Fixture> {
+        self.fixtures.iter().find(|fixture| fixture.name == name)
+    }
+
+    /// Adds a fixture, replacing any existing fixture with the same name.
+    pub fn set_fixture(&mut self, fixture: Fixture) {
+        match self.fixtures.iter_mut().find(|f| f.name == fixture.name) {
+            Some(existing) => *existing = fixture,
+            None => self.fixtures.push(fixture),
+        }
+        self.update_bounds();
+    }
+
+    /// Removes a fixture and returns it, if there was one with the name.
+    pub fn remove_fixture(&mut self, name: &str) -> Option<Fixture> {
+        let index = self.fixtures.iter().position(|f| f.name == name)?;
+        let fixture = self.fixtures.remove(index);
+        self.update_bounds();
+        Some(fixture)
+    }
+
+    /// Total number of LEDs across every fixture.
+    pub fn led_count(&self) -> usize {
+        self.fixtures.iter().map(|f| f.positions.len()).sum()
+    }
+
+    /// Smallest and largest coordinates of any LED, or None if there are no LEDs.
+    pub fn bounds(&self) -> Option<(NodeVector3, NodeVector3)> {
+        self.bounds
+    }
+
+    /// Maps a position so that the bounds of the map go from 0 to 1 along each axis.
+    /// Axes that every LED shares the same coordinate on map to 0.
+    pub fn normalize(&self, position: NodeVector3) -> NodeVector3 {
+        let (min, max) = match self.bounds {
+            Some(bounds) => bounds,
+            None => return NodeVector3::ZERO,
+        };
+        let axis = |value: f64, min: f64, max: f64| {
+            if max > min {
+                (value - min) / (max - min)
+            } else {
+                0.0
+            }
+        };
+        NodeVector3::new(
+            axis(position.x, min.x, max.x),
+            axis(position.y, min.y, max.y),
+            axis(position.z, min.z, max.z),
+        )
+    }
+
+    /// Normalized positions of a fixture's LEDs, in order, or None if the map has no
+    /// fixture with the name.
+    pub fn normalized_positions(&self, name: &str) -> Option<Vec<NodeVector3>> {
+        let fixture = self.fixture(name)?;
+        Some(
+            fixture
+                .positions
+                .iter()
+                .map(|position| self.normalize(*position))
+                .collect(),
+        )
+    }
+
+    fn update_bounds(&mut self) {
+        self.bounds = None;
+        for position in self.fixtures.iter().flat_map(|f| &f.positions) {
+            let (min, max) = self.bounds.get_or_insert((*position, *position));
+            *min = NodeVector3::new(
+                min.x.min(position.x),
+                min.y.min(position.y),
+                min.z.min(position.z),
+            );
+            *max = NodeVector3::new(
+                max.x.max(position.x),
+                max.y.max(position.y),
+                max.z.max(position.z),
+            );
+        }
+    }
+}
+
+/// Fields of a deserialized PixelMap, before its bounds are worked out.
+#[derive(Deserialize)]
+struct PixelMapFields {
+    fixtures: Vec<Fixture>,
+}
+
+impl From<PixelMapFields> for PixelMap {
+    fn from(fields: PixelMapFields) -> PixelMap {
+        PixelMap::new(fields.fixtures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::{from_json, to_json};
+
+    #[test]
+    fn normalizes_positions_to_bounds() {
+        let mut map = PixelMap::new(vec![Fixture::line(
+            "strip",
+            NodeVector3::new(-2.0, 1.0, 0.0),
+            NodeVector3::new(2.0, 1.0, 0.0),
+            5,
+        )]);
+        assert_eq!(
+            map.normalized_positions("strip").unwrap()[1],
+            NodeVector3::new(0.25, 0.0, 0.0)
+        );
+
+        map.set_fixture(Fixture::point("spot", NodeVector3::new(0.0, 3.0, 0.0)));
+        assert_eq!(map.led_count(), 6);
+        assert_eq!(
+            map.normalize(NodeVector3::new(2.0, 2.0, 0.0)),
+            NodeVector3::new(1.0, 0.5, 0.0)
+        );
+        assert_eq!(map.normalized_positions("flood"), None);
+
+        map.remove_fixture("spot");
+        assert_eq!(
+            map.bounds(),
+            Some((
+                NodeVector3::new(-2.0, 1.0, 0.0),
+                NodeVector3::new(2.0, 1.0, 0.0)
+            ))
+        );
+    }
+
+    #[test]
+    fn recomputes_bounds_when_deserialized() {
+        let map = PixelMap::new(vec![
+            Fixture::point("left", NodeVector3::new(-1.0, 0.0, 0.0)),
+            Fixture::point("right", NodeVector3::new(1.0, 0.0, 4.0)),
+        ]);
+        let json = to_json(&map).unwrap();
+        assert!(!json.contains("bounds"));
+        assert_eq!(from_json::<PixelMap>(&json).unwrap(), map);
+    }
+}
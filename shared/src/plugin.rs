@@ -0,0 +1,85 @@
+//! The plugin side of native plugins: shared libraries that add NodeDefs to a server
+//! without rebuilding it.
+//!
+//! A plugin is a `cdylib` crate that depends on proton_shared and declares itself with
+//! `declare_plugin!`:
+//!
+//! ```ignore
+//! use proton_shared::declare_plugin;
+//! use proton_shared::node_def_registry::{NodeDefRegistryError, NodeDefVersion};
+//! use proton_shared::plugin::PluginRegistrar;
+//!
+//! fn register(registrar: &mut dyn PluginRegistrar) -> Result<(), NodeDefRegistryError> {
+//!     registrar.register_node_def("acme.lasers.scan", NodeDefVersion::INITIAL, scan_def())
+//! }
+//!
+//! declare_plugin!("acme_lasers", register);
+//! ```
+//!
+//! Rust has no stable ABI, so a plugin has to be built by the same compiler and against
+//! the same build of proton_shared as the server: the same sources, features and target.
+//! The server checks both before calling into the plugin.
+
+use super::node_def::NodeDef;
+use super::node_def_registry::{NodeDefRegistryError, NodeDefVersion};
+
+/// Version of the PluginDeclaration layout. Bump this whenever it changes shape.
+pub const PLUGIN_ABI_VERSION: u32 = 2;
+
+/// Compiler that built this copy of proton_shared.
+pub const RUSTC_VERSION: &str = env!("PROTON_RUSTC_VERSION");
+
+/// Version of this copy of proton_shared.
+pub const SHARED_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Identifies the exact build of this copy of proton_shared, from a hash of its sources
+/// and enabled features, and the target it was built for. Two copies with the same
+/// version can still lay out their types differently, such as when one has local
+/// changes.
+pub const SHARED_BUILD_ID: &str = env!("PROTON_SHARED_BUILD_ID");
+
+/// Name of the static that `declare_plugin!` exports.
+pub const PLUGIN_DECLARATION_SYMBOL: &[u8] = b"PROTON_PLUGIN_DECLARATION\0";
+
+/// What a plugin exports for the server to find it by. The ABI version comes first,
+/// so that it can still be read if the rest of the layout has changed.
+#[repr(C)]
+pub struct PluginDeclaration {
+    pub abi_version: u32,
+    pub rustc_version: &'static str,
+    pub shared_version: &'static str,
+    pub shared_build_id: &'static str,
+
+    /// Name of the plugin, for error messages.
+    pub name: &'static str,
+    pub register: fn(&mut dyn PluginRegistrar) -> Result<(), NodeDefRegistryError>,
+}
+
+/// Lets a plugin add its NodeDefs to the server's registry. Output devices are added
+/// like any other def, with a NodeDefRunner::OutputDevice runner.
+pub trait PluginRegistrar {
+    fn register_node_def(
+        &mut self,
+        id: &str,
+        version: NodeDefVersion,
+        def: NodeDef,
+    ) -> Result<(), NodeDefRegistryError>;
+}
+
+/// Exports the PluginDeclaration of a plugin crate, given its name and a function that
+/// registers its defs.
+#[macro_export]
+macro_rules! declare_plugin {
+    ($name:expr, $register:expr) => {
+        #[no_mangle]
+        pub static PROTON_PLUGIN_DECLARATION: $crate::plugin::PluginDeclaration =
+            $crate::plugin::PluginDeclaration {
+                abi_version: $crate::plugin::PLUGIN_ABI_VERSION,
+                rustc_version: $crate::plugin::RUSTC_VERSION,
+                shared_version: $crate::plugin::SHARED_VERSION,
+                shared_build_id: $crate::plugin::SHARED_BUILD_ID,
+                name: $name,
+                register: $register,
+            };
+    };
+}
@@ -0,0 +1,246 @@
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::fmt;
+
+/// Version of the serialized format written by this build. Bump this whenever a
+/// serializable type changes shape, and keep reading older versions where possible.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Oldest format version this build can still read.
+pub const MIN_SUPPORTED_VERSION: u32 = 1;
+
+/// Largest binary body `from_binary` reads, in bytes. Enough for a 4096x4096 bitmap,
+/// while keeping a corrupt or hostile length prefix from allocating without bound.
+pub const MAX_BINARY_SIZE: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum SerializationError {
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+
+    /// The data is too short to contain a version header.
+    MissingVersion,
+
+    /// The data was written by a newer or much older build.
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SerializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializationError::Json(err) => write!(f, "Invalid JSON: {}", err),
+            SerializationError::Binary(err) => write!(f, "Invalid binary data: {}", err),
+            SerializationError::MissingVersion => f.write_str("Missing format version"),
+            SerializationError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported format version {} (supported: {} to {})",
+                version, MIN_SUPPORTED_VERSION, FORMAT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SerializationError {}
+
+impl From<serde_json::Error> for SerializationError {
+    fn from(err: serde_json::Error) -> SerializationError {
+        SerializationError::Json(err)
+    }
+}
+
+impl From<bincode::Error> for SerializationError {
+    fn from(err: bincode::Error) -> SerializationError {
+        SerializationError::Binary(err)
+    }
+}
+
+/// Envelope that tags JSON data with the format version it was written in.
+#[derive(Serialize, Deserialize)]
+struct Versioned<T> {
+    version: u32,
+    data: T,
+}
+
+fn check_version(version: u32) -> Result<(), SerializationError> {
+    if version < MIN_SUPPORTED_VERSION || version > FORMAT_VERSION {
+        return Err(SerializationError::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+/// Serializes to human-readable JSON, wrapped as `{"version": 1, "data": ...}`.
+pub fn to_json<T: Serialize>(value: &T) -> Result<String, SerializationError> {
+    let versioned = Versioned {
+        version: FORMAT_VERSION,
+        data: value,
+    };
+    Ok(serde_json::to_string_pretty(&versioned)?)
+}
+
+pub fn from_json<T: DeserializeOwned>(json: &str) -> Result<T, SerializationError> {
+    let versioned: Versioned<serde_json::Value> = serde_json::from_str(json)?;
+    check_version(versioned.version)?;
+    Ok(serde_json::from_value(versioned.data)?)
+}
+
+/// Settings for the bincode encoding, which are the same as `bincode::serialize` uses
+/// apart from the size limit.
+fn bincode_options() -> impl Options {
+    bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(MAX_BINARY_SIZE)
+}
+
+/// Serializes to a compact binary form for sending over the wire: a little-endian
+/// u32 format version followed by the bincode encoding of the value.
+pub fn to_binary<T: Serialize>(value: &T) -> Result<Vec<u8>, SerializationError> {
+    let mut bytes = FORMAT_VERSION.to_le_bytes().to_vec();
+    bincode_options().serialize_into(&mut bytes, value)?;
+    Ok(bytes)
+}
+
+pub fn from_binary<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, SerializationError> {
+    if bytes.len() < 4 {
+        return Err(SerializationError::MissingVersion);
+    }
+    let (header, body) = bytes.split_at(4);
+    check_version(u32::from_le_bytes(header.try_into().unwrap()))?;
+    Ok(bincode_options().deserialize(body)?)
+}
+
+/// Serde functions for f64 fields that can hold NaN or infinity. JSON has no way to
+/// write those, so they are written as the strings `"NaN"`, `"inf"` and `"-inf"`
+/// instead. Binary formats store every value as a plain number.
+pub(crate) mod any_f64 {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() || value.is_finite() {
+            serializer.serialize_f64(*value)
+        } else if value.is_nan() {
+            serializer.serialize_str("NaN")
+        } else if *value > 0.0 {
+            serializer.serialize_str("inf")
+        } else {
+            serializer.serialize_str("-inf")
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        if !deserializer.is_human_readable() {
+            return f64::deserialize(deserializer);
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Written {
+            Number(f64),
+            Name(String),
+        }
+        match Written::deserialize(deserializer)? {
+            Written::Number(value) => Ok(value),
+            Written::Name(name) => match name.as_str() {
+                "NaN" => Ok(f64::NAN),
+                "inf" => Ok(f64::INFINITY),
+                "-inf" => Ok(f64::NEG_INFINITY),
+                _ => Err(D::Error::custom(format!("Invalid number {:?}", name))),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node_def::{NodeDefBasicDescription, NodeInputDef};
+    use crate::node_value::fixtures::every_value;
+    use crate::node_value::*;
+    use std::sync::Arc;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn round_trips_every_value() {
+        let values = every_value();
+        assert_eq!(values.len(), NodeValueType::iter().count());
+        for value in values {
+            assert_eq!(
+                from_json::<NodeValue>(&to_json(&value).unwrap()).unwrap(),
+                value
+            );
+            assert_eq!(
+                from_binary::<NodeValue>(&to_binary(&value).unwrap()).unwrap(),
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_defs() {
+        let def = NodeInputDef {
+            desc: NodeDefBasicDescription {
+                name: "points".to_string(),
+                description: "Points to draw".to_string(),
+            },
+            allowed_types: vec![NodeValueType::List],
+            required: true,
+            enum_def: None,
+            composite_type: Some(NodeCompositeType::list_of(NodeValueType::Vector2D.into())),
+            default: None,
+        };
+        let json = to_json(&def).unwrap();
+        assert!(json.contains("\"version\": 1"));
+        assert_eq!(from_json::<NodeInputDef>(&json).unwrap(), def);
+        assert_eq!(
+            from_binary::<NodeInputDef>(&to_binary(&def).unwrap()).unwrap(),
+            def
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_data() {
+        let mut bytes = to_binary(&NodeValue::Count(1)).unwrap();
+        bytes[0] = 99;
+        match from_binary::<NodeValue>(&bytes) {
+            Err(SerializationError::UnsupportedVersion(99)) => {}
+            result => panic!("Expected a version error, got {:?}", result),
+        }
+
+        let json = r#"{"version": 1, "data": {"width": 2, "height": 2, "pixels": []}}"#;
+        assert!(from_json::<Bitmap2D>(json).is_err());
+
+        // A list claiming to hold more items than the limit allows, with none present.
+        let mut bytes = to_binary(&NodeValue::Text(Arc::new(String::new()))).unwrap();
+        let length = bytes.len() - 8;
+        bytes[length..].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(from_binary::<NodeValue>(&bytes).is_err());
+    }
+
+    #[test]
+    fn round_trips_non_finite_numbers() {
+        let values = vec![
+            NodeValue::UnconstrainedMagnitude(f64::INFINITY),
+            NodeValue::UnconstrainedMagnitude(f64::NEG_INFINITY),
+            NodeValue::Vector2D(NodeVector2::new(f64::INFINITY, 1.5)),
+            NodeValue::Vector3D(NodeVector3::new(0.0, f64::NEG_INFINITY, 2.0)),
+        ];
+        for value in values {
+            let json = to_json(&value).unwrap();
+            assert!(json.contains("inf"), "{}", json);
+            assert_eq!(from_json::<NodeValue>(&json).unwrap(), value);
+            assert_eq!(
+                from_binary::<NodeValue>(&to_binary(&value).unwrap()).unwrap(),
+                value
+            );
+        }
+
+        let nan = NodeValue::UnconstrainedMagnitude(f64::NAN);
+        match from_json::<NodeValue>(&to_json(&nan).unwrap()).unwrap() {
+            NodeValue::UnconstrainedMagnitude(value) => assert!(value.is_nan()),
+            value => panic!("Expected a magnitude, got {:?}", value),
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use super::parse::{BinaryOp, Expr, Spanned};
+use super::{noise, ColorOutput, Op, ShaderExpressionError, MAX_STACK_DEPTH};
+use std::f64::consts::{E, PI, TAU};
+
+/// A function that can be called from an expression.
+enum Function {
+    Unary(fn(f64) -> f64),
+    Binary(fn(f64, f64) -> f64),
+    Ternary(fn(f64, f64, f64) -> f64),
+
+    /// Noise takes 1 to 3 coordinates. Missing ones are filled in with 0.
+    Noise,
+}
+
+fn function(name: &str) -> Option<Function> {
+    use Function::*;
+    Some(match name {
+        "sin" => Unary(f64::sin),
+        "cos" => Unary(f64::cos),
+        "tan" => Unary(f64::tan),
+        "asin" => Unary(f64::asin),
+        "acos" => Unary(f64::acos),
+        "atan" => Unary(f64::atan),
+        "abs" => Unary(f64::abs),
+        "floor" => Unary(f64::floor),
+        "ceil" => Unary(f64::ceil),
+        "round" => Unary(f64::round),
+        "fract" => Unary(|x| x - x.floor()),
+        "sqrt" => Unary(f64::sqrt),
+        "exp" => Unary(f64::exp),
+        "log" => Unary(f64::ln),
+        "sign" => Unary(|x| if x == 0.0 { 0.0 } else { x.signum() }),
+        "min" => Binary(f64::min),
+        "max" => Binary(f64::max),
+        "pow" => Binary(f64::powf),
+        "atan2" => Binary(f64::atan2),
+        "mod" => Binary(f64::rem_euclid),
+        "step" => Binary(|edge, x| if x < edge { 0.0 } else { 1.0 }),
+        "clamp" => Ternary(|x, low, high| x.max(low).min(high)),
+        "mix" => Ternary(|a, b, amount| a + (b - a) * amount),
+        "smoothstep" => Ternary(|low, high, x| {
+            let t = ((x - low) / (high - low)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        }),
+        "noise" => Noise,
+        _ => return None,
+    })
+}
+
+fn color_output(name: &str) -> Option<ColorOutput> {
+    Some(match name {
+        "gray" => ColorOutput::Gray,
+        "rgb" => ColorOutput::Rgb,
+        "rgba" => ColorOutput::Rgba,
+        "hsv" => ColorOutput::Hsv,
+        "hsva" => ColorOutput::Hsva,
+        "hsl" => ColorOutput::Hsl,
+        "hsla" => ColorOutput::Hsla,
+        _ => return None,
+    })
+}
+
+fn binary_op(op: BinaryOp) -> fn(f64, f64) -> f64 {
+    match op {
+        BinaryOp::Add => |a, b| a + b,
+        BinaryOp::Subtract => |a, b| a - b,
+        BinaryOp::Multiply => |a, b| a * b,
+        BinaryOp::Divide => |a, b| a / b,
+        BinaryOp::Remainder => f64::rem_euclid,
+        BinaryOp::Power => f64::powf,
+    }
+}
+
+/// Turns a parsed expression into stack machine operations, folding any part of it
+/// that does not depend on position, time or uniforms into a constant.
+pub struct Compiler<'a> {
+    uniforms: &'a [&'a str],
+    pub ops: Vec<Op>,
+    depth: usize,
+    pub max_depth: usize,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(uniforms: &'a [&'a str]) -> Compiler<'a> {
+        Compiler {
+            uniforms,
+            ops: vec![],
+            depth: 0,
+            max_depth: 0,
+        }
+    }
+
+    /// Compiles the whole expression, which may be a color or a single number that
+    /// is used as a shade of gray.
+    pub fn color(&mut self, spanned: &Spanned) -> Result<ColorOutput, ShaderExpressionError> {
+        if let Expr::Call(name, arguments) = &spanned.expr {
+            if let Some(output) = color_output(name) {
+                self.check_arity(name, arguments, output.channel_count(), spanned.offset)?;
+                for argument in arguments {
+                    self.number(argument)?;
+                }
+                return Ok(output);
+            }
+        }
+        self.number(spanned)?;
+        Ok(ColorOutput::Gray)
+    }
+
+    fn check_arity(
+        &self,
+        name: &str,
+        arguments: &[Spanned],
+        expected: usize,
+        offset: usize,
+    ) -> Result<(), ShaderExpressionError> {
+        if arguments.len() != expected {
+            return Err(ShaderExpressionError::new(
+                &format!(
+                    "{} takes {} arguments but was given {}",
+                    name,
+                    expected,
+                    arguments.len()
+                ),
+                offset,
+            ));
+        }
+        Ok(())
+    }
+
+    fn number(&mut self, spanned: &Spanned) -> Result<(), ShaderExpressionError> {
+        match &spanned.expr {
+            Expr::Number(number) => self.push(Op::Const(*number), spanned.offset),
+            Expr::Name(name) => self.name(name, spanned.offset),
+            Expr::Negate(inner) => {
+                self.number(inner)?;
+                self.apply(Op::Unary(|x| -x), 1);
+                Ok(())
+            }
+            Expr::Binary(op, left, right) => {
+                self.number(left)?;
+                self.number(right)?;
+                self.apply(Op::Binary(binary_op(*op)), 2);
+                Ok(())
+            }
+            Expr::Call(name, arguments) => self.call(name, arguments, spanned.offset),
+        }
+    }
+
+    fn name(&mut self, name: &str, offset: usize) -> Result<(), ShaderExpressionError> {
+        let op = match name {
+            "x" => Op::X,
+            "y" => Op::Y,
+            "z" => Op::Z,
+            "t" => Op::Time,
+            "pi" => Op::Const(PI),
+            "tau" => Op::Const(TAU),
+            "e" => Op::Const(E),
+            _ => match self.uniforms.iter().position(|uniform| *uniform == name) {
+                Some(index) => Op::Uniform(index),
+                None => {
+                    return Err(ShaderExpressionError::new(
+                        &format!("Unknown name '{}'", name),
+                        offset,
+                    ))
+                }
+            },
+        };
+        self.push(op, offset)
+    }
+
+    fn call(
+        &mut self,
+        name: &str,
+        arguments: &[Spanned],
+        offset: usize,
+    ) -> Result<(), ShaderExpressionError> {
+        if color_output(name).is_some() {
+            return Err(ShaderExpressionError::new(
+                "Colors can only be used as the result of the whole expression",
+                offset,
+            ));
+        }
+        let function = function(name).ok_or_else(|| {
+            ShaderExpressionError::new(&format!("Unknown function '{}'", name), offset)
+        })?;
+
+        let (op, arity) = match function {
+            Function::Unary(f) => (Op::Unary(f), 1),
+            Function::Binary(f) => (Op::Binary(f), 2),
+            Function::Ternary(f) => (Op::Ternary(f), 3),
+            Function::Noise => {
+                if arguments.is_empty() || arguments.len() > 3 {
+                    return Err(ShaderExpressionError::new(
+                        "noise takes 1 to 3 arguments",
+                        offset,
+                    ));
+                }
+                for argument in arguments {
+                    self.number(argument)?;
+                }
+                for _ in arguments.len()..3 {
+                    self.push(Op::Const(0.0), offset)?;
+                }
+                self.apply(Op::Ternary(noise), 3);
+                return Ok(());
+            }
+        };
+        self.check_arity(name, arguments, arity, offset)?;
+        for argument in arguments {
+            self.number(argument)?;
+        }
+        self.apply(op, arity);
+        Ok(())
+    }
+
+    /// Pushes an operation that adds a value to the stack.
+    fn push(&mut self, op: Op, offset: usize) -> Result<(), ShaderExpressionError> {
+        self.depth += 1;
+        if self.depth > MAX_STACK_DEPTH {
+            return Err(ShaderExpressionError::new(
+                "Expression is too deeply nested",
+                offset,
+            ));
+        }
+        self.max_depth = self.max_depth.max(self.depth);
+        self.ops.push(op);
+        Ok(())
+    }
+
+    /// Adds an operation that replaces `arity` values with its result. If all of
+    /// them are constants, the operation is run now instead.
+    fn apply(&mut self, op: Op, arity: usize) {
+        self.depth -= arity - 1;
+        let args_start = self.ops.len() - arity;
+        let constants: Option<Vec<f64>> = self.ops[args_start..]
+            .iter()
+            .map(|arg| match arg {
+                Op::Const(value) => Some(*value),
+                _ => None,
+            })
+            .collect();
+        let args = match constants {
+            Some(args) => args,
+            None => {
+                self.ops.push(op);
+                return;
+            }
+        };
+        let folded = match op {
+            Op::Unary(f) => f(args[0]),
+            Op::Binary(f) => f(args[0], args[1]),
+            Op::Ternary(f) => f(args[0], args[1], args[2]),
+            _ => unreachable!("Only functions take arguments"),
+        };
+        self.ops.truncate(args_start);
+        self.ops.push(Op::Const(folded));
+    }
+}
@@ -0,0 +1,280 @@
+//! A small expression language for writing shader programs, such as
+//! `hsv(x + t*0.1, 1, sin(y*6.28))`.
+//!
+//! Expressions are made of numbers, the operators `+ - * / % ^` and parentheses.
+//! The names `x`, `y` and `z` are the position being shaded, `t` is the time in
+//! seconds, and `pi`, `tau` and `e` are constants. Any uniform names given when
+//! compiling can also be used. Functions include `sin`, `cos`, `tan`, `asin`, `acos`,
+//! `atan`, `atan2`, `abs`, `sign`, `floor`, `ceil`, `round`, `fract`, `mod`, `sqrt`,
+//! `exp`, `log`, `pow`, `min`, `max`, `clamp`, `mix`, `step`, `smoothstep` and
+//! `noise` (value noise with 1 to 3 coordinates, from 0 to 1).
+//!
+//! The whole expression is either a single number, used as a shade of gray, or one
+//! of the color constructors `gray(v)`, `rgb(r, g, b)`, `rgba(r, g, b, a)`,
+//! `hsv(h, s, v)`, `hsva(h, s, v, a)`, `hsl(h, s, l)` or `hsla(h, s, l, a)`. Every
+//! channel goes from 0 to 1, including hue, which wraps around.
+
+mod compile;
+mod parse;
+
+use super::node_value::{HslColor, HsvColor, NodeColor, NodeVector3};
+use super::shader_registry::{ShaderContext, ShaderProgram};
+use std::fmt;
+
+/// Most values an expression can need at once while being evaluated.
+const MAX_STACK_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderExpressionError {
+    pub message: String,
+
+    /// Byte offset in the source where the problem was found.
+    pub offset: usize,
+}
+
+impl ShaderExpressionError {
+    fn new(message: &str, offset: usize) -> ShaderExpressionError {
+        ShaderExpressionError {
+            message: message.to_string(),
+            offset,
+        }
+    }
+}
+
+impl fmt::Display for ShaderExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.offset + 1)
+    }
+}
+
+impl std::error::Error for ShaderExpressionError {}
+
+/// Instruction for the stack machine that evaluates compiled expressions. Functions
+/// pop their arguments and push their result.
+#[derive(Clone, Copy)]
+enum Op {
+    Const(f64),
+    X,
+    Y,
+    Z,
+    Time,
+    Uniform(usize),
+    Unary(fn(f64) -> f64),
+    Binary(fn(f64, f64) -> f64),
+    Ternary(fn(f64, f64, f64) -> f64),
+}
+
+/// How the numbers left on the stack are turned into a color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColorOutput {
+    Gray,
+    Rgb,
+    Rgba,
+    Hsv,
+    Hsva,
+    Hsl,
+    Hsla,
+}
+
+impl ColorOutput {
+    fn channel_count(self) -> usize {
+        match self {
+            ColorOutput::Gray => 1,
+            ColorOutput::Rgb | ColorOutput::Hsv | ColorOutput::Hsl => 3,
+            ColorOutput::Rgba | ColorOutput::Hsva | ColorOutput::Hsla => 4,
+        }
+    }
+
+    fn color(self, channels: &[f64]) -> NodeColor {
+        let channel = |index: usize| channels.get(index).map_or(1.0, |c| *c as f32);
+        let hue = |value: f32| (value - value.floor()) * 360.0;
+        match self {
+            ColorOutput::Gray => {
+                let value = channel(0);
+                NodeColor::from_srgb_f32(value, value, value, 1.0)
+            }
+            ColorOutput::Rgb | ColorOutput::Rgba => {
+                NodeColor::from_srgb_f32(channel(0), channel(1), channel(2), channel(3))
+            }
+            ColorOutput::Hsv | ColorOutput::Hsva => NodeColor::from_hsv(&HsvColor {
+                h: hue(channel(0)),
+                s: channel(1).clamp(0.0, 1.0),
+                v: channel(2).clamp(0.0, 1.0),
+                a: channel(3),
+            }),
+            ColorOutput::Hsl | ColorOutput::Hsla => NodeColor::from_hsl(&HslColor {
+                h: hue(channel(0)),
+                s: channel(1).clamp(0.0, 1.0),
+                l: channel(2).clamp(0.0, 1.0),
+                a: channel(3),
+            }),
+        }
+    }
+}
+
+/// A compiled expression, ready to be evaluated at any position as a ShaderProgram.
+#[derive(Clone)]
+pub struct ShaderExpression {
+    source: String,
+    ops: Vec<Op>,
+    output: ColorOutput,
+}
+
+impl ShaderExpression {
+    /// Parses and compiles an expression. `uniforms` names the values that will be
+    /// bound to the program, in order.
+    pub fn compile(
+        source: &str,
+        uniforms: &[&str],
+    ) -> Result<ShaderExpression, ShaderExpressionError> {
+        let parsed = parse::parse(source)?;
+        let mut compiler = compile::Compiler::new(uniforms);
+        let output = compiler.color(&parsed)?;
+        Ok(ShaderExpression {
+            source: source.to_string(),
+            ops: compiler.ops,
+            output,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the expression gives the same color everywhere, at all times.
+    pub fn is_constant(&self) -> bool {
+        self.ops.iter().all(|op| matches!(op, Op::Const(_)))
+    }
+
+    pub fn evaluate(&self, position: NodeVector3, context: &ShaderContext<'_>) -> NodeColor {
+        let mut stack = [0.0; MAX_STACK_DEPTH];
+        let mut len = 0;
+        for op in &self.ops {
+            let value = match *op {
+                Op::Const(value) => value,
+                Op::X => position.x,
+                Op::Y => position.y,
+                Op::Z => position.z,
+                Op::Time => context.time,
+                Op::Uniform(index) => context.uniforms.get(index).copied().unwrap_or(0.0),
+                Op::Unary(f) => {
+                    len -= 1;
+                    f(stack[len])
+                }
+                Op::Binary(f) => {
+                    len -= 2;
+                    f(stack[len], stack[len + 1])
+                }
+                Op::Ternary(f) => {
+                    len -= 3;
+                    f(stack[len], stack[len + 1], stack[len + 2])
+                }
+            };
+            stack[len] = value;
+            len += 1;
+        }
+        self.output.color(&stack[..len])
+    }
+}
+
+impl ShaderProgram for ShaderExpression {
+    fn color_at(&self, position: NodeVector3, context: &ShaderContext<'_>) -> NodeColor {
+        self.evaluate(position, context)
+    }
+}
+
+impl fmt::Debug for ShaderExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ShaderExpression")
+            .field(&self.source)
+            .finish()
+    }
+}
+
+/// Pseudo-random value from 0 to 1 for a point on the integer lattice.
+fn lattice_value(x: i64, y: i64, z: i64) -> f64 {
+    let mut hash = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    hash ^= hash >> 33;
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Smoothly interpolated value noise from 0 to 1, repeating nowhere.
+fn noise(x: f64, y: f64, z: f64) -> f64 {
+    let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+    let fade = |t: f64| t * t * (3.0 - 2.0 * t);
+    let (tx, ty, tz) = (fade(x - x0), fade(y - y0), fade(z - z0));
+    let (xi, yi, zi) = (x0 as i64, y0 as i64, z0 as i64);
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+
+    let corner = |dx: i64, dy: i64, dz: i64| lattice_value(xi + dx, yi + dy, zi + dz);
+    let face = |dz: i64| {
+        lerp(
+            lerp(corner(0, 0, dz), corner(1, 0, dz), tx),
+            lerp(corner(0, 1, dz), corner(1, 1, dz), tx),
+            ty,
+        )
+    };
+    lerp(face(0), face(1), tz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color_at(source: &str, x: f64, y: f64, time: f64, uniforms: &[f64]) -> NodeColor {
+        let expression = ShaderExpression::compile(source, &["speed"]).unwrap();
+        let context = ShaderContext { time, uniforms };
+        expression.evaluate(NodeVector3::new(x, y, 0.0), &context)
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        assert_eq!(
+            color_at("hsv(x + t*0.1, 1, sin(y*pi/2))", 0.0, 1.0, 10.0, &[]),
+            NodeColor::opaque(u16::MAX, 0, 0)
+        );
+        assert_eq!(
+            color_at("rgb(x, y * speed, -1 + 2 ^ 2 ^ 0)", 1.0, 0.25, 0.0, &[2.0]),
+            NodeColor::opaque(u16::MAX, u16::MAX / 2 + 1, u16::MAX)
+        );
+        assert_eq!(color_at("0.5 * 2", 0.0, 0.0, 0.0, &[]), NodeColor::WHITE);
+
+        let noise = ShaderExpression::compile("noise(x, y)", &[]).unwrap();
+        let context = ShaderContext {
+            time: 0.0,
+            uniforms: &[],
+        };
+        let sample = |x| noise.evaluate(NodeVector3::new(x, 0.5, 0.0), &context);
+        assert_eq!(sample(1.25), sample(1.25));
+        assert_ne!(sample(1.25), sample(3.25));
+    }
+
+    #[test]
+    fn folds_constants() {
+        let expression = ShaderExpression::compile("rgb(sin(pi / 2), 2 * 0.25, x)", &[]).unwrap();
+        assert_eq!(expression.ops.len(), 3);
+        assert!(!expression.is_constant());
+        assert!(ShaderExpression::compile("hsv(0.5, 1, cos(0))", &[])
+            .unwrap()
+            .is_constant());
+    }
+
+    #[test]
+    fn reports_errors() {
+        let error = |source| ShaderExpression::compile(source, &[]).unwrap_err();
+        assert_eq!(error("sin(q)").to_string(), "Unknown name 'q' at column 5");
+        assert_eq!(error("1 + wobble(x)").offset, 4);
+        assert_eq!(
+            error("rgb(1, 2)").message,
+            "rgb takes 3 arguments but was given 2"
+        );
+        assert_eq!(
+            error("sin(rgb(1, 1, 1))").message,
+            "Colors can only be used as the result of the whole expression"
+        );
+    }
+}
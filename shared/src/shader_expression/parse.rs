@@ -0,0 +1,303 @@
+use super::ShaderExpressionError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Name(String),
+    Negate(Box<Spanned>),
+    Binary(BinaryOp, Box<Spanned>, Box<Spanned>),
+    Call(String, Vec<Spanned>),
+}
+
+/// An expression along with the byte offset in the source where it starts, for
+/// pointing error messages at the right place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned {
+    pub expr: Expr,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+}
+
+impl BinaryOp {
+    /// How tightly the operator binds, and whether it groups to the right.
+    fn precedence(self) -> (u8, bool) {
+        match self {
+            BinaryOp::Add | BinaryOp::Subtract => (1, false),
+            BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Remainder => (2, false),
+            BinaryOp::Power => (4, true),
+        }
+    }
+}
+
+/// Binding power of unary minus. Lower than powers, so `-x^2` is `-(x^2)`.
+const NEGATE_PRECEDENCE: u8 = 3;
+
+/// Deepest that parentheses, arguments and operators can be nested, counting each
+/// operator in a chain like `a + b + c` as a level. Parsing, compiling and dropping an
+/// expression all recurse once per level, so this keeps text from artists from
+/// overflowing the stack.
+const MAX_NESTING: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Operator(BinaryOp),
+    OpenParen,
+    CloseParen,
+    Comma,
+    End,
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ShaderExpressionError> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(offset, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c.is_ascii_digit() || c == '.' {
+            let mut end = offset;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let number = source[offset..end]
+                .parse()
+                .map_err(|_| ShaderExpressionError::new("Invalid number", offset))?;
+            tokens.push((Token::Number(number), offset));
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' {
+            let mut end = offset;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push((Token::Name(source[offset..end].to_string()), offset));
+            continue;
+        }
+
+        let token = match c {
+            '+' => Token::Operator(BinaryOp::Add),
+            '-' => Token::Operator(BinaryOp::Subtract),
+            '*' => Token::Operator(BinaryOp::Multiply),
+            '/' => Token::Operator(BinaryOp::Divide),
+            '%' => Token::Operator(BinaryOp::Remainder),
+            '^' => Token::Operator(BinaryOp::Power),
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            ',' => Token::Comma,
+            _ => {
+                return Err(ShaderExpressionError::new(
+                    &format!("Unexpected character '{}'", c),
+                    offset,
+                ))
+            }
+        };
+        tokens.push((token, offset));
+        chars.next();
+    }
+    tokens.push((Token::End, source.len()));
+    Ok(tokens)
+}
+
+/// Precedence climbing parser over the token list.
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+
+    /// Nesting level of the expression being parsed. See MAX_NESTING.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &(Token, usize) {
+        &self.tokens[self.position]
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let token = self.tokens[self.position].clone();
+        if token.0 != Token::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, expected: Token, description: &str) -> Result<(), ShaderExpressionError> {
+        let (token, offset) = self.next();
+        if token != expected {
+            return Err(ShaderExpressionError::new(
+                &format!("Expected {}", description),
+                offset,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Goes one level deeper, or fails if that is too deep.
+    fn nest(&mut self, offset: usize) -> Result<(), ShaderExpressionError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(ShaderExpressionError::new(
+                "Expression is too deeply nested",
+                offset,
+            ));
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, min_precedence: u8) -> Result<Spanned, ShaderExpressionError> {
+        let depth = self.depth;
+        let expression = self.operator_chain(min_precedence);
+        self.depth = depth;
+        expression
+    }
+
+    fn operator_chain(&mut self, min_precedence: u8) -> Result<Spanned, ShaderExpressionError> {
+        self.nest(self.peek().1)?;
+        let mut left = self.operand()?;
+        while let (Token::Operator(op), offset) = *self.peek() {
+            let (precedence, right_associative) = op.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            self.next();
+            self.nest(offset)?;
+            let next_min = if right_associative {
+                precedence
+            } else {
+                precedence + 1
+            };
+            let right = self.expression(next_min)?;
+            left = Spanned {
+                expr: Expr::Binary(op, Box::new(left), Box::new(right)),
+                offset,
+            };
+        }
+        Ok(left)
+    }
+
+    fn operand(&mut self) -> Result<Spanned, ShaderExpressionError> {
+        let (token, offset) = self.next();
+        let expr = match token {
+            Token::Number(number) => Expr::Number(number),
+            Token::Operator(BinaryOp::Subtract) => {
+                Expr::Negate(Box::new(self.expression(NEGATE_PRECEDENCE)?))
+            }
+            Token::OpenParen => {
+                let inner = self.expression(0)?;
+                self.expect(Token::CloseParen, "')'")?;
+                return Ok(inner);
+            }
+            Token::Name(name) => {
+                if self.peek().0 != Token::OpenParen {
+                    Expr::Name(name)
+                } else {
+                    self.next();
+                    Expr::Call(name, self.arguments()?)
+                }
+            }
+            Token::End => {
+                return Err(ShaderExpressionError::new(
+                    "Unexpected end of expression",
+                    offset,
+                ))
+            }
+            _ => return Err(ShaderExpressionError::new("Expected a value", offset)),
+        };
+        Ok(Spanned { expr, offset })
+    }
+
+    /// Parses a comma separated argument list, after the opening parenthesis.
+    fn arguments(&mut self) -> Result<Vec<Spanned>, ShaderExpressionError> {
+        let mut arguments = vec![];
+        if self.peek().0 == Token::CloseParen {
+            self.next();
+            return Ok(arguments);
+        }
+        loop {
+            arguments.push(self.expression(0)?);
+            match self.next() {
+                (Token::Comma, _) => continue,
+                (Token::CloseParen, _) => return Ok(arguments),
+                (_, offset) => {
+                    return Err(ShaderExpressionError::new("Expected ',' or ')'", offset))
+                }
+            }
+        }
+    }
+}
+
+pub fn parse(source: &str) -> Result<Spanned, ShaderExpressionError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        depth: 0,
+    };
+    let expr = parser.expression(0)?;
+    parser.expect(Token::End, "an operator or the end of the expression")?;
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str, offset: usize) -> Box<Spanned> {
+        Box::new(Spanned {
+            expr: Expr::Name(name.to_string()),
+            offset,
+        })
+    }
+
+    #[test]
+    fn respects_precedence() {
+        let expr = parse("a - b ^ c").unwrap();
+        let power = Spanned {
+            expr: Expr::Binary(BinaryOp::Power, name("b", 4), name("c", 8)),
+            offset: 6,
+        };
+        assert_eq!(
+            expr.expr,
+            Expr::Binary(BinaryOp::Subtract, name("a", 0), Box::new(power))
+        );
+
+        assert_eq!(parse("sin(x, )").unwrap_err().offset, 7);
+        assert_eq!(parse("(x + 1").unwrap_err().offset, 6);
+        assert_eq!(parse("x $ 1").unwrap_err().offset, 2);
+    }
+
+    #[test]
+    fn limits_nesting() {
+        assert!(parse(&format!("{}x{}", "(".repeat(60), ")".repeat(60))).is_ok());
+        for source in &[
+            format!("{}x{}", "(".repeat(100_000), ")".repeat(100_000)),
+            format!("{}x", "-".repeat(100_000)),
+            format!("{}x", "x ^ ".repeat(100_000)),
+            format!("{}x", "x + ".repeat(100_000)),
+            format!("{}x{}", "sin(".repeat(100_000), ")".repeat(100_000)),
+        ] {
+            assert_eq!(
+                parse(source).unwrap_err().message,
+                "Expression is too deeply nested"
+            );
+        }
+    }
+}
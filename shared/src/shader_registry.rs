@@ -0,0 +1,407 @@
+use super::node_value::{NodeColor, NodeDuration, NodeValue, NodeValueType, NodeVector3};
+use parking_lot::RwLock;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+
+/// Which kind of Shader NodeValue a program backs. Each has its own set of ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderDimension {
+    One,
+    Two,
+    Three,
+}
+
+impl ShaderDimension {
+    /// The dimension and program id of a Shader value, or None for other values.
+    pub fn of(value: &NodeValue) -> Option<(ShaderDimension, u16)> {
+        match value {
+            NodeValue::Shader1D(id) => Some((ShaderDimension::One, *id)),
+            NodeValue::Shader2D(id) => Some((ShaderDimension::Two, *id)),
+            NodeValue::Shader3D(id) => Some((ShaderDimension::Three, *id)),
+            _ => None,
+        }
+    }
+
+    pub fn value_type(self) -> NodeValueType {
+        match self {
+            ShaderDimension::One => NodeValueType::Shader1D,
+            ShaderDimension::Two => NodeValueType::Shader2D,
+            ShaderDimension::Three => NodeValueType::Shader3D,
+        }
+    }
+
+    /// Wraps a program id as a Shader value of this dimension.
+    pub fn value(self, id: u16) -> NodeValue {
+        match self {
+            ShaderDimension::One => NodeValue::Shader1D(id),
+            ShaderDimension::Two => NodeValue::Shader2D(id),
+            ShaderDimension::Three => NodeValue::Shader3D(id),
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            ShaderDimension::One => 0,
+            ShaderDimension::Two => 1,
+            ShaderDimension::Three => 2,
+        }
+    }
+}
+
+/// Values a shader program can read besides its position.
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderContext<'a> {
+    /// Seconds since the graph started executing.
+    pub time: f64,
+
+    /// Values bound to the program, usually taken from the inputs of the Node that
+    /// created it.
+    pub uniforms: &'a [f64],
+}
+
+/// A program that computes a color for any position, evaluated on the CPU. Positions
+/// always have three components; 1D programs only use x and 2D programs use x and y,
+/// with the rest set to 0.
+pub trait ShaderProgram: Send + Sync {
+    fn color_at(&self, position: NodeVector3, context: &ShaderContext<'_>) -> NodeColor;
+}
+
+impl<F> ShaderProgram for F
+where
+    F: Fn(NodeVector3, &ShaderContext<'_>) -> NodeColor + Send + Sync,
+{
+    fn color_at(&self, position: NodeVector3, context: &ShaderContext<'_>) -> NodeColor {
+        self(position, context)
+    }
+}
+
+/// A registered program along with its current uniforms, ready to be evaluated
+/// without holding any lock on the registry.
+#[derive(Clone)]
+pub struct BoundShader {
+    program: Arc<dyn ShaderProgram>,
+    uniforms: Arc<Vec<f64>>,
+}
+
+impl BoundShader {
+    pub fn color_at(&self, position: NodeVector3, elapsed: NodeDuration) -> NodeColor {
+        let context = ShaderContext {
+            time: elapsed.as_secs_f64(),
+            uniforms: &self.uniforms,
+        };
+        self.program.color_at(position, &context)
+    }
+
+    pub fn uniforms(&self) -> &[f64] {
+        &self.uniforms
+    }
+}
+
+impl fmt::Debug for BoundShader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoundShader")
+            .field("uniforms", &self.uniforms)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShaderRegistryError {
+    /// Every id for the dimension is in use.
+    Full(ShaderDimension),
+    UnknownProgram(ShaderDimension, u16),
+}
+
+impl fmt::Display for ShaderRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderRegistryError::Full(dimension) => {
+                write!(f, "No free {:?} shader ids", dimension.value_type())
+            }
+            ShaderRegistryError::UnknownProgram(dimension, id) => {
+                write!(f, "No such {:?} program: {}", dimension.value_type(), id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderRegistryError {}
+
+struct ShaderEntry {
+    shader: BoundShader,
+
+    /// Number of owners keeping the program alive. It is removed when this hits 0.
+    ref_count: u32,
+}
+
+/// Programs for one dimension, indexed by id. Freed ids are reused oldest first, so
+/// that a stale id held elsewhere is unlikely to refer to a new program by the time
+/// it is looked up.
+#[derive(Default)]
+struct ShaderTable {
+    entries: Vec<Option<ShaderEntry>>,
+    free_ids: VecDeque<u16>,
+}
+
+impl ShaderTable {
+    fn entry_mut(
+        &mut self,
+        dimension: ShaderDimension,
+        id: u16,
+    ) -> Result<&mut ShaderEntry, ShaderRegistryError> {
+        self.entries
+            .get_mut(id as usize)
+            .and_then(Option::as_mut)
+            .ok_or(ShaderRegistryError::UnknownProgram(dimension, id))
+    }
+}
+
+/// Clonable map from Shader ids to the programs they run. Programs are reference
+/// counted: registering one gives the caller a single reference, which must be
+/// released when the caller no longer produces values with its id.
+#[derive(Clone)]
+pub struct ShaderRegistry {
+    internal: Arc<ShaderRegistryInternal>,
+}
+
+struct ShaderRegistryInternal {
+    tables: [RwLock<ShaderTable>; 3],
+    capacity: usize,
+}
+
+impl fmt::Debug for ShaderRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[Shader Registry]")
+    }
+}
+
+impl Default for ShaderRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShaderRegistry {
+    /// Id that is never given to a program, for Shader values that draw nothing.
+    pub const NO_PROGRAM: u16 = u16::MAX;
+
+    pub fn new() -> ShaderRegistry {
+        ShaderRegistry::with_capacity(ShaderRegistry::NO_PROGRAM as usize)
+    }
+
+    /// Creates a registry that holds at most `capacity` programs per dimension.
+    pub fn with_capacity(capacity: usize) -> ShaderRegistry {
+        ShaderRegistry {
+            internal: Arc::new(ShaderRegistryInternal {
+                tables: Default::default(),
+                capacity: capacity.min(ShaderRegistry::NO_PROGRAM as usize),
+            }),
+        }
+    }
+
+    fn table(&self, dimension: ShaderDimension) -> &RwLock<ShaderTable> {
+        &self.internal.tables[dimension.index()]
+    }
+
+    /// Adds a program with no uniforms and returns its id, with a reference count of 1.
+    pub fn register(
+        &self,
+        dimension: ShaderDimension,
+        program: Arc<dyn ShaderProgram>,
+    ) -> Result<u16, ShaderRegistryError> {
+        let entry = ShaderEntry {
+            shader: BoundShader {
+                program,
+                uniforms: Arc::new(vec![]),
+            },
+            ref_count: 1,
+        };
+        let mut table = self.table(dimension).write();
+        if let Some(id) = table.free_ids.pop_front() {
+            table.entries[id as usize] = Some(entry);
+            return Ok(id);
+        }
+        if table.entries.len() >= self.internal.capacity {
+            return Err(ShaderRegistryError::Full(dimension));
+        }
+        table.entries.push(Some(entry));
+        Ok((table.entries.len() - 1) as u16)
+    }
+
+    /// Looks up the program behind a Shader value.
+    pub fn get(&self, value: &NodeValue) -> Option<BoundShader> {
+        let (dimension, id) = ShaderDimension::of(value)?;
+        self.get_by_id(dimension, id)
+    }
+
+    pub fn get_by_id(&self, dimension: ShaderDimension, id: u16) -> Option<BoundShader> {
+        let table = self.table(dimension).read();
+        let entry = table.entries.get(id as usize)?.as_ref()?;
+        Some(entry.shader.clone())
+    }
+
+    /// Swaps in a new program for an existing id, keeping its uniforms.
+    pub fn replace(
+        &self,
+        dimension: ShaderDimension,
+        id: u16,
+        program: Arc<dyn ShaderProgram>,
+    ) -> Result<(), ShaderRegistryError> {
+        let mut table = self.table(dimension).write();
+        table.entry_mut(dimension, id)?.shader.program = program;
+        Ok(())
+    }
+
+    pub fn set_uniforms(
+        &self,
+        dimension: ShaderDimension,
+        id: u16,
+        uniforms: Vec<f64>,
+    ) -> Result<(), ShaderRegistryError> {
+        let mut table = self.table(dimension).write();
+        table.entry_mut(dimension, id)?.shader.uniforms = Arc::new(uniforms);
+        Ok(())
+    }
+
+    /// Adds a reference to a program, so it stays alive until released again.
+    pub fn retain(&self, dimension: ShaderDimension, id: u16) -> Result<(), ShaderRegistryError> {
+        let mut table = self.table(dimension).write();
+        table.entry_mut(dimension, id)?.ref_count += 1;
+        Ok(())
+    }
+
+    /// Removes a reference to a program, removing the program and freeing its id
+    /// once there are none left.
+    pub fn release(&self, dimension: ShaderDimension, id: u16) -> Result<(), ShaderRegistryError> {
+        let mut table = self.table(dimension).write();
+        let entry = table.entry_mut(dimension, id)?;
+        entry.ref_count -= 1;
+        if entry.ref_count == 0 {
+            table.entries[id as usize] = None;
+            table.free_ids.push_back(id);
+        }
+        Ok(())
+    }
+
+    /// Number of programs currently registered for a dimension.
+    pub fn len(&self, dimension: ShaderDimension) -> usize {
+        let table = self.table(dimension).read();
+        table.entries.len() - table.free_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        [
+            ShaderDimension::One,
+            ShaderDimension::Two,
+            ShaderDimension::Three,
+        ]
+        .iter()
+        .all(|dimension| self.len(*dimension) == 0)
+    }
+
+    pub fn reset(&self) {
+        for table in &self.internal.tables {
+            *table.write() = ShaderTable::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(color: NodeColor) -> Arc<dyn ShaderProgram> {
+        Arc::new(move |_: NodeVector3, _: &ShaderContext<'_>| color)
+    }
+
+    #[test]
+    fn evaluates_programs_with_uniforms() {
+        let registry = ShaderRegistry::new();
+        let gradient = registry
+            .register(
+                ShaderDimension::Two,
+                Arc::new(|position: NodeVector3, context: &ShaderContext<'_>| {
+                    let level = (position.x * context.uniforms[0] + context.time) as u16;
+                    NodeColor::opaque(level, level, level)
+                }),
+            )
+            .unwrap();
+        registry
+            .set_uniforms(ShaderDimension::Two, gradient, vec![100.0])
+            .unwrap();
+
+        let shader = registry.get(&NodeValue::Shader2D(gradient)).unwrap();
+        let color = shader.color_at(
+            NodeVector3::new(2.0, 0.0, 0.0),
+            NodeDuration::from_secs_f64(5.0),
+        );
+        assert_eq!(color, NodeColor::opaque(205, 205, 205));
+
+        // Ids are separate for each dimension.
+        assert!(registry.get(&NodeValue::Shader1D(gradient)).is_none());
+    }
+
+    #[test]
+    fn frees_ids_when_released() {
+        let registry = ShaderRegistry::with_capacity(2);
+        let first = registry
+            .register(ShaderDimension::One, solid(NodeColor::WHITE))
+            .unwrap();
+        let second = registry
+            .register(ShaderDimension::One, solid(NodeColor::BLACK))
+            .unwrap();
+        assert_eq!(
+            registry
+                .register(ShaderDimension::One, solid(NodeColor::BLACK))
+                .unwrap_err(),
+            ShaderRegistryError::Full(ShaderDimension::One)
+        );
+
+        registry.retain(ShaderDimension::One, first).unwrap();
+        registry.release(ShaderDimension::One, first).unwrap();
+        assert_eq!(registry.len(ShaderDimension::One), 2);
+        registry.release(ShaderDimension::One, first).unwrap();
+        assert_eq!(registry.len(ShaderDimension::One), 1);
+        assert!(registry.get_by_id(ShaderDimension::One, first).is_none());
+        assert_eq!(
+            registry.release(ShaderDimension::One, first),
+            Err(ShaderRegistryError::UnknownProgram(
+                ShaderDimension::One,
+                first
+            ))
+        );
+
+        let third = registry
+            .register(ShaderDimension::One, solid(NodeColor::WHITE))
+            .unwrap();
+        assert_eq!(third, first);
+        assert_ne!(third, second);
+    }
+
+    #[test]
+    fn reuses_the_oldest_freed_id_first() {
+        let registry = ShaderRegistry::with_capacity(3);
+        let ids: Vec<u16> = (0..3)
+            .map(|_| {
+                registry
+                    .register(ShaderDimension::Two, solid(NodeColor::WHITE))
+                    .unwrap()
+            })
+            .collect();
+        registry.release(ShaderDimension::Two, ids[1]).unwrap();
+        registry.release(ShaderDimension::Two, ids[0]).unwrap();
+
+        let reused = registry
+            .register(ShaderDimension::Two, solid(NodeColor::BLACK))
+            .unwrap();
+        assert_eq!(reused, ids[1]);
+        registry.release(ShaderDimension::Two, reused).unwrap();
+
+        // The id that was just released waits behind the one freed before it.
+        let reused = registry
+            .register(ShaderDimension::Two, solid(NodeColor::BLACK))
+            .unwrap();
+        assert_eq!(reused, ids[0]);
+    }
+}
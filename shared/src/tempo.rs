@@ -0,0 +1,226 @@
+use crate::node_value::NodeDuration;
+
+pub const DEFAULT_BPM: f64 = 120.0;
+pub const MIN_BPM: f64 = 20.0;
+pub const MAX_BPM: f64 = 999.0;
+
+/// Taps further apart than this start a new tap tempo measurement.
+pub const TAP_TIMEOUT: NodeDuration = NodeDuration::from_millis(2000);
+
+/// Number of recent taps averaged together to measure tap tempo.
+pub const MAX_TAPS: usize = 8;
+
+/// A musical clock that converts elapsed time into a position measured in beats.
+/// The clock is defined by an anchor, a point in time known to be at a given beat,
+/// and a tempo that beats advance at from there. Changing the tempo moves the anchor
+/// so that the beat position never jumps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoClock {
+    bpm: f64,
+    beats_per_bar: u32,
+    anchor_time: NodeDuration,
+    anchor_beat: f64,
+    taps: Vec<NodeDuration>,
+}
+
+/// A change to a TempoClock requested while a graph is executing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TempoChange {
+    /// Sets the tempo, in beats per minute.
+    SetBpm(f64),
+
+    /// Records a tap. Tapping repeatedly in time with music sets the tempo to match,
+    /// and each tap lands exactly on a beat.
+    Tap,
+
+    /// Moves the clock ahead by the given time, or behind if it is negative.
+    Nudge(NodeDuration),
+
+    /// Marks the current time as the start of a bar.
+    Downbeat,
+}
+
+impl TempoClock {
+    pub fn new(bpm: f64, beats_per_bar: u32) -> TempoClock {
+        TempoClock {
+            bpm: bpm.clamp(MIN_BPM, MAX_BPM),
+            beats_per_bar: beats_per_bar.max(1),
+            anchor_time: NodeDuration::ZERO,
+            anchor_beat: 0.0,
+            taps: vec![],
+        }
+    }
+
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    pub fn beats_per_bar(&self) -> u32 {
+        self.beats_per_bar
+    }
+
+    pub fn beat_duration(&self) -> NodeDuration {
+        NodeDuration::from_secs_f64(60.0 / self.bpm)
+    }
+
+    /// Number of beats that have passed at the given time, including the fraction of
+    /// the current beat.
+    pub fn beat_at(&self, at: NodeDuration) -> f64 {
+        self.anchor_beat + (at - self.anchor_time).as_secs_f64() * self.bpm / 60.0
+    }
+
+    /// Number of bars that have passed at the given time, including the fraction of
+    /// the current bar.
+    pub fn bar_at(&self, at: NodeDuration) -> f64 {
+        self.beat_at(at) / self.beats_per_bar as f64
+    }
+
+    /// How far through the current beat the clock is, from 0 to 1.
+    pub fn beat_phase_at(&self, at: NodeDuration) -> f64 {
+        self.beat_at(at).rem_euclid(1.0)
+    }
+
+    /// How far through the current bar the clock is, from 0 to 1.
+    pub fn bar_phase_at(&self, at: NodeDuration) -> f64 {
+        self.bar_at(at).rem_euclid(1.0)
+    }
+
+    /// Time of the first beat at or after the given time.
+    pub fn next_beat_after(&self, at: NodeDuration) -> NodeDuration {
+        let beats_until = self.beat_at(at).ceil() - self.beat_at(at);
+        at + NodeDuration::from_secs_f64(beats_until * 60.0 / self.bpm)
+    }
+
+    /// Moves the anchor to the given time without changing the beat position there.
+    fn reanchor(&mut self, at: NodeDuration) {
+        self.anchor_beat = self.beat_at(at);
+        self.anchor_time = at;
+    }
+
+    /// Changes the tempo, keeping it within the supported range. Non-finite tempos are
+    /// ignored.
+    pub fn set_bpm(&mut self, bpm: f64, at: NodeDuration) {
+        if !bpm.is_finite() {
+            return;
+        }
+        self.reanchor(at);
+        self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+    }
+
+    pub fn set_beats_per_bar(&mut self, beats_per_bar: u32) {
+        self.beats_per_bar = beats_per_bar.max(1);
+    }
+
+    pub fn nudge(&mut self, offset: NodeDuration, at: NodeDuration) {
+        self.reanchor(at);
+        self.anchor_beat += offset.as_secs_f64() * self.bpm / 60.0;
+    }
+
+    pub fn tap(&mut self, at: NodeDuration) {
+        let timed_out = self
+            .taps
+            .last()
+            .is_some_and(|last| at < *last || at - *last > TAP_TIMEOUT);
+        if timed_out {
+            self.taps.clear();
+        }
+        self.taps.push(at);
+        if self.taps.len() > MAX_TAPS {
+            self.taps.remove(0);
+        }
+
+        if self.taps.len() >= 2 {
+            let span = *self.taps.last().unwrap() - self.taps[0];
+            let interval = span.as_secs_f64() / (self.taps.len() - 1) as f64;
+            if interval > 0.0 {
+                self.set_bpm(60.0 / interval, at);
+            }
+        }
+        self.anchor_beat = self.beat_at(at).round();
+        self.anchor_time = at;
+    }
+
+    pub fn downbeat(&mut self, at: NodeDuration) {
+        let bar = self.bar_at(at).round();
+        self.anchor_beat = bar * self.beats_per_bar as f64;
+        self.anchor_time = at;
+    }
+
+    pub fn apply(&mut self, change: TempoChange, at: NodeDuration) {
+        match change {
+            TempoChange::SetBpm(bpm) => self.set_bpm(bpm, at),
+            TempoChange::Tap => self.tap(at),
+            TempoChange::Nudge(offset) => self.nudge(offset, at),
+            TempoChange::Downbeat => self.downbeat(at),
+        }
+    }
+
+    /// Makes the given time the new zero point for elapsed time, keeping the beat
+    /// position the same. Used when the time source restarts.
+    pub fn rebase(&mut self, at: NodeDuration) {
+        self.reanchor(at);
+        self.anchor_time = NodeDuration::ZERO;
+        self.taps.clear();
+    }
+}
+
+impl Default for TempoClock {
+    /// 120 BPM in 4/4 time, starting on a downbeat.
+    fn default() -> TempoClock {
+        TempoClock::new(DEFAULT_BPM, 4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: i64) -> NodeDuration {
+        NodeDuration::from_millis(millis)
+    }
+
+    #[test]
+    fn keeps_position_when_tempo_changes() {
+        let mut clock = TempoClock::default();
+        assert_eq!(clock.beat_at(ms(1250)), 2.5);
+        assert_eq!(clock.bar_phase_at(ms(1250)), 0.625);
+        assert_eq!(clock.next_beat_after(ms(1250)), ms(1500));
+
+        clock.set_bpm(60.0, ms(1250));
+        assert_eq!(clock.beat_at(ms(1250)), 2.5);
+        assert_eq!(clock.beat_at(ms(2250)), 3.5);
+
+        clock.nudge(ms(-500), ms(2250));
+        assert_eq!(clock.beat_at(ms(2250)), 3.0);
+
+        clock.downbeat(ms(2250));
+        assert_eq!(clock.bar_phase_at(ms(2250)), 0.0);
+    }
+
+    #[test]
+    fn measures_tap_tempo() {
+        let mut clock = TempoClock::default();
+        for i in 0..4 {
+            clock.tap(ms(100 + i * 400));
+        }
+        assert!((clock.bpm() - 150.0).abs() < 1e-9);
+        assert_eq!(clock.beat_phase_at(ms(1300)), 0.0);
+
+        // Taps after a long pause start a new measurement.
+        clock.tap(ms(5000));
+        clock.tap(ms(6000));
+        assert!((clock.bpm() - 60.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ignores_non_finite_tempos() {
+        let mut clock = TempoClock::default();
+        clock.set_bpm(f64::NAN, ms(1000));
+        clock.set_bpm(f64::INFINITY, ms(1000));
+        assert_eq!(clock.bpm(), DEFAULT_BPM);
+        assert_eq!(clock.beat_at(ms(1250)), 2.5);
+
+        clock.set_bpm(1e9, ms(0));
+        assert_eq!(clock.bpm(), MAX_BPM);
+    }
+}